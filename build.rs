@@ -1,82 +1,169 @@
 use std::{
+    collections::HashSet,
+    env,
+    fmt::Write as _,
     fs::{self},
     io,
-    path::Path,
+    path::{Path, PathBuf},
+    process,
 };
 
 extern crate shaderc;
 
+#[path = "src/terra/shader/include/mod.rs"]
+mod include;
+
+const SHADERS_PATH: &str = "src/shaders";
+
+struct CompiledShader {
+    name: String,
+    kind: &'static str,
+    source: String,
+    output: PathBuf,
+}
+
 fn main() {
-    println!("cargo:rerun-if-changed=src/shaders");
-    let compiler = shaderc::Compiler::new().unwrap();
-    let mut options = shaderc::CompileOptions::new().unwrap();
+    println!("cargo:rerun-if-changed={SHADERS_PATH}");
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").expect("OUT_DIR is not set"));
+    let root = Path::new(SHADERS_PATH);
+
+    let compiler = shaderc::Compiler::new().expect("Failed to create shader compiler");
+    let mut options = shaderc::CompileOptions::new().expect("Failed to create compile options");
     options.add_macro_definition("EP", Some("main"));
+    options.set_include_callback(|requested, include_type, requesting, _| {
+        let relative = include_type == shaderc::IncludeType::Relative;
+        include::resolve(root, requested, relative, requesting).map(|(resolved_name, content)| {
+            shaderc::ResolvedInclude {
+                resolved_name,
+                content,
+            }
+        })
+    });
 
-    let shaders_path = "./src/shaders";
-
-    let compile_shader = |kind: shaderc::ShaderKind, filepath: &str, filename: &str| {
-        match fs::read_to_string(filepath) {
-            Ok(contents) => {
-                match compiler.compile_into_spirv(&contents, kind, filename, "main", Some(&options))
-                {
-                    Ok(binary) => {
-                        if let Some(path) = get_base_path(filepath) {
-                            let mut full_path = String::from(path);
-                            full_path.push_str(".spv");
-                            if let Err(e) = fs::write(full_path, binary.as_binary_u8()) {
-                                println!("{}", e.to_string());
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        println!("COMPILATION ERROR: {}", e.to_string())
-                    }
-                }
+    let mut sources = vec![];
+    if let Err(e) = visit_dirs(root, &mut |path| sources.push(path.to_path_buf())) {
+        fail(&[format!("Failed to read {SHADERS_PATH}: {e}")]);
+    }
+    sources.sort();
+
+    let mut compiled = vec![];
+    let mut errors = vec![];
+    let mut names = HashSet::new();
+
+    for path in sources.iter() {
+        let (kind, shader_kind) = match get_shader_kind(path) {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        let source = path.to_string_lossy().replace('\\', "/");
+        let name = match path.file_stem().and_then(|name| name.to_str()) {
+            Some(name) => name.to_owned(),
+            None => continue,
+        };
+
+        if !names.insert((kind, name.clone())) {
+            errors.push(format!(
+                "{source}: duplicate {kind} shader named \"{name}\""
+            ));
+            continue;
+        }
+
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                errors.push(format!("{source}: {e}"));
+                continue;
             }
-            Err(e) => println!("READ ERROR:{}", e.to_string()),
         };
-    };
-
-    let mut process_file = |path: &Path| {
-        if let Some(filepath) = path.to_str() {
-            if let Some(filename) = path.file_name() {
-                if let Some(filename) = filename.to_str() {
-                    match path
-                        .extension()
-                        .expect("Failed to get extension")
-                        .to_str()
-                        .expect("Failed to convert string")
-                    {
-                        "vs" => compile_shader(shaderc::ShaderKind::Vertex, filepath, filename),
-                        "fs" => compile_shader(shaderc::ShaderKind::Fragment, filepath, filename),
-                        _ => {}
+
+        match compiler.compile_into_spirv(&contents, shader_kind, &source, "main", Some(&options)) {
+            Ok(binary) => {
+                if binary.get_num_warnings() > 0 {
+                    for line in binary.get_warning_messages().lines() {
+                        println!("cargo:warning={line}");
                     }
                 }
-            }
-        }
-    };
-
-    std::fs::read_dir(shaders_path).unwrap().for_each(|e| {
-        if let Ok(e) = e {
-            if let Ok(data) = e.metadata() {
-                if data.is_dir() {
-                    let _ = visit_dirs(e.path().as_path(), &mut process_file);
-                } else if data.is_file() {
-                    process_file(e.path().as_path());
+
+                let output = out_dir
+                    .join("shaders")
+                    .join(kind)
+                    .join(format!("{name}.spv"));
+                let written = output
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&output, binary.as_binary_u8()));
+
+                match written {
+                    Ok(_) => compiled.push(CompiledShader {
+                        name,
+                        kind,
+                        source,
+                        output,
+                    }),
+                    Err(e) => errors.push(format!("{}: {e}", output.display())),
                 }
             }
+            Err(e) => errors.push(e.to_string()),
         }
-    });
+    }
+
+    if !errors.is_empty() {
+        fail(&errors);
+    }
+
+    if let Err(e) = fs::write(out_dir.join("shaders.rs"), generate_module(&compiled)) {
+        fail(&[format!("Failed to write shader module: {e}")]);
+    }
 }
 
-fn get_base_path(filepath: &str) -> Option<&str> {
-    if let Some(index) = filepath.rfind(".") {
-        Some(&filepath[..index])
-    } else {
-        None
+fn get_shader_kind(path: &Path) -> Option<(&'static str, shaderc::ShaderKind)> {
+    match path.extension()?.to_str()? {
+        "vs" => Some(("vertex", shaderc::ShaderKind::Vertex)),
+        "fs" => Some(("fragment", shaderc::ShaderKind::Fragment)),
+        "cs" | "comp" => Some(("compute", shaderc::ShaderKind::Compute)),
+        _ => None,
     }
 }
 
+// Shader diagnostics are already formatted as file:line: message, so they are printed as is
+fn fail(errors: &[String]) -> ! {
+    for error in errors.iter() {
+        for line in error.lines() {
+            eprintln!("{line}");
+        }
+    }
+
+    eprintln!("{} shader error(s) in {SHADERS_PATH}", errors.len());
+    process::exit(1);
+}
+
+fn generate_module(shaders: &[CompiledShader]) -> String {
+    let mut module = String::from("// Generated by build.rs from src/shaders\n");
+    module.push_str("pub const SHADERS: &[EmbeddedShader] = &[\n");
+
+    for shader in shaders.iter() {
+        let kind = match shader.kind {
+            "vertex" => "Vertex",
+            "fragment" => "Fragment",
+            _ => "Compute",
+        };
+
+        let _ = writeln!(
+            module,
+            "    EmbeddedShader {{ name: {:?}, kind: ShaderKind::{}, source: {:?}, spirv: include_bytes!({:?}) }},",
+            shader.name,
+            kind,
+            shader.source,
+            shader.output.to_string_lossy(),
+        );
+    }
+
+    module.push_str("];\n");
+    module
+}
+
 fn visit_dirs(dir: &Path, cb: &mut dyn FnMut(&Path)) -> io::Result<()> {
     if dir.is_dir() {
         for entry in fs::read_dir(dir)? {
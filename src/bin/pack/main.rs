@@ -0,0 +1,85 @@
+// Packs a directory into a TPAK archive that can be mounted with vfs::Archive
+//
+//   cargo run --bin pack -- src/assets/sprites sprites.tpak
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+#[path = "../../vfs/archive/format/mod.rs"]
+mod format;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (input, output) = match args.as_slice() {
+        [input, output] => (PathBuf::from(input), PathBuf::from(output)),
+        _ => {
+            eprintln!("Usage: pack <directory> <archive>");
+            process::exit(2);
+        }
+    };
+
+    if let Err(e) = pack(&input, &output) {
+        eprintln!("Failed to pack {}: {e}", input.display());
+        process::exit(1);
+    }
+}
+
+fn pack(input: &Path, output: &Path) -> io::Result<()> {
+    let mut paths = vec![];
+    visit_dirs(input, &mut paths)?;
+    paths.sort();
+
+    let mut files = vec![];
+    for path in paths.iter() {
+        let relative = path.strip_prefix(input).unwrap();
+        let name = relative.to_string_lossy().replace('\\', "/");
+        files.push((name, fs::read(path)?));
+    }
+
+    let mut archive = vec![];
+    let toc = format::write(&mut archive, &files)?;
+
+    // Read everything back so a broken archive never gets shipped
+    let written = format::read_toc(&archive)?;
+    for (entry, (_, contents)) in written.iter().zip(files.iter()) {
+        if format::read_entry(&archive, entry)? != *contents {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} didn't survive a round trip", entry.path),
+            ));
+        }
+    }
+
+    fs::write(output, &archive)?;
+
+    let size: u64 = toc.iter().map(|entry| entry.size).sum();
+    let compressed = toc
+        .iter()
+        .filter(|entry| entry.compression == format::Compression::Lz4)
+        .count();
+    println!(
+        "Packed {} files ({compressed} compressed) into {}, {size} bytes down to {}",
+        toc.len(),
+        output.display(),
+        archive.len()
+    );
+
+    Ok(())
+}
+
+fn visit_dirs(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            visit_dirs(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
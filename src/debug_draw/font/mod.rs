@@ -0,0 +1,78 @@
+// Stroke font for debug text. Glyphs sit on a 3x5 grid with y pointing up, each stroke is
+// written as the digits x1 y1 x2 y2. Lowercase letters are drawn as uppercase.
+
+pub const GLYPH_WIDTH: f32 = 2.0;
+pub const GLYPH_HEIGHT: f32 = 4.0;
+pub const GLYPH_ADVANCE: f32 = 3.0;
+pub const LINE_ADVANCE: f32 = 6.0;
+
+pub fn strokes(character: char) -> impl Iterator<Item = [f32; 4]> {
+    glyph(character.to_ascii_uppercase())
+        .split_whitespace()
+        .filter_map(|stroke| {
+            let mut digits = stroke.chars().filter_map(|digit| digit.to_digit(10));
+            Some([
+                digits.next()? as f32,
+                digits.next()? as f32,
+                digits.next()? as f32,
+                digits.next()? as f32,
+            ])
+        })
+}
+
+fn glyph(character: char) -> &'static str {
+    match character {
+        '0' => "0004 0424 2420 2000 0024",
+        '1' => "1014 1403 0020",
+        '2' => "0424 2422 2202 0200 0020",
+        '3' => "0424 2420 2000 0222",
+        '4' => "0402 0222 2420",
+        '5' => "2404 0402 0222 2220 2000",
+        '6' => "2404 0400 0020 2022 2202",
+        '7' => "0424 2410",
+        '8' => "0004 0424 2420 2000 0222",
+        '9' => "0222 0204 0424 2420 2000",
+        'A' => "0003 0314 1423 2320 0222",
+        'B' => "0004 0414 1423 2312 0212 1221 2110 1000",
+        'C' => "2404 0400 0020",
+        'D' => "0004 0414 1423 2321 2110 1000",
+        'E' => "2404 0400 0020 0212",
+        'F' => "2404 0400 0212",
+        'G' => "2404 0400 0020 2022 2212",
+        'H' => "0004 2420 0222",
+        'I' => "0424 1410 0020",
+        'J' => "1424 2420 2000 0001",
+        'K' => "0004 0224 0220",
+        'L' => "0400 0020",
+        'M' => "0004 0412 1224 2420",
+        'N' => "0004 0420 2024",
+        'O' => "0004 0424 2420 2000",
+        'P' => "0004 0424 2422 2202",
+        'Q' => "0004 0424 2420 2000 1120",
+        'R' => "0004 0424 2422 2202 1220",
+        'S' => "2404 0402 0222 2220 2000",
+        'T' => "0424 1410",
+        'U' => "0400 0020 2024",
+        'V' => "0410 1024",
+        'W' => "0400 0012 1220 2024",
+        'X' => "0024 0420",
+        'Y' => "0412 1224 1210",
+        'Z' => "0424 2400 0020",
+        '-' => "0222",
+        '+' => "0222 1113",
+        '=' => "0121 0323",
+        '_' => "0020",
+        '.' => "1011",
+        ',' => "1100",
+        ':' => "1011 1314",
+        '!' => "1412 1011",
+        '/' => "0024",
+        '(' => "1403 0301 0110",
+        ')' => "1423 2321 2110",
+        '[' => "1404 0400 0010",
+        ']' => "0414 1410 1000",
+        '<' => "2302 0221",
+        '>' => "0322 2201",
+        _ => "",
+    }
+}
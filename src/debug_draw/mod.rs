@@ -0,0 +1,219 @@
+pub mod font;
+
+use std::f32::consts::TAU;
+
+use nalgebra_glm::{self as glm, Vec2};
+
+use crate::terra::data::DebugVertex;
+
+#[cfg(feature = "debug-draw")]
+use std::cell::RefCell;
+
+// Segments used to approximate circles
+const CIRCLE_SEGMENTS: usize = 32;
+
+// Shapes are queued from anywhere on the main thread and drawn over the scene by the next frame.
+// Without the debug-draw feature every call is a no-op.
+#[cfg(feature = "debug-draw")]
+thread_local! {
+    static SHAPES: RefCell<Vec<DebugShape>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Default)]
+#[cfg_attr(not(feature = "debug-draw"), allow(dead_code))]
+struct DebugShape {
+    lines: Vec<DebugVertex>,
+    triangles: Vec<DebugVertex>,
+    remaining: f32,
+}
+
+impl DebugShape {
+    fn line(&mut self, start: Vec2, end: Vec2, color: [f32; 4]) {
+        self.lines.push(vertex(start, color));
+        self.lines.push(vertex(end, color));
+    }
+
+    // Outline connecting the last point back to the first
+    fn outline(&mut self, points: &[Vec2], color: [f32; 4]) {
+        for (i, point) in points.iter().enumerate() {
+            self.line(*point, points[(i + 1) % points.len()], color);
+        }
+    }
+
+    // Triangle fan, so only convex shapes fill correctly
+    fn fill(&mut self, points: &[Vec2], color: [f32; 4]) {
+        for i in 1..points.len().saturating_sub(1) {
+            self.triangles.push(vertex(points[0], color));
+            self.triangles.push(vertex(points[i], color));
+            self.triangles.push(vertex(points[i + 1], color));
+        }
+    }
+}
+
+// Durations are in seconds, None draws the shape for a single frame
+pub fn line(start: Vec2, end: Vec2, color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| shape.line(start, end, color));
+}
+
+pub fn arrow(start: Vec2, end: Vec2, color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| {
+        shape.line(start, end, color);
+
+        let direction = end - start;
+        let length = direction.norm();
+        if length <= f32::EPSILON {
+            return;
+        }
+
+        let head = (length * 0.25).min(0.25);
+        let back = -direction / length * head;
+        let side = glm::vec2(-back.y, back.x) * 0.5;
+        shape.line(end, end + back + side, color);
+        shape.line(end, end + back - side, color);
+    });
+}
+
+pub fn rect(center: Vec2, size: Vec2, color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| {
+        shape.outline(&corners(center, size), color)
+    });
+}
+
+pub fn filled_rect(center: Vec2, size: Vec2, color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| shape.fill(&corners(center, size), color));
+}
+
+pub fn circle(center: Vec2, radius: f32, color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| {
+        shape.outline(&circle_points(center, radius), color)
+    });
+}
+
+pub fn filled_circle(center: Vec2, radius: f32, color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| {
+        shape.fill(&circle_points(center, radius), color)
+    });
+}
+
+pub fn polygon(points: &[Vec2], color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| shape.outline(points, color));
+}
+
+// Points must form a convex polygon
+pub fn filled_polygon(points: &[Vec2], color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| shape.fill(points, color));
+}
+
+pub fn cross(center: Vec2, size: f32, color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| {
+        let half = size * 0.5;
+        shape.line(
+            center - glm::vec2(half, half),
+            center + glm::vec2(half, half),
+            color,
+        );
+        shape.line(
+            center - glm::vec2(half, -half),
+            center + glm::vec2(half, -half),
+            color,
+        );
+    });
+}
+
+// Position is the bottom left of the first line, height is the height of a capital letter
+pub fn text(position: Vec2, text: &str, height: f32, color: [f32; 4], duration: Option<f32>) {
+    submit(duration, |shape| {
+        let scale = height / font::GLYPH_HEIGHT;
+        let mut cursor = position;
+
+        for character in text.chars() {
+            if character == '\n' {
+                cursor = glm::vec2(position.x, cursor.y - font::LINE_ADVANCE * scale);
+                continue;
+            }
+
+            for [x1, y1, x2, y2] in font::strokes(character) {
+                shape.line(
+                    cursor + glm::vec2(x1, y1) * scale,
+                    cursor + glm::vec2(x2, y2) * scale,
+                    color,
+                );
+            }
+            cursor.x += font::GLYPH_ADVANCE * scale;
+        }
+    });
+}
+
+// Removes every queued shape, including ones with time left
+pub fn clear() {
+    #[cfg(feature = "debug-draw")]
+    SHAPES.with(|shapes| shapes.borrow_mut().clear());
+}
+
+// Line and triangle vertices of every queued shape
+#[cfg(feature = "debug-draw")]
+pub fn vertices() -> (Vec<DebugVertex>, Vec<DebugVertex>) {
+    SHAPES.with(|shapes| {
+        let shapes = shapes.borrow();
+        let lines = shapes.iter().flat_map(|shape| shape.lines.iter().copied());
+        let triangles = shapes
+            .iter()
+            .flat_map(|shape| shape.triangles.iter().copied());
+
+        (lines.collect(), triangles.collect())
+    })
+}
+
+// Called once the frame is recorded, drops shapes that have run out of time
+#[cfg(feature = "debug-draw")]
+pub fn end_frame(delta: f32) {
+    SHAPES.with(|shapes| {
+        let mut shapes = shapes.borrow_mut();
+
+        for shape in shapes.iter_mut() {
+            shape.remaining -= delta;
+        }
+        shapes.retain(|shape| shape.remaining > 0.0);
+    });
+}
+
+#[cfg(feature = "debug-draw")]
+fn submit(duration: Option<f32>, build: impl FnOnce(&mut DebugShape)) {
+    let mut shape = DebugShape {
+        remaining: duration.unwrap_or(0.0),
+        ..Default::default()
+    };
+    build(&mut shape);
+
+    SHAPES.with(|shapes| shapes.borrow_mut().push(shape));
+}
+
+#[cfg(not(feature = "debug-draw"))]
+fn submit(_duration: Option<f32>, _build: impl FnOnce(&mut DebugShape)) {}
+
+fn vertex(position: Vec2, color: [f32; 4]) -> DebugVertex {
+    DebugVertex {
+        position: [position.x, position.y],
+        color,
+    }
+}
+
+fn corners(center: Vec2, size: Vec2) -> [Vec2; 4] {
+    let half = size * 0.5;
+
+    [
+        center + glm::vec2(-half.x, -half.y),
+        center + glm::vec2(half.x, -half.y),
+        center + glm::vec2(half.x, half.y),
+        center + glm::vec2(-half.x, half.y),
+    ]
+}
+
+fn circle_points(center: Vec2, radius: f32) -> Vec<Vec2> {
+    (0..CIRCLE_SEGMENTS)
+        .map(|i| {
+            let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+            center + glm::vec2(angle.cos(), angle.sin()) * radius
+        })
+        .collect()
+}
@@ -0,0 +1,137 @@
+pub mod occluder;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::terra::data::Color;
+use crate::transform::Transform;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Point,
+    // Cone along the transform's x axis. Angles are the full cone width in degrees, the light
+    // fades out between the inner and outer angle.
+    Spot { inner_angle: f32, outer_angle: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shadows {
+    None,
+    Hard,
+    // Penumbras grow with the distance between occluder and receiver. Size is the width of the
+    // light source in world units, larger sizes give blurrier shadows.
+    Soft { size: f32 },
+}
+
+pub struct Light {
+    id: u64,
+    transform: Transform,
+    kind: LightKind,
+    color: Color,
+    intensity: f32,
+    radius: f32,
+    falloff: f32,
+    height: f32,
+    shadows: Shadows,
+}
+
+impl Light {
+    pub fn new(kind: LightKind) -> Light {
+        let mut hasher = DefaultHasher::new();
+        uuid::Uuid::new_v4().to_string().hash(&mut hasher);
+
+        Light {
+            id: hasher.finish(),
+            transform: Transform::new(),
+            kind,
+            color: Color::new(),
+            intensity: 1.0,
+            radius: 2.0,
+            falloff: 1.0,
+            height: 0.5,
+            shadows: Shadows::None,
+        }
+    }
+
+    pub fn point() -> Light {
+        Light::new(LightKind::Point)
+    }
+
+    pub fn spot(inner_angle: f32, outer_angle: f32) -> Light {
+        Light::new(LightKind::Spot {
+            inner_angle,
+            outer_angle,
+        })
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+
+    pub fn kind(&self) -> &LightKind {
+        &self.kind
+    }
+
+    pub fn set_kind(&mut self, kind: LightKind) {
+        self.kind = kind;
+    }
+
+    pub fn color(&self) -> &Color {
+        &self.color
+    }
+
+    pub fn color_mut(&mut self) -> &mut Color {
+        &mut self.color
+    }
+
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    pub fn set_intensity(&mut self, intensity: f32) {
+        self.intensity = intensity.max(0.0);
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn set_radius(&mut self, radius: f32) {
+        self.radius = radius.max(f32::EPSILON);
+    }
+
+    // Exponent applied to the linear fade towards the radius, 1.0 fades linearly
+    pub fn falloff(&self) -> f32 {
+        self.falloff
+    }
+
+    pub fn set_falloff(&mut self, falloff: f32) {
+        self.falloff = falloff.max(0.0);
+    }
+
+    // Distance of the light above the sprites, lower values give grazing light on normal maps
+    pub fn height(&self) -> f32 {
+        self.height
+    }
+
+    pub fn set_height(&mut self, height: f32) {
+        self.height = height;
+    }
+
+    // Only occluders registered with the graphics context cast shadows
+    pub fn shadows(&self) -> Shadows {
+        self.shadows
+    }
+
+    pub fn set_shadows(&mut self, shadows: Shadows) {
+        self.shadows = shadows;
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use nalgebra_glm as glm;
+
+use crate::sprite::Sprite;
+use crate::terra::error::TerraResult;
+use crate::transform::Transform;
+
+// Outline that blocks light from lights with shadows enabled. Points are in local space and the
+// last point connects back to the first.
+pub struct Occluder {
+    id: u64,
+    transform: Transform,
+    points: Vec<[f32; 2]>,
+}
+
+impl Occluder {
+    pub fn new(points: Vec<[f32; 2]>) -> Occluder {
+        let mut hasher = DefaultHasher::new();
+        uuid::Uuid::new_v4().to_string().hash(&mut hasher);
+
+        Occluder {
+            id: hasher.finish(),
+            transform: Transform::new(),
+            points,
+        }
+    }
+
+    pub fn rectangle(width: f32, height: f32) -> Occluder {
+        let (x, y) = (width * 0.5, height * 0.5);
+        Occluder::new(vec![[-x, -y], [x, -y], [x, y], [-x, y]])
+    }
+
+    // Convex hull of the sprite's opaque pixels, on the same quad the sprite is drawn with.
+    // Concave shapes should be built from several occluders instead.
+    pub fn from_sprite(sprite: &Sprite, alpha_threshold: u8) -> TerraResult<Occluder> {
+        Ok(Occluder::new(sprite.outline(alpha_threshold)?))
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+
+    pub fn points(&self) -> &[[f32; 2]] {
+        &self.points
+    }
+
+    pub fn set_points(&mut self, points: Vec<[f32; 2]>) {
+        self.points = points;
+    }
+
+    // Edges of the outline in world space as [start.x, start.y, end.x, end.y]
+    pub fn segments(&self) -> Vec<[f32; 4]> {
+        let model = self.transform.matrix();
+        let points: Vec<_> = self
+            .points
+            .iter()
+            .map(|point| model * glm::vec4(point[0], point[1], 0.0, 1.0))
+            .collect();
+
+        match points.len() {
+            0 | 1 => vec![],
+            2 => vec![[points[0].x, points[0].y, points[1].x, points[1].y]],
+            count => (0..count)
+                .map(|i| {
+                    let (start, end) = (points[i], points[(i + 1) % count]);
+                    [start.x, start.y, end.x, end.y]
+                })
+                .collect(),
+        }
+    }
+}
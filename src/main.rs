@@ -1,17 +1,20 @@
 pub mod camera;
+pub mod debug_draw;
+pub mod light;
+pub mod material;
+pub mod mesh;
+pub mod particle;
+pub mod shape;
 pub mod sprite;
 pub mod terra;
 pub mod transform;
+pub mod vfs;
 
-use std::{
-    cell::RefCell,
-    collections::hash_map::DefaultHasher,
-    hash::{Hash, Hasher},
-    rc::Rc,
-};
+use std::{cell::RefCell, process, rc::Rc};
 
 use sprite::{loader::SpriteLoader, SpriteRenderer};
-use terra::Terra;
+use terra::{config::TerraConfig, Terra};
+use vfs::{Archive, Directory};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::EventLoop,
@@ -19,12 +22,42 @@ use winit::{
 
 fn main() {
     let events = EventLoop::new();
+
+    // Mounted from the source tree so the app runs from any working directory
+    vfs::mount(
+        "sprites",
+        Directory::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/assets/sprites")),
+    );
+    // A packed archive next to the executable takes precedence over the source tree
+    let archive = std::env::current_exe().map(|exe| exe.with_file_name("sprites.tpak"));
+    if let Ok(archive) = archive.and_then(Archive::open) {
+        vfs::mount("sprites", archive);
+    }
+
+    vfs::mount(
+        "shaders",
+        Directory::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders")),
+    );
+
     let sprites = SpriteLoader::load();
 
-    let mut terra = Terra::init(&events);
+    let config = TerraConfig {
+        app_name: "Hello Triangle".into(),
+        window_title: "Hello Triangle".into(),
+        samples: 4,
+        ..Default::default()
+    };
+
+    let mut terra = match Terra::init(&events, &config) {
+        Ok(terra) => terra,
+        Err(e) => {
+            eprintln!("Failed to start the renderer: {e}");
+            process::exit(1);
+        }
+    };
     let id = terra.window_id();
 
-    if let Some(sprite) = sprites.get(&get_id("./src/assets/sprites/094 - Copy.png")) {
+    if let Some(sprite) = sprites.get_by_path("sprites://094 - Copy.png") {
         let gengar = Rc::new(RefCell::new(SpriteRenderer::new(sprite)));
         let mut context = terra.graphics_context().borrow_mut();
         context.add_sprite_renderer(&gengar);
@@ -37,23 +70,24 @@ fn main() {
             Event::WindowEvent { event, window_id } if window_id == id => match event {
                 WindowEvent::CloseRequested => flow.set_exit(),
                 WindowEvent::Resized(_) => {
-                    terra.recreate_swapchain();
+                    if let Err(e) = terra.recreate_swapchain() {
+                        eprintln!("{e}");
+                        flow.set_exit_with_code(1);
+                    }
                 }
                 _ => (),
             },
 
             Event::RedrawRequested(window_id) if window_id == id => {}
 
-            Event::RedrawEventsCleared => terra.render(),
+            Event::RedrawEventsCleared => {
+                if let Err(e) = terra.render() {
+                    eprintln!("{e}");
+                    flow.set_exit_with_code(1);
+                }
+            }
 
             _ => (),
         }
     })
 }
-
-fn get_id(path: &str) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    path.hash(&mut hasher);
-
-    hasher.finish()
-}
@@ -0,0 +1,275 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::sprite::Sprite;
+use crate::terra::error::{TerraError, TerraResult};
+use crate::terra::shader::{
+    loader::ShaderLoader,
+    reflect::{SamplerBinding, ShaderReflection, UniformBlock, UniformType},
+    ShaderKind,
+};
+
+// Descriptor set reserved for material parameters. Sets 0 and 1 hold the camera and the sprite.
+pub const MATERIAL_SET: u32 = 2;
+
+#[derive(Clone)]
+pub enum MaterialParam {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec4([f32; 4]),
+    Color([f32; 4]),
+    Texture(Sprite),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MaterialError {
+    UnknownParam(String),
+    TypeMismatch { name: String, expected: String },
+}
+
+impl fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MaterialError::UnknownParam(name) => {
+                write!(f, "Material has no parameter named {name}")
+            }
+            MaterialError::TypeMismatch { name, expected } => {
+                write!(f, "Material parameter {name} expects a {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MaterialError {}
+
+// Parameters declared in the material set by either of its shaders. Only the first uniform
+// block is used, so all numeric parameters should live in one block.
+#[derive(Clone, Debug, Default)]
+pub struct MaterialLayout {
+    uniform_block: Option<UniformBlock>,
+    textures: Vec<SamplerBinding>,
+}
+
+impl MaterialLayout {
+    pub fn new(reflections: &[&ShaderReflection]) -> MaterialLayout {
+        let mut layout = MaterialLayout::default();
+
+        for reflection in reflections.iter() {
+            if layout.uniform_block.is_none() {
+                layout.uniform_block = reflection
+                    .uniform_blocks()
+                    .iter()
+                    .find(|block| block.set == MATERIAL_SET)
+                    .cloned();
+            }
+
+            for sampler in reflection.samplers().iter() {
+                let known = layout
+                    .textures
+                    .iter()
+                    .any(|texture| texture.binding == sampler.binding);
+
+                if sampler.set == MATERIAL_SET && !known {
+                    layout.textures.push(sampler.clone());
+                }
+            }
+        }
+
+        layout
+    }
+
+    pub fn uniform_block(&self) -> Option<&UniformBlock> {
+        self.uniform_block.as_ref()
+    }
+
+    pub fn textures(&self) -> &[SamplerBinding] {
+        &self.textures
+    }
+
+    pub fn texture(&self, name: &str) -> Option<&SamplerBinding> {
+        self.textures.iter().find(|texture| texture.name == name)
+    }
+}
+
+// A pair of shaders plus the values they read. Parameters are matched by name against the
+// uniform block members and samplers the shaders declare in the material set.
+pub struct Material {
+    id: u64,
+    vertex_shader: Box<str>,
+    fragment_shader: Box<str>,
+    layout: MaterialLayout,
+    params: Vec<(String, MaterialParam)>,
+    uniform_data: Vec<u8>,
+    revision: u64,
+}
+
+impl Material {
+    pub fn new(shaders: &ShaderLoader, fragment_shader: &str) -> TerraResult<Material> {
+        Material::with_vertex_shader(shaders, "sprite", fragment_shader)
+    }
+
+    pub fn with_vertex_shader(
+        shaders: &ShaderLoader,
+        vertex_shader: &str,
+        fragment_shader: &str,
+    ) -> TerraResult<Material> {
+        let vertex = shaders
+            .reflection(ShaderKind::Vertex, vertex_shader)
+            .ok_or_else(|| TerraError::shader(vertex_shader, "not found"))?;
+        let fragment = shaders
+            .reflection(ShaderKind::Fragment, fragment_shader)
+            .ok_or_else(|| TerraError::shader(fragment_shader, "not found"))?;
+
+        let layout = MaterialLayout::new(&[fragment, vertex]);
+        let size = layout
+            .uniform_block()
+            .map_or(0, |block| block.size as usize);
+
+        let mut hasher = DefaultHasher::new();
+        uuid::Uuid::new_v4().to_string().hash(&mut hasher);
+
+        Ok(Material {
+            id: hasher.finish(),
+            vertex_shader: vertex_shader.into(),
+            fragment_shader: fragment_shader.into(),
+            layout,
+            params: vec![],
+            uniform_data: vec![0; size],
+            revision: 0,
+        })
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn vertex_shader(&self) -> &str {
+        &self.vertex_shader
+    }
+
+    pub fn fragment_shader(&self) -> &str {
+        &self.fragment_shader
+    }
+
+    pub fn layout(&self) -> &MaterialLayout {
+        &self.layout
+    }
+
+    // Bumped on every change so the renderer knows when to rebuild the material's descriptor set
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn params(&self) -> &[(String, MaterialParam)] {
+        &self.params
+    }
+
+    pub fn param(&self, name: &str) -> Option<&MaterialParam> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn set_float(&mut self, name: &str, value: f32) -> Result<(), MaterialError> {
+        self.set_param(name, MaterialParam::Float(value))
+    }
+
+    pub fn set_vec2(&mut self, name: &str, value: [f32; 2]) -> Result<(), MaterialError> {
+        self.set_param(name, MaterialParam::Vec2(value))
+    }
+
+    pub fn set_vec4(&mut self, name: &str, value: [f32; 4]) -> Result<(), MaterialError> {
+        self.set_param(name, MaterialParam::Vec4(value))
+    }
+
+    // Colors can be declared as either vec3 or vec4, the alpha is dropped for a vec3
+    pub fn set_color(&mut self, name: &str, value: [f32; 4]) -> Result<(), MaterialError> {
+        self.set_param(name, MaterialParam::Color(value))
+    }
+
+    pub fn set_texture(&mut self, name: &str, sprite: Sprite) -> Result<(), MaterialError> {
+        self.set_param(name, MaterialParam::Texture(sprite))
+    }
+
+    pub fn set_param(&mut self, name: &str, value: MaterialParam) -> Result<(), MaterialError> {
+        match &value {
+            MaterialParam::Texture(_) => {
+                if self.layout.texture(name).is_none() {
+                    return Err(self.mismatch(name));
+                }
+            }
+            _ => {
+                let member = self
+                    .layout
+                    .uniform_block
+                    .as_ref()
+                    .and_then(|block| block.member(name));
+
+                let (offset, ty) = match member {
+                    Some(member) => (member.offset as usize, member.ty),
+                    None => return Err(self.mismatch(name)),
+                };
+
+                let values: &[f32] = match (&value, ty) {
+                    (MaterialParam::Float(value), UniformType::Float) => {
+                        std::slice::from_ref(value)
+                    }
+                    (MaterialParam::Vec2(value), UniformType::Vec2) => value,
+                    (MaterialParam::Vec4(value), UniformType::Vec4) => value,
+                    (MaterialParam::Color(value), UniformType::Vec4) => value,
+                    (MaterialParam::Color(value), UniformType::Vec3) => &value[..3],
+                    _ => return Err(self.mismatch(name)),
+                };
+
+                for (index, value) in values.iter().enumerate() {
+                    let start = offset + index * 4;
+                    self.uniform_data[start..start + 4].copy_from_slice(&value.to_ne_bytes());
+                }
+            }
+        }
+
+        match self.params.iter_mut().find(|(param, _)| param == name) {
+            Some((_, param)) => *param = value,
+            None => self.params.push((name.to_owned(), value)),
+        }
+
+        self.revision += 1;
+        Ok(())
+    }
+
+    // Texture parameters paired with the binding they are sampled from
+    pub fn textures(&self) -> impl Iterator<Item = (u32, &Sprite)> {
+        self.params.iter().filter_map(|(name, value)| match value {
+            MaterialParam::Texture(sprite) => self
+                .layout
+                .texture(name)
+                .map(|texture| (texture.binding, sprite)),
+            _ => None,
+        })
+    }
+
+    // Contents of the material's uniform block, laid out as the shader declares it
+    pub fn uniform_data(&self) -> &[u8] {
+        &self.uniform_data
+    }
+
+    fn mismatch(&self, name: &str) -> MaterialError {
+        let member = self
+            .layout
+            .uniform_block()
+            .and_then(|block| block.member(name));
+
+        let expected = match member {
+            Some(member) => format!("{:?}", member.ty),
+            None if self.layout.texture(name).is_some() => "Texture".to_owned(),
+            None => return MaterialError::UnknownParam(name.to_owned()),
+        };
+
+        MaterialError::TypeMismatch {
+            name: name.to_owned(),
+            expected,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use crate::sprite::Sprite;
+use crate::terra::data::Color;
+use crate::terra::error::TerraResult;
+use crate::transform::Transform;
+
+pub use crate::terra::data::MeshVertex;
+
+// Triangle list in local space. UVs use the same layout as sprites, where the unit quad from
+// -0.5 to 0.5 covers the whole texture.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<MeshVertex>, indices: Vec<u32>) -> Mesh {
+        Mesh { vertices, indices }
+    }
+
+    pub fn quad(width: f32, height: f32, color: [f32; 4]) -> Mesh {
+        Mesh::gradient_quad(width, height, [color; 4])
+    }
+
+    // Colors go counter-clockwise from the bottom left corner
+    pub fn gradient_quad(width: f32, height: f32, colors: [[f32; 4]; 4]) -> Mesh {
+        let (x, y) = (width * 0.5, height * 0.5);
+
+        Mesh::new(
+            vec![
+                MeshVertex::new([-x, -y], [0.0, 0.0], colors[0]),
+                MeshVertex::new([x, -y], [1.0, 0.0], colors[1]),
+                MeshVertex::new([x, y], [1.0, 1.0], colors[2]),
+                MeshVertex::new([-x, y], [0.0, 1.0], colors[3]),
+            ],
+            vec![0, 1, 2, 2, 3, 0],
+        )
+    }
+
+    // Fan over the convex hull of the sprite's opaque pixels, which skips most of the
+    // transparent area a full quad would draw
+    pub fn from_sprite(sprite: &Sprite, alpha_threshold: u8) -> TerraResult<Mesh> {
+        let (outline, dimensions) = sprite.outline_uv(alpha_threshold)?;
+
+        let vertices = outline
+            .iter()
+            .map(|uv| MeshVertex::new(sprite.local_point(*uv, dimensions), *uv, [1.0; 4]))
+            .collect();
+        let indices = (1..outline.len().saturating_sub(1) as u32)
+            .flat_map(|i| [0, i, i + 1])
+            .collect();
+
+        Ok(Mesh::new(vertices, indices))
+    }
+}
+
+pub struct MeshRenderer {
+    id: u64,
+    transform: Transform,
+    mesh: Mesh,
+    sprite: Option<Sprite>,
+    color: Color,
+    sorting_order: i32,
+    revision: u64,
+}
+
+impl MeshRenderer {
+    pub fn new(mesh: Mesh) -> MeshRenderer {
+        let mut hasher = DefaultHasher::new();
+        uuid::Uuid::new_v4().to_string().hash(&mut hasher);
+
+        MeshRenderer {
+            id: hasher.finish(),
+            transform: Transform::new(),
+            mesh,
+            sprite: None,
+            color: Color::new(),
+            sorting_order: 0,
+            revision: 0,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+
+    pub fn mesh(&self) -> &Mesh {
+        &self.mesh
+    }
+
+    // The mesh is uploaded again on the next frame, so deforming it every frame is fine
+    pub fn mesh_mut(&mut self) -> &mut Mesh {
+        self.revision += 1;
+        &mut self.mesh
+    }
+
+    pub fn set_mesh(&mut self, mesh: Mesh) {
+        self.mesh = mesh;
+        self.revision += 1;
+    }
+
+    pub fn sprite(&self) -> Option<&Sprite> {
+        self.sprite.as_ref()
+    }
+
+    // Meshes without a sprite are drawn with their vertex colors only
+    pub fn set_sprite(&mut self, sprite: Option<Sprite>) {
+        self.sprite = sprite;
+    }
+
+    pub fn color(&self) -> &Color {
+        &self.color
+    }
+
+    pub fn color_mut(&mut self) -> &mut Color {
+        &mut self.color
+    }
+
+    // Lower orders are drawn first. Within the same order sprites are drawn first, then meshes
+    // and then shapes.
+    pub fn sorting_order(&self) -> i32 {
+        self.sorting_order
+    }
+
+    pub fn set_sorting_order(&mut self, sorting_order: i32) {
+        self.sorting_order = sorting_order;
+    }
+
+    // Bumped whenever the mesh may have changed so the renderer knows when to upload it again
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+}
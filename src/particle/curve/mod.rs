@@ -0,0 +1,176 @@
+use nalgebra_glm::Vec2;
+
+use super::random::Random;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParticleRange<T> {
+    pub min: T,
+    pub max: T,
+}
+
+impl<T: Copy> ParticleRange<T> {
+    pub fn new(min: T, max: T) -> ParticleRange<T> {
+        ParticleRange { min, max }
+    }
+
+    pub fn constant(value: T) -> ParticleRange<T> {
+        ParticleRange {
+            min: value,
+            max: value,
+        }
+    }
+}
+
+impl ParticleRange<f32> {
+    pub fn sample(&self, random: &mut Random) -> f32 {
+        random.range(self.min, self.max)
+    }
+}
+
+impl ParticleRange<Vec2> {
+    pub fn sample(&self, random: &mut Random) -> Vec2 {
+        Vec2::new(
+            random.range(self.min.x, self.max.x),
+            random.range(self.min.y, self.max.y),
+        )
+    }
+}
+
+// Piecewise linear curve over a particle's normalized lifetime
+#[derive(Clone, Debug, PartialEq)]
+pub struct Curve {
+    keys: Vec<(f32, f32)>,
+}
+
+impl Curve {
+    pub fn new(keys: Vec<(f32, f32)>) -> Curve {
+        let mut keys = keys;
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Curve { keys }
+    }
+
+    pub fn constant(value: f32) -> Curve {
+        Curve::new(vec![(0.0, value)])
+    }
+
+    pub fn linear(start: f32, end: f32) -> Curve {
+        Curve::new(vec![(0.0, start), (1.0, end)])
+    }
+
+    pub fn keys(&self) -> &[(f32, f32)] {
+        &self.keys
+    }
+
+    pub fn evaluate(&self, time: f32) -> f32 {
+        evaluate(&self.keys, time, 1.0, |a, b, t| a + (b - a) * t)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    keys: Vec<(f32, [f32; 4])>,
+}
+
+impl Gradient {
+    pub fn new(keys: Vec<(f32, [f32; 4])>) -> Gradient {
+        let mut keys = keys;
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Gradient { keys }
+    }
+
+    pub fn constant(color: [f32; 4]) -> Gradient {
+        Gradient::new(vec![(0.0, color)])
+    }
+
+    pub fn linear(start: [f32; 4], end: [f32; 4]) -> Gradient {
+        Gradient::new(vec![(0.0, start), (1.0, end)])
+    }
+
+    pub fn keys(&self) -> &[(f32, [f32; 4])] {
+        &self.keys
+    }
+
+    pub fn evaluate(&self, time: f32) -> [f32; 4] {
+        evaluate(&self.keys, time, [1.0; 4], |a, b, t| {
+            [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
+        })
+    }
+}
+
+fn evaluate<T: Copy>(keys: &[(f32, T)], time: f32, default: T, lerp: impl Fn(T, T, f32) -> T) -> T {
+    let (first, last) = match (keys.first(), keys.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return default,
+    };
+
+    if time <= first.0 {
+        return first.1;
+    }
+
+    if time >= last.0 {
+        return last.1;
+    }
+
+    for pair in keys.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        if time <= end.0 {
+            let span = end.0 - start.0;
+            let t = if span > 0.0 {
+                (time - start.0) / span
+            } else {
+                1.0
+            };
+            return lerp(start.1, end.1, t);
+        }
+    }
+
+    last.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_clamps_outside_its_keys() {
+        let curve = Curve::new(vec![(0.75, 4.0), (0.25, 2.0)]);
+        assert_eq!(curve.evaluate(0.0), 2.0);
+        assert_eq!(curve.evaluate(0.25), 2.0);
+        assert_eq!(curve.evaluate(0.75), 4.0);
+        assert_eq!(curve.evaluate(1.0), 4.0);
+    }
+
+    #[test]
+    fn curve_interpolates_between_keys() {
+        let curve = Curve::new(vec![(0.0, 0.0), (0.5, 1.0), (1.0, 0.0)]);
+        assert_eq!(curve.evaluate(0.25), 0.5);
+        assert_eq!(curve.evaluate(0.5), 1.0);
+        assert_eq!(curve.evaluate(0.875), 0.25);
+
+        assert_eq!(Curve::constant(3.0).evaluate(0.5), 3.0);
+        assert_eq!(Curve::new(vec![]).evaluate(0.5), 1.0);
+    }
+
+    #[test]
+    fn gradient_interpolates_each_channel() {
+        let gradient = Gradient::linear([0.0, 1.0, 0.0, 1.0], [1.0, 0.0, 0.5, 0.0]);
+        assert_eq!(gradient.evaluate(-1.0), [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(gradient.evaluate(0.5), [0.5, 0.5, 0.25, 0.5]);
+        assert_eq!(gradient.evaluate(2.0), [1.0, 0.0, 0.5, 0.0]);
+    }
+
+    #[test]
+    fn range_samples_within_bounds() {
+        let mut random = Random::from_seed(7);
+        let range = ParticleRange::new(Vec2::new(-1.0, 2.0), Vec2::new(1.0, 3.0));
+
+        for _ in 0..100 {
+            let value = range.sample(&mut random);
+            assert!((-1.0..1.0).contains(&value.x));
+            assert!((2.0..3.0).contains(&value.y));
+        }
+        assert_eq!(ParticleRange::constant(5.0).sample(&mut random), 5.0);
+    }
+}
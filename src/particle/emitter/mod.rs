@@ -0,0 +1,443 @@
+use std::collections::hash_map::DefaultHasher;
+use std::f32::consts::TAU;
+use std::hash::{Hash, Hasher};
+
+use nalgebra_glm::{self as glm, Vec2};
+
+use crate::sprite::Sprite;
+use crate::transform::Transform;
+
+use super::curve::{Curve, Gradient, ParticleRange};
+use super::random::Random;
+use super::Particle;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmitterShape {
+    Point,
+    Circle { radius: f32 },
+    Rectangle { width: f32, height: f32 },
+    Edge { length: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SimulationSpace {
+    // Particles follow the emitter's transform after they are spawned
+    Local,
+    // Particles are left behind in the world when the emitter moves
+    World,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Burst {
+    pub time: f32,
+    pub count: u32,
+}
+
+impl Burst {
+    pub fn new(time: f32, count: u32) -> Burst {
+        Burst { time, count }
+    }
+}
+
+pub struct ParticleEmitter {
+    id: u64,
+    transform: Transform,
+    sprite: Sprite,
+    rate: f32,
+    bursts: Vec<Burst>,
+    duration: f32,
+    looping: bool,
+    playing: bool,
+    shape: EmitterShape,
+    simulation_space: SimulationSpace,
+    max_particles: usize,
+    lifetime: ParticleRange<f32>,
+    velocity: ParticleRange<Vec2>,
+    gravity: ParticleRange<Vec2>,
+    rotation: ParticleRange<f32>,
+    angular_velocity: ParticleRange<f32>,
+    size: ParticleRange<f32>,
+    color_over_lifetime: Gradient,
+    size_over_lifetime: Curve,
+    particles: Vec<Particle>,
+    time: f32,
+    pending: f32,
+    random: Random,
+}
+
+impl ParticleEmitter {
+    pub fn new(sprite: Sprite) -> ParticleEmitter {
+        let mut hasher = DefaultHasher::new();
+        uuid::Uuid::new_v4().to_string().hash(&mut hasher);
+
+        ParticleEmitter {
+            id: hasher.finish(),
+            transform: Transform::new(),
+            sprite,
+            rate: 10.0,
+            bursts: vec![],
+            duration: 5.0,
+            looping: true,
+            playing: true,
+            shape: EmitterShape::Point,
+            simulation_space: SimulationSpace::Local,
+            max_particles: 1000,
+            lifetime: ParticleRange::constant(1.0),
+            velocity: ParticleRange::new(Vec2::new(-0.5, 1.0), Vec2::new(0.5, 2.0)),
+            gravity: ParticleRange::constant(Vec2::zeros()),
+            rotation: ParticleRange::constant(0.0),
+            angular_velocity: ParticleRange::constant(0.0),
+            size: ParticleRange::constant(0.1),
+            color_over_lifetime: Gradient::constant([1.0; 4]),
+            size_over_lifetime: Curve::constant(1.0),
+            particles: vec![],
+            time: 0.0,
+            pending: 0.0,
+            random: Random::new(),
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn sprite(&self) -> &Sprite {
+        &self.sprite
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate.max(0.0);
+    }
+
+    pub fn bursts(&self) -> &[Burst] {
+        &self.bursts
+    }
+
+    pub fn add_burst(&mut self, burst: Burst) {
+        self.bursts.push(burst);
+    }
+
+    pub fn clear_bursts(&mut self) {
+        self.bursts.clear();
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, duration: f32) {
+        self.duration = duration.max(f32::EPSILON);
+    }
+
+    pub fn looping(&self) -> bool {
+        self.looping
+    }
+
+    pub fn set_looping(&mut self, looping: bool) {
+        self.looping = looping;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+        self.time = 0.0;
+        self.pending = 0.0;
+    }
+
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    pub fn shape(&self) -> &EmitterShape {
+        &self.shape
+    }
+
+    pub fn set_shape(&mut self, shape: EmitterShape) {
+        self.shape = shape;
+    }
+
+    pub fn simulation_space(&self) -> SimulationSpace {
+        self.simulation_space
+    }
+
+    pub fn set_simulation_space(&mut self, simulation_space: SimulationSpace) {
+        self.simulation_space = simulation_space;
+    }
+
+    pub fn max_particles(&self) -> usize {
+        self.max_particles
+    }
+
+    pub fn set_max_particles(&mut self, max_particles: usize) {
+        self.max_particles = max_particles;
+        self.particles.truncate(max_particles);
+    }
+
+    pub fn lifetime(&self) -> &ParticleRange<f32> {
+        &self.lifetime
+    }
+
+    pub fn set_lifetime(&mut self, lifetime: ParticleRange<f32>) {
+        self.lifetime = lifetime;
+    }
+
+    pub fn velocity(&self) -> &ParticleRange<Vec2> {
+        &self.velocity
+    }
+
+    pub fn set_velocity(&mut self, velocity: ParticleRange<Vec2>) {
+        self.velocity = velocity;
+    }
+
+    pub fn gravity(&self) -> &ParticleRange<Vec2> {
+        &self.gravity
+    }
+
+    pub fn set_gravity(&mut self, gravity: ParticleRange<Vec2>) {
+        self.gravity = gravity;
+    }
+
+    pub fn rotation(&self) -> &ParticleRange<f32> {
+        &self.rotation
+    }
+
+    pub fn set_rotation(&mut self, rotation: ParticleRange<f32>) {
+        self.rotation = rotation;
+    }
+
+    pub fn angular_velocity(&self) -> &ParticleRange<f32> {
+        &self.angular_velocity
+    }
+
+    pub fn set_angular_velocity(&mut self, angular_velocity: ParticleRange<f32>) {
+        self.angular_velocity = angular_velocity;
+    }
+
+    pub fn size(&self) -> &ParticleRange<f32> {
+        &self.size
+    }
+
+    pub fn set_size(&mut self, size: ParticleRange<f32>) {
+        self.size = size;
+    }
+
+    pub fn color_over_lifetime(&self) -> &Gradient {
+        &self.color_over_lifetime
+    }
+
+    pub fn set_color_over_lifetime(&mut self, gradient: Gradient) {
+        self.color_over_lifetime = gradient;
+    }
+
+    pub fn size_over_lifetime(&self) -> &Curve {
+        &self.size_over_lifetime
+    }
+
+    pub fn set_size_over_lifetime(&mut self, curve: Curve) {
+        self.size_over_lifetime = curve;
+    }
+
+    pub fn emit(&mut self, count: u32) {
+        for _ in 0..count {
+            if self.particles.len() >= self.max_particles {
+                break;
+            }
+
+            let particle = self.spawn();
+            self.particles.push(particle);
+        }
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        for particle in self.particles.iter_mut() {
+            particle.update(delta);
+        }
+        self.particles.retain(|p| p.is_alive());
+
+        if self.playing {
+            self.advance(delta);
+        }
+    }
+
+    fn advance(&mut self, delta: f32) {
+        let start = self.time;
+        let end = self.time + delta;
+
+        let mut count = self.bursts_between(start, end);
+
+        self.pending += self.rate * delta;
+        let continuous = self.pending.floor();
+        self.pending -= continuous;
+        count += continuous as u32;
+
+        if end >= self.duration {
+            if self.looping {
+                // A long frame can cover several loops, each firing every burst again
+                let loops = (end / self.duration).floor() as u32;
+                self.time = end % self.duration;
+                let repeated = self
+                    .bursts_between(0.0, self.duration)
+                    .saturating_mul(loops - 1);
+                count = count.saturating_add(repeated);
+                count = count.saturating_add(self.bursts_between(0.0, self.time));
+            } else {
+                self.time = self.duration;
+                self.playing = false;
+            }
+        } else {
+            self.time = end;
+        }
+
+        self.emit(count);
+    }
+
+    fn bursts_between(&self, start: f32, end: f32) -> u32 {
+        self.bursts
+            .iter()
+            .filter(|burst| burst.time >= start && burst.time < end)
+            .map(|burst| burst.count)
+            .sum()
+    }
+
+    fn spawn(&mut self) -> Particle {
+        let position = self.sample_shape();
+        let velocity = self.velocity.sample(&mut self.random);
+
+        let (position, velocity) = match self.simulation_space {
+            SimulationSpace::Local => (position, velocity),
+            SimulationSpace::World => {
+                let model = self.transform.matrix();
+                let position = model * glm::vec4(position.x, position.y, 0.0, 1.0);
+                let velocity = model * glm::vec4(velocity.x, velocity.y, 0.0, 0.0);
+                (position.xy(), velocity.xy())
+            }
+        };
+
+        Particle {
+            position,
+            velocity,
+            gravity: self.gravity.sample(&mut self.random),
+            rotation: self.rotation.sample(&mut self.random),
+            angular_velocity: self.angular_velocity.sample(&mut self.random),
+            size: self.size.sample(&mut self.random),
+            age: 0.0,
+            lifetime: self.lifetime.sample(&mut self.random),
+        }
+    }
+
+    fn sample_shape(&mut self) -> Vec2 {
+        let random = &mut self.random;
+
+        match self.shape {
+            EmitterShape::Point => Vec2::zeros(),
+            EmitterShape::Circle { radius } => {
+                let angle = random.next_f32() * TAU;
+                let distance = radius * random.next_f32().sqrt();
+                Vec2::new(angle.cos(), angle.sin()) * distance
+            }
+            EmitterShape::Rectangle { width, height } => Vec2::new(
+                random.range(-0.5, 0.5) * width,
+                random.range(-0.5, 0.5) * height,
+            ),
+            EmitterShape::Edge { length } => Vec2::new(random.range(-0.5, 0.5) * length, 0.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn emitter() -> ParticleEmitter {
+        let mut emitter =
+            ParticleEmitter::new(Sprite::new(Uuid::nil(), "sprites://dot.png".into()));
+        emitter.set_rate(0.0);
+        emitter.set_duration(1.0);
+        emitter.set_lifetime(ParticleRange::constant(100.0));
+        emitter
+    }
+
+    #[test]
+    fn rate_accumulates_across_frames() {
+        let mut emitter = emitter();
+        emitter.set_rate(3.0);
+
+        // A third of a particle per frame, so one spawns every third frame
+        for _ in 0..2 {
+            emitter.update(0.125);
+        }
+        assert_eq!(emitter.particles().len(), 0);
+        emitter.update(0.125);
+        assert_eq!(emitter.particles().len(), 1);
+
+        for _ in 0..5 {
+            emitter.update(0.125);
+        }
+        assert_eq!(emitter.particles().len(), 3);
+    }
+
+    #[test]
+    fn bursts_fire_once_per_loop() {
+        let mut emitter = emitter();
+        emitter.add_burst(Burst::new(0.5, 4));
+
+        emitter.update(0.25);
+        assert_eq!(emitter.particles().len(), 0);
+        emitter.update(0.5);
+        assert_eq!(emitter.particles().len(), 4);
+        emitter.update(0.5);
+        assert_eq!(emitter.particles().len(), 4);
+        emitter.update(0.5);
+        assert_eq!(emitter.particles().len(), 8);
+    }
+
+    #[test]
+    fn bursts_fire_for_every_skipped_loop() {
+        let mut emitter = emitter();
+        emitter.add_burst(Burst::new(0.0, 1));
+        emitter.add_burst(Burst::new(0.5, 2));
+
+        // Covers [0, 3.25): three whole loops plus the burst at 0 of the fourth
+        emitter.update(3.25);
+        assert_eq!(emitter.particles().len(), 3 * 3 + 1);
+    }
+
+    #[test]
+    fn bursts_stop_after_the_last_loop() {
+        let mut emitter = emitter();
+        emitter.set_looping(false);
+        emitter.add_burst(Burst::new(0.5, 2));
+
+        emitter.update(3.0);
+        assert_eq!(emitter.particles().len(), 2);
+        assert!(!emitter.is_playing());
+
+        emitter.update(3.0);
+        assert_eq!(emitter.particles().len(), 2);
+    }
+}
@@ -0,0 +1,40 @@
+pub mod curve;
+pub mod emitter;
+pub mod random;
+pub use emitter::ParticleEmitter;
+
+use nalgebra_glm::Vec2;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Particle {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    pub gravity: Vec2,
+    pub rotation: f32,
+    pub angular_velocity: f32,
+    pub size: f32,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+impl Particle {
+    // Normalized age in the range [0, 1], used to sample the over-lifetime curves
+    pub fn progress(&self) -> f32 {
+        if self.lifetime > 0.0 {
+            (self.age / self.lifetime).clamp(0.0, 1.0)
+        } else {
+            1.0
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.age < self.lifetime
+    }
+
+    pub fn update(&mut self, delta: f32) {
+        self.age += delta;
+        self.velocity += self.gravity * delta;
+        self.position += self.velocity * delta;
+        self.rotation += self.angular_velocity * delta;
+    }
+}
@@ -0,0 +1,63 @@
+#version 450
+
+// Builds a 1D shadow map per light. Each column is a direction around the light and stores the
+// distance to the nearest occluder edge along it, or the light's radius if nothing blocks it.
+
+#include "include/lights.glsl"
+
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) uniform Lights {
+    vec4 ambient;
+    uint count;
+    Light lights[MAX_LIGHTS];
+} lights;
+
+// Occluder edges in world space as start.xy, end.xy
+layout(set = 0, binding = 1) readonly buffer Segments {
+    vec4 segments[];
+} segments;
+
+layout(set = 0, binding = 2, r32f) uniform writeonly image2D shadow_map;
+
+layout(push_constant) uniform Constants {
+    uint segment_count;
+} constants;
+
+void main()
+{
+    ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+    int width = imageSize(shadow_map).x;
+
+    if (texel.x >= width || texel.y >= int(min(lights.count, MAX_LIGHTS))) {
+        return;
+    }
+
+    Light light = lights.lights[texel.y];
+    float nearest = light.position.w;
+
+    if (light.shadow.x != SHADOWS_NONE) {
+        float angle = (float(texel.x) + 0.5) / float(width) * 2.0 * PI - PI;
+        vec2 direction = vec2(cos(angle), sin(angle));
+
+        for (uint i = 0; i < constants.segment_count; i++) {
+            vec2 start = segments.segments[i].xy - light.position.xy;
+            vec2 edge = segments.segments[i].zw - segments.segments[i].xy;
+
+            float denominator = direction.x * edge.y - direction.y * edge.x;
+            if (abs(denominator) < 1e-6) {
+                continue;
+            }
+
+            // Distance along the ray and position along the edge where they cross
+            float t = (start.x * edge.y - start.y * edge.x) / denominator;
+            float u = (start.x * direction.y - start.y * direction.x) / denominator;
+
+            if (t >= 0.0 && u >= 0.0 && u <= 1.0) {
+                nearest = min(nearest, t);
+            }
+        }
+    }
+
+    imageStore(shadow_map, texel, vec4(nearest));
+}
@@ -0,0 +1,22 @@
+#version 450
+
+// Example material shader. Blends the sprite towards a flat colour, e.g. for hit flashes.
+
+layout(location = 0) in vec2 TexCoords;
+layout(location = 1) in vec4 Color;
+
+layout(location = 0) out vec4 color;
+
+layout(set = 1, binding = 0) uniform sampler2D image;
+
+// Material parameters, set by member name on the material
+layout(set = 2, binding = 0) uniform Params {
+    vec4 flash_color;
+    float amount;
+} params;
+
+void main()
+{
+    vec4 texel = Color * texture(image, TexCoords);
+    color = vec4(mix(texel.rgb, params.flash_color.rgb, params.amount), texel.a);
+}
@@ -0,0 +1,15 @@
+#version 450
+
+layout(location = 0) in vec4 Color;
+layout(location = 1) in vec2 Coverage;
+
+layout(location = 0) out vec4 color;
+
+void main()
+{
+    // Fades out over about a pixel past the edge, whatever the zoom
+    float across = abs(Coverage.x);
+    float alpha = clamp((Coverage.y - across) / max(fwidth(across), 1e-6) + 0.5, 0.0, 1.0);
+
+    color = vec4(Color.rgb, Color.a * alpha);
+}
@@ -0,0 +1,116 @@
+#version 450
+
+#include "include/lights.glsl"
+
+// Offset that keeps surfaces from shadowing themselves, in world units
+#define SHADOW_BIAS 0.01
+// Samples taken on each side of the fragment for soft shadows
+#define SHADOW_SAMPLES 4
+// Widest soft shadow filter, in shadow map texels
+#define MAX_SHADOW_SPREAD 32.0
+
+layout(location = 0) in vec2 TexCoords;
+layout(location = 1) in vec4 Color;
+layout(location = 2) in vec2 WorldPosition;
+layout(location = 3) in vec2 Tangent;
+layout(location = 4) in vec2 Bitangent;
+
+layout(location = 0) out vec4 color;
+
+layout(set = 1, binding = 0) uniform sampler2D image;
+layout(set = 1, binding = 1) uniform sampler2D normal_map;
+
+layout(set = 2, binding = 0) uniform Lights {
+    vec4 ambient;
+    uint count;
+    Light lights[MAX_LIGHTS];
+} lights;
+
+layout(set = 2, binding = 1, r32f) uniform readonly image2D shadow_map;
+
+float occluder_distance(int row, float column)
+{
+    int width = imageSize(shadow_map).x;
+    int x = int(floor(column)) % width;
+    return imageLoad(shadow_map, ivec2(x < 0 ? x + width : x, row)).r;
+}
+
+// 1.0 when the fragment is fully lit, 0.0 when it's fully in shadow
+float shadow(int row, Light l, vec2 to_fragment, float distance)
+{
+    if (l.shadow.x == SHADOWS_NONE) {
+        return 1.0;
+    }
+
+    float width = float(imageSize(shadow_map).x);
+    float column = (atan(to_fragment.y, to_fragment.x) + PI) / (2.0 * PI) * width;
+
+    if (l.shadow.x == SHADOWS_HARD) {
+        return distance <= occluder_distance(row, column) + SHADOW_BIAS ? 1.0 : 0.0;
+    }
+
+    // Shadow map texels covered by one world unit at the fragment's distance
+    float texels_per_unit = width / (2.0 * PI * max(distance, SHADOW_BIAS));
+
+    // Find the average distance of the occluders around the fragment...
+    float search = clamp(l.shadow.y * texels_per_unit, 1.0, MAX_SHADOW_SPREAD);
+    float blockers = 0.0;
+    float blocker_count = 0.0;
+    for (int i = -SHADOW_SAMPLES; i <= SHADOW_SAMPLES; i++) {
+        float occluder = occluder_distance(row, column + search * float(i) / SHADOW_SAMPLES);
+        if (occluder + SHADOW_BIAS < distance) {
+            blockers += occluder;
+            blocker_count += 1.0;
+        }
+    }
+
+    if (blocker_count == 0.0) {
+        return 1.0;
+    }
+
+    // ...then widen the filter the further the fragment is behind them
+    float blocker = blockers / blocker_count;
+    float penumbra = l.shadow.y * (distance - blocker) / max(blocker, SHADOW_BIAS);
+    float spread = clamp(penumbra * texels_per_unit, 1.0, MAX_SHADOW_SPREAD);
+
+    float lit = 0.0;
+    for (int i = -SHADOW_SAMPLES; i <= SHADOW_SAMPLES; i++) {
+        float occluder = occluder_distance(row, column + spread * float(i) / SHADOW_SAMPLES);
+        lit += distance <= occluder + SHADOW_BIAS ? 1.0 : 0.0;
+    }
+
+    return lit / float(SHADOW_SAMPLES * 2 + 1);
+}
+
+void main()
+{
+    vec4 texel = Color * texture(image, TexCoords);
+
+    vec3 normal = texture(normal_map, TexCoords).xyz * 2.0 - 1.0;
+    normal = normalize(vec3(Tangent * normal.x + Bitangent * normal.y, normal.z));
+
+    vec3 light = lights.ambient.rgb;
+    for (uint i = 0; i < min(lights.count, MAX_LIGHTS); i++) {
+        Light l = lights.lights[i];
+
+        vec2 offset = l.position.xy - WorldPosition;
+        float distance = length(offset);
+        if (distance >= l.position.w) {
+            continue;
+        }
+
+        float attenuation = pow(1.0 - distance / l.position.w, l.falloff.x);
+
+        vec2 to_fragment = distance > 0.0 ? -offset / distance : l.cone.xy;
+        float spot = smoothstep(l.cone.w, l.cone.z, dot(to_fragment, l.cone.xy));
+
+        vec3 direction = normalize(vec3(offset, l.position.z));
+        float diffuse = max(dot(normal, direction), 0.0);
+
+        float visibility = shadow(int(i), l, to_fragment, distance);
+
+        light += l.color.rgb * l.color.a * attenuation * spot * diffuse * visibility;
+    }
+
+    color = vec4(texel.rgb * light, texel.a);
+}
@@ -0,0 +1,19 @@
+// Light layout shared by the lit sprite shader and the shadow map pass
+
+// Must match MAX_LIGHTS in terra/data
+#define MAX_LIGHTS 32
+
+// Must match the shadow modes written by LightData
+#define SHADOWS_NONE 0.0
+#define SHADOWS_HARD 1.0
+#define SHADOWS_SOFT 2.0
+
+#define PI 3.14159265359
+
+struct Light {
+    vec4 position; // xy position, z height, w radius
+    vec4 color;    // rgb color, a intensity
+    vec4 cone;     // xy direction, z cosine of the inner angle, w cosine of the outer angle
+    vec4 falloff;  // x falloff exponent
+    vec4 shadow;   // x shadow mode, y soft shadow size
+};
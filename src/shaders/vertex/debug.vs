@@ -0,0 +1,18 @@
+#version 450
+
+// Debug shapes are built in world space, so only the camera is applied
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 Color;
+
+layout(set = 0, binding = 0) uniform PerCamera {
+    mat4 projection;
+    mat4 view;
+} camera;
+
+void main()
+{
+    Color = color;
+    gl_Position = camera.projection * camera.view * vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,28 @@
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 color;
+
+// Same outputs as the sprite vertex shader so the sprite fragment shader can be reused
+layout(location = 0) out vec2 TexCoords;
+layout(location = 1) out vec4 Color;
+
+layout(set = 0, binding = 0) uniform PerCamera {
+    mat4 projection;
+    mat4 view;
+} camera;
+
+// Mesh transform and tint
+layout(push_constant) uniform PerObject {
+    mat4 model;
+    vec4 color;
+    vec4 uv; // <vec2 scale, vec2 offset>
+} object;
+
+void main()
+{
+    TexCoords = uv * object.uv.xy + object.uv.zw;
+    Color = color * object.color;
+    gl_Position = camera.projection * camera.view * object.model * vec4(position, 0.0, 1.0);
+}
@@ -0,0 +1,34 @@
+#version 450
+
+// Quad corner shared with the sprite program <vec2 position, vec2 texCoords>
+layout (location = 0) in vec4 vertex;
+
+// Per particle instance data
+layout (location = 1) in vec2 position;
+layout (location = 2) in float rotation;
+layout (location = 3) in float size;
+layout (location = 4) in vec4 color;
+
+layout(location = 0) out vec2 TexCoords;
+layout(location = 1) out vec4 Color;
+
+layout(set = 0, binding = 0) uniform PerCamera {
+    mat4 projection;
+    mat4 view;
+}camera;
+
+// Emitter transform for local space particles, identity for world space particles
+layout(push_constant) uniform PerEmitter {
+    mat4 model;
+}emitter;
+
+void main()
+{
+    float angle = radians(rotation);
+    mat2 rotate = mat2(cos(angle), sin(angle), -sin(angle), cos(angle));
+    vec2 corner = rotate * (vertex.xy * size) + position;
+
+    TexCoords = vertex.zw;
+    Color = color;
+    gl_Position = camera.projection * camera.view * emitter.model * vec4(corner, 0.0, 1.0);
+}
@@ -0,0 +1,25 @@
+#version 450
+
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 color;
+layout(location = 2) in vec2 coverage; // x distance across the edge, y where the edge is
+
+layout(location = 0) out vec4 Color;
+layout(location = 1) out vec2 Coverage;
+
+layout(set = 0, binding = 0) uniform PerCamera {
+    mat4 projection;
+    mat4 view;
+} camera;
+
+// Shape transform
+layout(push_constant) uniform PerObject {
+    mat4 model;
+} object;
+
+void main()
+{
+    Color = color;
+    Coverage = coverage;
+    gl_Position = camera.projection * camera.view * object.model * vec4(position, 0.0, 1.0);
+}
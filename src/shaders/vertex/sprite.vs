@@ -17,12 +17,13 @@ layout(set = 0, binding = 0) uniform PerCamera {
 layout(push_constant) uniform PerObject {
     mat4 model;
     vec4 color;
+    vec4 uv; // <vec2 scale, vec2 offset>
 }object;
 
 
 void main()
 {
-    TexCoords = vertex.zw;
+    TexCoords = vertex.zw * object.uv.xy + object.uv.zw;
     Color = object.color;
     gl_Position = camera.projection * camera.view * object.model * vec4(vertex.xy, 0.0, 1.0);
     // gl_position is used to store the position of the current vertex
@@ -0,0 +1,35 @@
+#version 450
+
+// Same inputs as sprite.vs, plus what the lighting needs in world space
+layout (location = 0) in vec4 vertex; // <vec2 position, vec2 texCoords>
+
+layout(location = 0) out vec2 TexCoords;
+layout(location = 1) out vec4 Color;
+layout(location = 2) out vec2 WorldPosition;
+// Sprite axes in world space, used to rotate normal map samples with the sprite
+layout(location = 3) out vec2 Tangent;
+layout(location = 4) out vec2 Bitangent;
+
+layout(set = 0, binding = 0) uniform PerCamera {
+    mat4 projection;
+    mat4 view;
+}camera;
+
+layout(push_constant) uniform PerObject {
+    mat4 model;
+    vec4 color;
+    vec4 uv; // <vec2 scale, vec2 offset>
+}object;
+
+void main()
+{
+    vec4 world = object.model * vec4(vertex.xy, 0.0, 1.0);
+
+    TexCoords = vertex.zw * object.uv.xy + object.uv.zw;
+    Color = object.color;
+    WorldPosition = world.xy;
+    // Flipped textures flip their normal map samples too
+    Tangent = normalize(mat2(object.model) * vec2(sign(object.uv.x), 0.0));
+    Bitangent = normalize(mat2(object.model) * vec2(0.0, sign(object.uv.y)));
+    gl_Position = camera.projection * camera.view * world;
+}
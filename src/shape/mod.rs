@@ -0,0 +1,197 @@
+pub mod tessellate;
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use lyon::math::{point, Box2D};
+use lyon::path::{builder::BorderRadii, Polygon, Winding};
+
+use crate::transform::Transform;
+
+pub use lyon::path::{FillRule, Path};
+pub use lyon::tessellation::{LineCap, LineJoin};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stroke {
+    pub color: [f32; 4],
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    // Alternating dash and gap lengths, a solid line when empty
+    pub dashes: Vec<f32>,
+    pub dash_offset: f32,
+}
+
+impl Stroke {
+    pub fn new(color: [f32; 4], width: f32) -> Stroke {
+        Stroke {
+            color,
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            dashes: vec![],
+            dash_offset: 0.0,
+        }
+    }
+
+    pub fn dashed(mut self, dashes: Vec<f32>) -> Stroke {
+        self.dashes = dashes;
+        self
+    }
+}
+
+// A vector path in local space that is filled and/or stroked. Paths are built with lyon's path
+// builder, which covers lines, quadratic and cubic Béziers and arcs.
+pub struct Shape {
+    id: u64,
+    transform: Transform,
+    path: Path,
+    fill: Option<[f32; 4]>,
+    fill_rule: FillRule,
+    stroke: Option<Stroke>,
+    feather: f32,
+    sorting_order: i32,
+    revision: u64,
+}
+
+impl Shape {
+    pub fn new(path: Path) -> Shape {
+        let mut hasher = DefaultHasher::new();
+        uuid::Uuid::new_v4().to_string().hash(&mut hasher);
+
+        Shape {
+            id: hasher.finish(),
+            transform: Transform::new(),
+            path,
+            fill: Some([1.0; 4]),
+            fill_rule: FillRule::NonZero,
+            stroke: None,
+            feather: 0.02,
+            sorting_order: 0,
+            revision: 0,
+        }
+    }
+
+    pub fn rect(width: f32, height: f32) -> Shape {
+        Shape::rounded_rect(width, height, 0.0)
+    }
+
+    pub fn rounded_rect(width: f32, height: f32, radius: f32) -> Shape {
+        let (x, y) = (width * 0.5, height * 0.5);
+        let mut builder = Path::builder();
+        builder.add_rounded_rectangle(
+            &Box2D::new(point(-x, -y), point(x, y)),
+            &BorderRadii::new(radius.clamp(0.0, x.min(y))),
+            Winding::Positive,
+        );
+
+        Shape::new(builder.build())
+    }
+
+    pub fn circle(radius: f32) -> Shape {
+        Shape::ellipse(radius, radius)
+    }
+
+    pub fn ellipse(radius_x: f32, radius_y: f32) -> Shape {
+        let mut builder = Path::builder();
+        builder.add_ellipse(
+            point(0.0, 0.0),
+            lyon::math::vector(radius_x, radius_y),
+            lyon::math::Angle::zero(),
+            Winding::Positive,
+        );
+
+        Shape::new(builder.build())
+    }
+
+    // Open polylines are only stroked, so they start without a fill
+    pub fn polygon(points: &[[f32; 2]], closed: bool) -> Shape {
+        let points: Vec<_> = points.iter().map(|p| point(p[0], p[1])).collect();
+        let mut builder = Path::builder();
+        builder.add_polygon(Polygon {
+            points: &points,
+            closed,
+        });
+
+        let mut shape = Shape::new(builder.build());
+        if !closed {
+            shape.fill = None;
+            shape.stroke = Some(Stroke::new([1.0; 4], 0.05));
+        }
+        shape
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn transform_mut(&mut self) -> &mut Transform {
+        &mut self.transform
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn set_path(&mut self, path: Path) {
+        self.path = path;
+        self.revision += 1;
+    }
+
+    pub fn fill(&self) -> Option<[f32; 4]> {
+        self.fill
+    }
+
+    pub fn set_fill(&mut self, fill: Option<[f32; 4]>) {
+        self.fill = fill;
+        self.revision += 1;
+    }
+
+    pub fn fill_rule(&self) -> FillRule {
+        self.fill_rule
+    }
+
+    pub fn set_fill_rule(&mut self, fill_rule: FillRule) {
+        self.fill_rule = fill_rule;
+        self.revision += 1;
+    }
+
+    pub fn stroke(&self) -> Option<&Stroke> {
+        self.stroke.as_ref()
+    }
+
+    pub fn set_stroke(&mut self, stroke: Option<Stroke>) {
+        self.stroke = stroke;
+        self.revision += 1;
+    }
+
+    // Width in local units of the fringe that anti-aliases the edges, 0.0 disables it. The
+    // fade itself is always about a pixel wide, the fringe only has to be at least that big.
+    pub fn feather(&self) -> f32 {
+        self.feather
+    }
+
+    pub fn set_feather(&mut self, feather: f32) {
+        self.feather = feather.max(0.0);
+        self.revision += 1;
+    }
+
+    // Lower orders are drawn first. Within the same order sprites are drawn first, then meshes
+    // and then shapes.
+    pub fn sorting_order(&self) -> i32 {
+        self.sorting_order
+    }
+
+    pub fn set_sorting_order(&mut self, sorting_order: i32) {
+        self.sorting_order = sorting_order;
+    }
+
+    // Bumped on every change to the geometry so the renderer knows when to tessellate again
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+}
@@ -0,0 +1,420 @@
+use lyon::algorithms::hit_test::hit_test_path;
+use lyon::math::{vector, Point};
+use lyon::path::{iterator::PathIterator, EndpointId, IdEvent, Path, PathEvent, Side};
+use lyon::tessellation::{
+    BuffersBuilder, FillOptions, FillTessellator, FillVertex, StrokeOptions, StrokeTessellator,
+    StrokeVertex, VertexBuffers, VertexSource,
+};
+
+use crate::terra::{
+    data::ShapeVertex,
+    error::{TerraError, TerraResult},
+};
+
+use super::{Shape, Stroke};
+
+// Maximum distance between curves and the line segments approximating them, in local units
+const TOLERANCE: f32 = 0.001;
+
+// Coverage for vertices that are always fully opaque
+const SOLID: [f32; 2] = [0.0, 1.0];
+
+// Builds the triangles for a shape's fill, its anti-aliasing fringe and its stroke, in draw order.
+// Coverage is [distance across the edge, distance at which the edge is] and is turned into alpha
+// by the fragment shader.
+pub fn tessellate(shape: &Shape) -> TerraResult<(Vec<ShapeVertex>, Vec<u32>)> {
+    let mut geometry: VertexBuffers<ShapeVertex, u32> = VertexBuffers::new();
+    let feather = shape.feather();
+
+    if let Some(color) = shape.fill() {
+        let options = FillOptions::tolerance(TOLERANCE).with_fill_rule(shape.fill_rule());
+        FillTessellator::new()
+            .tessellate_path(
+                shape.path(),
+                &options,
+                &mut BuffersBuilder::new(&mut geometry, |vertex: FillVertex| {
+                    shape_vertex(vertex.position(), color, SOLID)
+                }),
+            )
+            .map_err(TerraError::tessellation("fill"))?;
+
+        // The fringe only grows outwards, whichever way the outline winds
+        if feather > 0.0 {
+            let outward = outward_sides(shape);
+            StrokeTessellator::new()
+                .tessellate_path(
+                    shape.path(),
+                    &StrokeOptions::tolerance(TOLERANCE),
+                    &mut BuffersBuilder::new(&mut geometry, |vertex: StrokeVertex| {
+                        let on_path = vertex.position_on_path();
+                        let endpoint = match vertex.source() {
+                            VertexSource::Endpoint { id } => id,
+                            VertexSource::Edge { from, .. } => from,
+                        };
+                        let sub_path = outward.partition_point(|(first, _)| first.0 <= endpoint.0);
+
+                        if sub_path > 0 && vertex.side() == outward[sub_path - 1].1 {
+                            shape_vertex(on_path + vertex.normal() * feather, color, [1.0, 0.0])
+                        } else {
+                            shape_vertex(on_path, color, [0.0, 0.0])
+                        }
+                    }),
+                )
+                .map_err(TerraError::tessellation("fringe"))?;
+        }
+    }
+
+    if let Some(stroke) = shape.stroke() {
+        let path = if stroke.dashes.is_empty() {
+            shape.path().clone()
+        } else {
+            dash(shape.path(), &stroke.dashes, stroke.dash_offset)
+        };
+
+        tessellate_stroke(&path, stroke, feather, &mut geometry)?;
+    }
+
+    Ok((geometry.vertices, geometry.indices))
+}
+
+// The first endpoint of every sub-path and the side of it that faces away from the fill. Each
+// sub-path is hit tested once, just off the middle of its longest edge, so holes get their
+// fringe on the right side whatever the fill rule and winding.
+fn outward_sides(shape: &Shape) -> Vec<(EndpointId, Side)> {
+    let path = shape.path();
+    let mut firsts = Vec::new();
+    for event in path.id_iter() {
+        if let IdEvent::Begin { at } = event {
+            firsts.push(at);
+        }
+    }
+
+    let mut edges = Vec::with_capacity(firsts.len());
+    let mut longest: Option<(Point, Point)> = None;
+    for event in path.iter().flattened(TOLERANCE) {
+        let (from, to) = match event {
+            PathEvent::Line { from, to } => (from, to),
+            PathEvent::End { last, first, .. } => (last, first),
+            _ => continue,
+        };
+
+        if longest.is_none_or(|(a, b)| (to - from).square_length() > (b - a).square_length()) {
+            longest = Some((from, to));
+        }
+        if let PathEvent::End { .. } = event {
+            edges.push(longest.take());
+        }
+    }
+
+    firsts
+        .into_iter()
+        .zip(edges)
+        .map(|(first, edge)| {
+            // Side::Positive is to the left of the direction of travel with y up
+            let positive_inside = edge.is_some_and(|(from, to)| {
+                if from == to {
+                    return false;
+                }
+                let direction = (to - from).normalize();
+                let probe =
+                    from.lerp(to, 0.5) + vector(-direction.y, direction.x) * TOLERANCE * 4.0;
+                hit_test_path(&probe, path.iter(), shape.fill_rule(), TOLERANCE)
+            });
+
+            if positive_inside {
+                (first, Side::Negative)
+            } else {
+                (first, Side::Positive)
+            }
+        })
+        .collect()
+}
+
+// The stroke is widened by the feather on both sides and faded out past its real width
+fn tessellate_stroke(
+    path: &Path,
+    stroke: &Stroke,
+    feather: f32,
+    geometry: &mut VertexBuffers<ShapeVertex, u32>,
+) -> TerraResult<()> {
+    let half_width = stroke.width * 0.5;
+    let edge = half_width / (half_width + feather);
+
+    let options = StrokeOptions::tolerance(TOLERANCE)
+        .with_line_width(stroke.width)
+        .with_line_join(stroke.join)
+        .with_line_cap(stroke.cap);
+
+    StrokeTessellator::new()
+        .tessellate_path(
+            path,
+            &options,
+            &mut BuffersBuilder::new(geometry, |vertex: StrokeVertex| {
+                let position = vertex.position_on_path() + vertex.normal() * (half_width + feather);
+                let across = match vertex.side() {
+                    Side::Positive => 1.0,
+                    Side::Negative => -1.0,
+                };
+
+                if feather > 0.0 {
+                    shape_vertex(position, stroke.color, [across, edge])
+                } else {
+                    shape_vertex(position, stroke.color, SOLID)
+                }
+            }),
+        )
+        .map_err(TerraError::tessellation("stroke"))?;
+
+    Ok(())
+}
+
+// Splits the path into dashes. The pattern restarts on every sub-path and, like SVG, patterns
+// with an odd number of entries are repeated to get an even one.
+pub fn dash(path: &Path, dashes: &[f32], offset: f32) -> Path {
+    let dashes = match dashes.len() % 2 {
+        0 => dashes.to_vec(),
+        _ => dashes.repeat(2),
+    };
+
+    let length: f32 = dashes.iter().map(|dash| dash.max(0.0)).sum();
+    if length <= 0.0 {
+        return path.clone();
+    }
+
+    let mut dasher = Dasher {
+        builder: Path::builder(),
+        dashes: &dashes,
+        offset: offset.rem_euclid(length),
+        index: 0,
+        remaining: 0.0,
+        drawing: false,
+    };
+
+    for event in path.iter().flattened(TOLERANCE) {
+        match event {
+            PathEvent::Begin { .. } => dasher.restart(),
+            PathEvent::Line { from, to } => dasher.segment(from, to),
+            PathEvent::End { last, first, close } => {
+                if close {
+                    dasher.segment(last, first);
+                }
+                dasher.stop();
+            }
+            // Flattened paths only contain lines
+            _ => (),
+        }
+    }
+
+    dasher.builder.build()
+}
+
+struct Dasher<'a> {
+    builder: lyon::path::path::Builder,
+    dashes: &'a [f32],
+    offset: f32,
+    index: usize,
+    remaining: f32,
+    drawing: bool,
+}
+
+impl Dasher<'_> {
+    fn restart(&mut self) {
+        self.stop();
+        self.index = 0;
+        self.remaining = self.dashes[0].max(0.0);
+
+        let mut offset = self.offset;
+        while offset > self.remaining {
+            offset -= self.remaining;
+            self.advance();
+        }
+        self.remaining -= offset;
+    }
+
+    fn segment(&mut self, from: Point, to: Point) {
+        let length = (to - from).length();
+        let mut travelled = 0.0;
+
+        while travelled < length {
+            let step = self.remaining.min(length - travelled);
+            let start = from.lerp(to, travelled / length);
+            travelled = if step >= length - travelled {
+                length
+            } else {
+                travelled + step
+            };
+            let end = from.lerp(to, travelled / length);
+
+            // Even entries are dashes, odd ones are gaps
+            if self.index.is_multiple_of(2) {
+                if !self.drawing {
+                    self.builder.begin(start);
+                    self.drawing = true;
+                }
+                self.builder.line_to(end);
+            }
+
+            self.remaining -= step;
+            if self.remaining <= 0.0 {
+                self.stop();
+                self.advance();
+            }
+        }
+    }
+
+    fn advance(&mut self) {
+        self.index = (self.index + 1) % self.dashes.len();
+        self.remaining = self.dashes[self.index].max(0.0);
+    }
+
+    fn stop(&mut self) {
+        if self.drawing {
+            self.builder.end(false);
+            self.drawing = false;
+        }
+    }
+}
+
+fn shape_vertex(position: Point, color: [f32; 4], coverage: [f32; 2]) -> ShapeVertex {
+    ShapeVertex {
+        position: [position.x, position.y],
+        color,
+        coverage,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use lyon::math::point;
+    use lyon::path::{FillRule, Polygon};
+
+    use super::*;
+
+    fn line(length: f32) -> Path {
+        let mut builder = Path::builder();
+        builder.begin(point(0.0, 0.0));
+        builder.line_to(point(length, 0.0));
+        builder.end(false);
+        builder.build()
+    }
+
+    // The [start, end] x of every dash along a horizontal line
+    fn spans(path: &Path) -> Vec<[f32; 2]> {
+        let mut spans = Vec::new();
+        for event in path.iter() {
+            if let PathEvent::End { last, first, .. } = event {
+                spans.push([first.x, last.x]);
+            }
+        }
+        spans
+    }
+
+    fn assert_spans(path: &Path, expected: &[[f32; 2]]) {
+        let spans = spans(path);
+        assert_eq!(spans.len(), expected.len(), "{spans:?}");
+        for (span, expected) in spans.iter().zip(expected) {
+            assert!((span[0] - expected[0]).abs() < 1e-4, "{spans:?}");
+            assert!((span[1] - expected[1]).abs() < 1e-4, "{spans:?}");
+        }
+    }
+
+    #[test]
+    fn dash_alternates_dashes_and_gaps() {
+        let path = dash(&line(10.0), &[2.0, 3.0], 0.0);
+        assert_spans(&path, &[[0.0, 2.0], [5.0, 7.0]]);
+    }
+
+    #[test]
+    fn dash_offset_shifts_the_pattern() {
+        let path = dash(&line(10.0), &[2.0, 3.0], 1.0);
+        assert_spans(&path, &[[0.0, 1.0], [4.0, 6.0], [9.0, 10.0]]);
+
+        // Negative offsets wrap around the pattern
+        let path = dash(&line(10.0), &[2.0, 3.0], -4.0);
+        assert_spans(&path, &[[0.0, 1.0], [4.0, 6.0], [9.0, 10.0]]);
+    }
+
+    #[test]
+    fn dash_repeats_odd_patterns() {
+        let path = dash(&line(10.0), &[2.0], 0.0);
+        assert_spans(&path, &[[0.0, 2.0], [4.0, 6.0], [8.0, 10.0]]);
+    }
+
+    #[test]
+    fn dash_keeps_paths_without_length() {
+        let path = dash(&line(10.0), &[0.0, -1.0], 0.0);
+        assert_spans(&path, &[[0.0, 10.0]]);
+    }
+
+    #[test]
+    fn tessellate_fills_without_feather() {
+        let mut shape = Shape::rect(2.0, 2.0);
+        shape.set_feather(0.0);
+        let (vertices, indices) = tessellate(&shape).unwrap();
+
+        assert_eq!(indices.len(), 6);
+        assert!(indices.iter().all(|&i| (i as usize) < vertices.len()));
+        assert!(vertices.iter().all(|v| v.coverage == SOLID));
+    }
+
+    // Fringe vertices that fade out have to be outside the rectangle whichever way it winds
+    #[test]
+    fn tessellate_grows_the_fringe_outwards() {
+        let square = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]];
+        let mut reversed = square;
+        reversed.reverse();
+
+        for points in [square, reversed] {
+            let mut shape = Shape::polygon(&points, true);
+            shape.set_feather(0.1);
+            let (vertices, _) = tessellate(&shape).unwrap();
+
+            let fringe: Vec<_> = vertices
+                .iter()
+                .filter(|v| v.coverage == [1.0, 0.0])
+                .collect();
+            assert!(!fringe.is_empty());
+            for vertex in fringe {
+                let [x, y] = vertex.position;
+                assert!(x.abs().max(y.abs()) > 1.05, "{:?}", vertex.position);
+            }
+        }
+    }
+
+    // The fringe of a hole grows into the hole, not into the fill around it
+    #[test]
+    fn tessellate_grows_the_fringe_into_holes() {
+        for fill_rule in [FillRule::EvenOdd, FillRule::NonZero] {
+            let mut builder = Path::builder();
+            builder.add_polygon(Polygon {
+                points: &[
+                    point(-2.0, -2.0),
+                    point(2.0, -2.0),
+                    point(2.0, 2.0),
+                    point(-2.0, 2.0),
+                ],
+                closed: true,
+            });
+            builder.add_polygon(Polygon {
+                points: &[
+                    point(-1.0, -1.0),
+                    point(-1.0, 1.0),
+                    point(1.0, 1.0),
+                    point(1.0, -1.0),
+                ],
+                closed: true,
+            });
+
+            let mut shape = Shape::new(builder.build());
+            shape.set_fill_rule(fill_rule);
+            shape.set_feather(0.1);
+            let (vertices, _) = tessellate(&shape).unwrap();
+
+            for vertex in vertices.iter().filter(|v| v.coverage == [1.0, 0.0]) {
+                let [x, y] = vertex.position;
+                let distance = x.abs().max(y.abs());
+                assert!(!(0.95..=2.05).contains(&distance), "{:?}", vertex.position);
+            }
+        }
+    }
+}
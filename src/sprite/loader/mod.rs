@@ -1,14 +1,16 @@
-use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
-    fs,
-    hash::{Hash, Hasher},
-    path::{Path, PathBuf},
-};
+use std::{collections::HashMap, fs, io, path::Path, time::SystemTime};
 
-use super::Sprite;
+use uuid::Uuid;
+
+use super::{meta::SpriteMeta, Sprite};
+use crate::vfs;
+
+const EXTENSIONS: [&str; 8] = ["png", "jpeg", "jpg", "tiff", "bmp", "tga", "ktx2", "dds"];
 
 pub struct SpriteLoader {
     sprites: HashMap<u64, Sprite>,
+    paths: HashMap<String, u64>,
+    guids: HashMap<Uuid, u64>,
 }
 
 impl SpriteLoader {
@@ -20,52 +22,251 @@ impl SpriteLoader {
         }
     }
 
+    pub fn get_mut(&mut self, id: &u64) -> Option<&mut Sprite> {
+        self.sprites.get_mut(id)
+    }
+
+    // By logical path, such as `sprites://hero.png`
+    pub fn get_by_path(&self, path: &str) -> Option<Sprite> {
+        self.paths.get(path).and_then(|id| self.get(id))
+    }
+
+    pub fn get_by_guid(&self, guid: &Uuid) -> Option<Sprite> {
+        self.guids.get(guid).and_then(|id| self.get(id))
+    }
+
     pub fn load() -> SpriteLoader {
-        let mut sprites: HashMap<u64, Sprite> = HashMap::new();
-
-        fs::read_dir("./src/assets/sprites")
-            .unwrap()
-            .for_each(|entry| {
-                let path = entry.unwrap().path();
-                let ext = path.extension().unwrap().to_str().unwrap();
-                match ext {
-                    "png" => Self::add_sprite(&mut sprites, &path),
-                    "jpeg" => Self::add_sprite(&mut sprites, &path),
-                    "jpg" => Self::add_sprite(&mut sprites, &path),
-                    "tiff" => Self::add_sprite(&mut sprites, &path),
-                    "bmp" => Self::add_sprite(&mut sprites, &path),
-                    "tga" => Self::add_sprite(&mut sprites, &path),
-                    _ => (),
+        SpriteLoader::load_from("sprites://")
+    }
+
+    // Every image under a logical directory, including subdirectories. Each one is set up from
+    // its meta file, which is created with a new guid the first time the image is seen.
+    pub fn load_from(dir: &str) -> SpriteLoader {
+        let mut loader = SpriteLoader {
+            sprites: HashMap::new(),
+            paths: HashMap::new(),
+            guids: HashMap::new(),
+        };
+
+        let paths = vfs::list(dir).unwrap_or_else(|e| {
+            eprintln!("Failed to list sprites in {dir}: {e}");
+            vec![]
+        });
+
+        let found: Vec<(String, Option<SpriteMeta>)> = paths
+            .into_iter()
+            .filter(|path| {
+                let extension = Path::new(path)
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .map(|extension| extension.to_ascii_lowercase());
+
+                extension.is_some_and(|extension| EXTENSIONS.contains(&extension.as_str()))
+            })
+            .filter_map(|path| {
+                let meta = read_meta(&path)?;
+                Some((path, meta))
+            })
+            .collect();
+
+        let copies = find_copies(&found);
+        for (index, (path, meta)) in found.into_iter().enumerate() {
+            loader.add_sprite(&path, meta, copies.get(&index).copied());
+        }
+
+        loader
+    }
+
+    // Copies get a new guid, written to their meta file when `write_copy` is set
+    fn add_sprite(&mut self, path: &str, meta: Option<SpriteMeta>, write_copy: Option<bool>) {
+        let meta_path = format!("{path}.meta");
+
+        let sprite = match (meta, write_copy) {
+            (Some(meta), None) => Sprite::from_meta(path.into(), &meta),
+            (Some(mut meta), Some(write)) => {
+                meta.guid = Uuid::new_v4();
+                if write {
+                    write_meta(&meta_path, &meta);
                 }
-            });
 
-        SpriteLoader { sprites }
+                Sprite::from_meta(path.into(), &meta)
+            }
+            (None, _) => {
+                let meta = SpriteMeta::new(Uuid::new_v4());
+
+                // Without a meta file the guid changes on every run, so anything referring to
+                // the sprite by guid won't find it next time
+                write_meta(&meta_path, &meta);
+
+                Sprite::from_meta(path.into(), &meta)
+            }
+        };
+
+        self.paths.insert(path.to_owned(), sprite.id());
+        self.guids.insert(sprite.guid(), sprite.id());
+        self.sprites.insert(sprite.id(), sprite);
+    }
+}
+
+// The sprite's meta file, None inside if it doesn't have one yet. Sprites with a meta file that
+// can't be read are skipped, rather than given a new guid that would overwrite the one in it.
+fn read_meta(path: &str) -> Option<Option<SpriteMeta>> {
+    let meta_path = format!("{path}.meta");
+
+    match vfs::read(&meta_path) {
+        Ok(bytes) => match SpriteMeta::parse(&meta_path, &String::from_utf8_lossy(&bytes)) {
+            Ok(meta) => Some(Some(meta)),
+            Err(e) => {
+                eprintln!("Skipping {path}: {e}");
+                None
+            }
+        },
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Some(None),
+        Err(e) => {
+            eprintln!("Skipping {path}: failed to read {meta_path}: {e}");
+            None
+        }
+    }
+}
+
+// A meta file copied along with its image keeps the settings but shares the original's guid.
+// The sprite whose meta file was modified first is taken as the original and keeps the guid,
+// the copies get a new one written to their meta file. When the files can't be told apart that
+// way, nothing is written: the first path keeps the guid and the others get one for this run
+// only. Returns whether each copy, by index, should have its new guid written.
+fn find_copies(found: &[(String, Option<SpriteMeta>)]) -> HashMap<usize, bool> {
+    let mut shared: HashMap<Uuid, Vec<usize>> = HashMap::new();
+    for (index, (_, meta)) in found.iter().enumerate() {
+        if let Some(meta) = meta {
+            shared.entry(meta.guid).or_default().push(index);
+        }
+    }
+
+    let mut copies = HashMap::new();
+    for (guid, sprites) in shared.into_iter().filter(|(_, sprites)| sprites.len() > 1) {
+        let modified: Option<Vec<SystemTime>> = sprites
+            .iter()
+            .map(|&index| modified(&format!("{}.meta", found[index].0)))
+            .collect();
+        let oldest = modified.and_then(|modified| {
+            let first = modified.iter().min()?;
+            match modified.iter().filter(|time| *time == first).count() {
+                1 => Some(sprites[modified.iter().position(|time| time == first)?]),
+                _ => None,
+            }
+        });
+
+        let paths: Vec<&str> = sprites
+            .iter()
+            .map(|&index| found[index].0.as_str())
+            .collect();
+        let original = match oldest {
+            Some(original) => {
+                eprintln!(
+                    "{} share the guid {guid}, {} has the oldest meta file and keeps it",
+                    paths.join(", "),
+                    found[original].0
+                );
+                original
+            }
+            None => {
+                eprintln!(
+                    "{} share the guid {guid} and it can't be told which is the original, {} \
+                     keeps it until the copies' meta files are given new guids",
+                    paths.join(", "),
+                    paths[0]
+                );
+                sprites[0]
+            }
+        };
+
+        for index in sprites.into_iter().filter(|index| *index != original) {
+            copies.insert(index, oldest.is_some());
+        }
     }
 
-    fn add_sprite(sprites: &mut HashMap<u64, Sprite>, path: &Path) {
-        let mut filepath = path.parent().unwrap().to_str().unwrap().to_owned();
-        let file_name = path.file_name().unwrap().to_str().unwrap();
-        filepath.push_str("/");
-        filepath.push_str(path.file_name().unwrap().to_str().unwrap());
-
-        let mut full_path = std::env::current_dir().unwrap();
-        full_path.push(PathBuf::from("src\\assets\\sprites\\"));
-        full_path.push(file_name);
-
-        let mut hasher = DefaultHasher::new();
-        filepath.hash(&mut hasher);
-        let id = hasher.finish();
-        println!("FILEPATH: {}", filepath);
-
-        let sprite = Sprite::new(
-            id,
-            full_path
-                .to_str()
-                .unwrap()
-                .to_owned()
-                .replace("\\", "/")
-                .into(),
+    copies
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(vfs::local_path(path)?).ok()?.modified().ok()
+}
+
+fn write_meta(path: &str, meta: &SpriteMeta) {
+    if let Err(e) = vfs::write(path, meta.to_text().as_bytes()) {
+        eprintln!("Failed to write {path}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    const GUID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    const SHORT_META: &[u8] = b"guid = 67e55044-10b1-426f-9247-bb680e5fe0c8\n";
+
+    fn meta_text() -> String {
+        SpriteMeta::new(Uuid::parse_str(GUID).unwrap()).to_text()
+    }
+
+    #[test]
+    fn older_meta_file_keeps_a_shared_guid() {
+        let root = std::env::temp_dir().join(format!("sprite-loader-{}", std::process::id()));
+        fs::create_dir_all(&root).unwrap();
+        for name in ["a.png", "b.png"] {
+            fs::write(root.join(name), b"").unwrap();
+            fs::write(root.join(format!("{name}.meta")), meta_text()).unwrap();
+        }
+
+        // b.png sorts later but its meta file is the original
+        let older = SystemTime::now() - Duration::from_secs(60);
+        let file = fs::File::options()
+            .write(true)
+            .open(root.join("b.png.meta"))
+            .unwrap();
+        file.set_modified(older).unwrap();
+
+        vfs::mount("test-copies", vfs::Directory::new(&root));
+        let loader = SpriteLoader::load_from("test-copies://");
+        vfs::unmount("test-copies");
+
+        let a_meta = fs::read_to_string(root.join("a.png.meta")).unwrap();
+        let b_meta = fs::read_to_string(root.join("b.png.meta")).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+
+        let guid = Uuid::parse_str(GUID).unwrap();
+        let a = loader.get_by_path("test-copies://a.png").unwrap();
+        let b = loader.get_by_path("test-copies://b.png").unwrap();
+        assert_eq!(b.guid(), guid);
+        assert_ne!(a.guid(), guid);
+
+        assert_eq!(b_meta, meta_text());
+        let written = SpriteMeta::parse("a.png.meta", &a_meta).unwrap();
+        assert_eq!(written.guid, a.guid());
+    }
+
+    #[test]
+    fn shared_guid_is_not_written_when_the_original_is_unknown() {
+        vfs::mount(
+            "test-shared",
+            vfs::Embedded::new([
+                ("a.png", &b""[..]),
+                ("a.png.meta", SHORT_META),
+                ("b.png", &b""[..]),
+                ("b.png.meta", SHORT_META),
+            ]),
         );
-        sprites.insert(id, sprite);
+        let loader = SpriteLoader::load_from("test-shared://");
+        vfs::unmount("test-shared");
+
+        // Embedded files have no modification time, so the first path keeps the guid
+        let guid = Uuid::parse_str(GUID).unwrap();
+        let a = loader.get_by_path("test-shared://a.png").unwrap();
+        let b = loader.get_by_path("test-shared://b.png").unwrap();
+        assert_eq!(a.guid(), guid);
+        assert_ne!(b.guid(), guid);
+        assert_eq!(loader.get_by_guid(&guid).unwrap().path(), a.path());
     }
 }
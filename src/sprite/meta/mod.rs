@@ -0,0 +1,209 @@
+use std::{fmt::Write, str::FromStr};
+
+use uuid::Uuid;
+
+use super::slice::SpriteBorder;
+use crate::terra::{
+    data::{FilterMode, SamplerSettings, WrapMode},
+    error::{TerraError, TerraResult},
+};
+
+// Import settings kept next to a sprite in `<file>.meta`, one `key = value` per line. The guid
+// is what identifies the sprite, so it survives the file being renamed or moved as long as the
+// meta file goes with it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpriteMeta {
+    pub guid: Uuid,
+    pub sampler: SamplerSettings,
+    pub mipmaps: bool,
+    // None draws the sprite as a unit quad whatever its size in pixels
+    pub pixels_per_unit: Option<f32>,
+    // In texture coordinates, so [0.5, 0.5] is the centre and [0.0, 0.0] the top left
+    pub pivot: [f32; 2],
+    pub border: SpriteBorder,
+}
+
+impl SpriteMeta {
+    pub fn new(guid: Uuid) -> SpriteMeta {
+        SpriteMeta {
+            guid,
+            sampler: SamplerSettings::default(),
+            mipmaps: false,
+            pixels_per_unit: None,
+            pivot: [0.5, 0.5],
+            border: SpriteBorder::default(),
+        }
+    }
+
+    // Fails on anything it doesn't understand rather than guessing, since a guid that silently
+    // changed would break every reference to the sprite
+    pub fn parse(path: &str, text: &str) -> TerraResult<SpriteMeta> {
+        let mut guid = None;
+        let mut meta = SpriteMeta::new(Uuid::nil());
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fail =
+                |reason: &str| TerraError::meta(path, format!("line {}: {reason}", number + 1));
+
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| fail("expected `key = value`"))?;
+
+            match key {
+                "guid" => guid = Some(Uuid::parse_str(value).map_err(|_| fail("bad guid"))?),
+                "filter" => {
+                    meta.sampler.filter = match value {
+                        "nearest" => FilterMode::Nearest,
+                        "linear" => FilterMode::Linear,
+                        _ => return Err(fail("filter is nearest or linear")),
+                    }
+                }
+                "wrap" => {
+                    meta.sampler.wrap = match value {
+                        "clamp" => WrapMode::Clamp,
+                        "repeat" => WrapMode::Repeat,
+                        "mirror" => WrapMode::Mirror,
+                        _ => return Err(fail("wrap is clamp, repeat or mirror")),
+                    }
+                }
+                "anisotropy" => {
+                    meta.sampler.anisotropy = value.parse().map_err(|_| fail("bad number"))?
+                }
+                "mipmaps" => meta.mipmaps = value.parse().map_err(|_| fail("bad boolean"))?,
+                "pixels_per_unit" => {
+                    let pixels_per_unit: f32 = value.parse().map_err(|_| fail("bad number"))?;
+                    if pixels_per_unit <= 0.0 {
+                        return Err(fail("pixels_per_unit must be positive"));
+                    }
+                    meta.pixels_per_unit = Some(pixels_per_unit);
+                }
+                "pivot" => match numbers(value).as_deref() {
+                    Some(&[x, y]) => meta.pivot = [x, y],
+                    _ => return Err(fail("pivot is two numbers, x and y")),
+                },
+                "border" => match numbers(value).as_deref() {
+                    Some(&[left, right, top, bottom]) => {
+                        meta.border = SpriteBorder::new(left, right, top, bottom)
+                    }
+                    _ => return Err(fail("border is four numbers, left right top bottom")),
+                },
+                _ => return Err(fail(&format!("unknown key {key}"))),
+            }
+        }
+
+        meta.guid = guid.ok_or_else(|| TerraError::meta(path, "missing its guid"))?;
+        Ok(meta)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let filter = match self.sampler.filter {
+            FilterMode::Nearest => "nearest",
+            FilterMode::Linear => "linear",
+        };
+        let wrap = match self.sampler.wrap {
+            WrapMode::Clamp => "clamp",
+            WrapMode::Repeat => "repeat",
+            WrapMode::Mirror => "mirror",
+        };
+        let border = &self.border;
+
+        writeln!(text, "guid = {}", self.guid).unwrap();
+        writeln!(text, "filter = {filter}").unwrap();
+        writeln!(text, "wrap = {wrap}").unwrap();
+        writeln!(text, "anisotropy = {}", self.sampler.anisotropy).unwrap();
+        writeln!(text, "mipmaps = {}", self.mipmaps).unwrap();
+        if let Some(pixels_per_unit) = self.pixels_per_unit {
+            writeln!(text, "pixels_per_unit = {pixels_per_unit}").unwrap();
+        }
+        writeln!(text, "pivot = {} {}", self.pivot[0], self.pivot[1]).unwrap();
+        writeln!(
+            text,
+            "border = {} {} {} {}",
+            border.left, border.right, border.top, border.bottom
+        )
+        .unwrap();
+
+        text
+    }
+}
+
+fn numbers<T: FromStr>(value: &str) -> Option<Vec<T>> {
+    value
+        .split_whitespace()
+        .map(|number| number.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+    #[test]
+    fn parse_reads_what_to_text_writes() {
+        let meta = SpriteMeta {
+            guid: Uuid::parse_str(GUID).unwrap(),
+            sampler: SamplerSettings {
+                filter: FilterMode::Nearest,
+                wrap: WrapMode::Mirror,
+                anisotropy: 8,
+            },
+            mipmaps: true,
+            pixels_per_unit: Some(32.0),
+            pivot: [0.25, 1.0],
+            border: SpriteBorder::new(1, 2, 3, 4),
+        };
+
+        let parsed = SpriteMeta::parse("hero.png.meta", &meta.to_text()).unwrap();
+        assert_eq!(parsed, meta);
+
+        let defaults = SpriteMeta::new(Uuid::parse_str(GUID).unwrap());
+        let parsed = SpriteMeta::parse("hero.png.meta", &defaults.to_text()).unwrap();
+        assert_eq!(parsed, defaults);
+    }
+
+    #[test]
+    fn parse_skips_comments_and_blank_lines() {
+        let text = format!("# Imported sprite\n\n  guid = {GUID}  \nfilter=linear\n");
+        let meta = SpriteMeta::parse("hero.png.meta", &text).unwrap();
+
+        assert_eq!(meta.guid, Uuid::parse_str(GUID).unwrap());
+        assert_eq!(meta.sampler.filter, FilterMode::Linear);
+    }
+
+    #[test]
+    fn parse_requires_a_guid() {
+        let error = SpriteMeta::parse("hero.png.meta", "filter = linear\n").unwrap_err();
+
+        assert!(matches!(error, TerraError::Meta { .. }));
+        assert!(error.to_string().contains("missing its guid"), "{error}");
+    }
+
+    #[test]
+    fn parse_reports_the_bad_line() {
+        let lines = [
+            "filter",
+            "filter = blurry",
+            "pivot = 0.5",
+            "pixels_per_unit = -1",
+            "guid = not-a-guid",
+            "colour = red",
+        ];
+
+        for line in lines {
+            let text = format!("guid = {GUID}\n\n{line}\n");
+            let error = SpriteMeta::parse("hero.png.meta", &text).unwrap_err();
+
+            assert!(matches!(error, TerraError::Meta { .. }), "{line}");
+            assert!(error.to_string().contains("line 3"), "{line}: {error}");
+        }
+    }
+}
@@ -1,63 +1,371 @@
 pub mod loader;
+pub mod meta;
 pub mod renderer;
-pub use renderer::SpriteRenderer;
+pub mod slice;
+pub mod texture;
+pub use renderer::{DrawMode, SpriteRenderer};
 
-use crate::terra::data::{SpriteData, Vertex};
-use image::EncodableLayout;
+use crate::{
+    terra::{
+        data::{BlendMode, Mipmaps, SamplerSettings, SpriteData, TextureFormat, Vertex},
+        error::{TerraError, TerraResult},
+    },
+    vfs,
+};
+use image::{EncodableLayout, ImageFormat};
+use meta::SpriteMeta;
+use slice::SpriteBorder;
+use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct Sprite {
     id: u64,
+    guid: Uuid,
     path: Box<str>,
+    border: SpriteBorder,
+    blend_mode: BlendMode,
+    sampler: SamplerSettings,
+    mipmaps: bool,
+    pixels_per_unit: Option<f32>,
+    pivot: [f32; 2],
+    normal_map: Option<Box<Sprite>>,
 }
 
 impl Sprite {
-    pub fn new(id: u64, path: Box<str>) -> Sprite {
-        Sprite { id, path }
+    pub fn new(guid: Uuid, path: Box<str>) -> Sprite {
+        Sprite::from_meta(path, &SpriteMeta::new(guid))
+    }
+
+    pub fn from_meta(path: Box<str>, meta: &SpriteMeta) -> Sprite {
+        // Folded from the guid rather than hashed so it's the same on every run and build
+        let (high, low) = meta.guid.as_u64_pair();
+
+        Sprite {
+            id: high ^ low,
+            guid: meta.guid,
+            path,
+            border: meta.border,
+            blend_mode: BlendMode::default(),
+            sampler: meta.sampler,
+            mipmaps: meta.mipmaps,
+            pixels_per_unit: meta.pixels_per_unit,
+            pivot: meta.pivot,
+            normal_map: None,
+        }
+    }
+
+    // Import settings as they'd be saved to the sprite's meta file
+    pub fn meta(&self) -> SpriteMeta {
+        SpriteMeta {
+            guid: self.guid,
+            sampler: self.sampler,
+            mipmaps: self.mipmaps,
+            pixels_per_unit: self.pixels_per_unit,
+            pivot: self.pivot,
+            border: self.border,
+        }
     }
 
     pub fn id(&self) -> u64 {
         self.id
     }
 
-    pub fn load(&self) -> SpriteData {
-        let path: &str = &self.path;
+    pub fn guid(&self) -> Uuid {
+        self.guid
+    }
+
+    // Logical path in the VFS, such as `sprites://hero.png`
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn border(&self) -> &SpriteBorder {
+        &self.border
+    }
+
+    pub fn set_border(&mut self, border: SpriteBorder) {
+        self.border = border;
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.blend_mode
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.blend_mode = blend_mode;
+    }
+
+    pub fn sampler(&self) -> SamplerSettings {
+        self.sampler
+    }
+
+    // Also used for the normal map so both are sampled at the same texels
+    pub fn set_sampler(&mut self, sampler: SamplerSettings) {
+        self.sampler = sampler;
+    }
+
+    pub fn mipmaps(&self) -> bool {
+        self.mipmaps
+    }
+
+    // Worth enabling for sprites that are drawn well below their native size. Textures that
+    // come with a mip chain use it, others have one generated. Only affects textures uploaded
+    // after the change.
+    pub fn set_mipmaps(&mut self, mipmaps: bool) {
+        self.mipmaps = mipmaps;
+    }
+
+    pub fn pixels_per_unit(&self) -> Option<f32> {
+        self.pixels_per_unit
+    }
+
+    // Sizes the quad from the texture, so a 64 pixel wide sprite at 32 pixels per unit is two
+    // units wide. None keeps it a unit quad. Only affects textures uploaded after the change.
+    pub fn set_pixels_per_unit(&mut self, pixels_per_unit: Option<f32>) {
+        self.pixels_per_unit = pixels_per_unit;
+    }
+
+    pub fn pivot(&self) -> [f32; 2] {
+        self.pivot
+    }
+
+    // Point of the texture that sits at the renderer's position, in texture coordinates
+    pub fn set_pivot(&mut self, pivot: [f32; 2]) {
+        self.pivot = pivot;
+    }
+
+    // Size of the quad the sprite is drawn with, for a texture of the given size in pixels
+    pub fn local_size(&self, dimensions: [u32; 2]) -> [f32; 2] {
+        match self.pixels_per_unit {
+            Some(pixels_per_unit) => [
+                dimensions[0] as f32 / pixels_per_unit,
+                dimensions[1] as f32 / pixels_per_unit,
+            ],
+            None => [1.0, 1.0],
+        }
+    }
+
+    // Where a texture coordinate ends up on the quad the sprite is drawn with
+    pub fn local_point(&self, uv: [f32; 2], dimensions: [u32; 2]) -> [f32; 2] {
+        let size = self.local_size(dimensions);
+
+        [
+            (uv[0] - self.pivot[0]) * size[0],
+            (uv[1] - self.pivot[1]) * size[1],
+        ]
+    }
+
+    pub fn normal_map(&self) -> Option<&Sprite> {
+        self.normal_map.as_deref()
+    }
+
+    // Only used when the sprite is drawn by a lit renderer
+    pub fn set_normal_map(&mut self, normal_map: Option<Sprite>) {
+        self.normal_map = normal_map.map(Box::new);
+    }
+
+    // Convex hull of every pixel with an alpha above the threshold, on the quad the sprite is
+    // drawn with and in counter-clockwise order
+    pub fn outline(&self, alpha_threshold: u8) -> TerraResult<Vec<[f32; 2]>> {
+        let (hull, dimensions) = self.outline_uv(alpha_threshold)?;
+
+        Ok(hull
+            .iter()
+            .map(|uv| self.local_point(*uv, dimensions))
+            .collect())
+    }
+
+    // The same hull in texture coordinates, along with the size of the texture in pixels
+    pub fn outline_uv(&self, alpha_threshold: u8) -> TerraResult<(Vec<[f32; 2]>, [u32; 2])> {
+        let data = texture::decompress(&self.path, self.load()?)?;
+        let hull = opaque_hull(&self.path, &data, alpha_threshold)?;
 
-        let image = image::io::Reader::open(path).expect("Failed to load sprite at path: {path}");
-        let image = image.decode().expect("Failed to decode image");
+        Ok((hull, [data.width, data.height]))
+    }
+
+    pub fn load(&self) -> TerraResult<SpriteData> {
+        let path: &str = &self.path;
+        let extension = path.rsplit('.').next().unwrap_or_default();
+        let bytes = vfs::read(path).map_err(|source| TerraError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
 
-        let width = image.width();
-        let height = image.height();
+        // KTX2 and DDS textures stay compressed and can bring their own mip chain
+        let (width, height, format, mut levels) = match extension.to_ascii_lowercase().as_str() {
+            "ktx2" => texture::read_ktx2(path, &bytes)?,
+            "dds" => texture::read_dds(path, &bytes)?,
+            _ => {
+                // Some formats like TGA can't be told apart by their contents
+                let image_format = ImageFormat::from_extension(extension)
+                    .or_else(|| image::guess_format(&bytes).ok())
+                    .ok_or_else(|| TerraError::image(path, "unknown image format"))?;
+                let image = image::load_from_memory_with_format(&bytes, image_format)
+                    .map_err(|e| TerraError::image(path, e))?;
+                let (width, height) = (image.width(), image.height());
 
-        let w_mult: f32 = (image.width() as f32) / (image.width() as f32);
-        let h_mult: f32 = (image.height() as f32) / (image.height() as f32);
+                let pixels = image.into_rgba8();
+                (
+                    width,
+                    height,
+                    TextureFormat::Rgba8,
+                    vec![pixels.as_bytes().into()],
+                )
+            }
+        };
 
-        let pixels = image.into_rgba8();
-        let pixels = pixels.as_bytes().into();
+        if levels.is_empty() {
+            return Err(TerraError::image(path, "the file has no image data"));
+        }
+        let pixels = levels.remove(0);
 
         let indices = [0, 1, 2, 1, 0, 3];
 
-        let vertices = [
+        let dimensions = [width, height];
+        let vertices = [[0.0, 1.0], [1.0, 0.0], [0.0, 0.0], [1.0, 1.0]].map(|uv| {
+            let [x, y] = self.local_point(uv, dimensions);
             Vertex {
-                vertex: [-0.5 * w_mult, 0.5 * h_mult, 0.0, 1.0],
-            },
-            Vertex {
-                vertex: [0.5 * w_mult, -0.5 * h_mult, 1.0, 0.0],
-            },
-            Vertex {
-                vertex: [-0.5 * w_mult, -0.5 * h_mult, 0.0, 0.0],
-            },
-            Vertex {
-                vertex: [0.5 * w_mult, 0.5 * h_mult, 1.0, 1.0],
-            },
-        ];
+                vertex: [x, y, uv[0], uv[1]],
+            }
+        });
 
-        SpriteData {
+        Ok(SpriteData {
             vertices,
             indices,
             width,
             height,
+            format,
             pixels,
+            mipmaps: match (self.mipmaps, levels.is_empty()) {
+                (false, _) => Mipmaps::None,
+                (true, true) => Mipmaps::Generate,
+                (true, false) => Mipmaps::Levels(levels),
+            },
+        })
+    }
+}
+
+// Convex hull of the pixels above the alpha threshold, in texture coordinates. Only the
+// outermost opaque pixels of each row can be on the hull.
+fn opaque_hull(path: &str, data: &SpriteData, alpha_threshold: u8) -> TerraResult<Vec<[f32; 2]>> {
+    let (width, height) = (data.width as usize, data.height as usize);
+    let size = width * height * 4;
+
+    if width == 0 || height == 0 {
+        return Err(TerraError::image(path, "the image has no pixels"));
+    }
+    if data.pixels.len() != size {
+        return Err(TerraError::image(
+            path,
+            format!(
+                "expected {size} bytes of pixels for {width}x{height}, found {}",
+                data.pixels.len()
+            ),
+        ));
+    }
+
+    let mut corners = vec![];
+    for (y, row) in data.pixels.chunks_exact(width * 4).enumerate() {
+        let mut opaque = row
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, pixel)| pixel[3] > alpha_threshold)
+            .map(|(x, _)| x);
+
+        let first = match opaque.next() {
+            Some(first) => first,
+            None => continue,
+        };
+        let last = opaque.next_back().unwrap_or(first);
+
+        for x in [first, last + 1] {
+            for y in [y, y + 1] {
+                corners.push([x as f32 / width as f32, y as f32 / height as f32]);
+            }
         }
     }
+
+    Ok(convex_hull(corners))
+}
+
+// Andrew's monotone chain, returns the hull in counter-clockwise order
+fn convex_hull(mut points: Vec<[f32; 2]>) -> Vec<[f32; 2]> {
+    points.sort_by(|a, b| a[0].total_cmp(&b[0]).then(a[1].total_cmp(&b[1])));
+    points.dedup();
+
+    if points.len() < 3 {
+        return points;
+    }
+
+    let cross = |o: [f32; 2], a: [f32; 2], b: [f32; 2]| {
+        (a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])
+    };
+
+    let mut lower: Vec<[f32; 2]> = vec![];
+    for point in points.iter() {
+        while lower.len() >= 2
+            && cross(lower[lower.len() - 2], lower[lower.len() - 1], *point) <= 0.0
+        {
+            lower.pop();
+        }
+        lower.push(*point);
+    }
+
+    let mut upper: Vec<[f32; 2]> = vec![];
+    for point in points.iter().rev() {
+        while upper.len() >= 2
+            && cross(upper[upper.len() - 2], upper[upper.len() - 1], *point) <= 0.0
+        {
+            upper.pop();
+        }
+        upper.push(*point);
+    }
+
+    lower.pop();
+    upper.pop();
+    lower.extend(upper);
+    lower
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(width: u32, height: u32, pixels: Vec<u8>) -> SpriteData {
+        SpriteData {
+            vertices: [Vertex { vertex: [0.0; 4] }; 4],
+            indices: [0; 6],
+            width,
+            height,
+            format: TextureFormat::Rgba8,
+            pixels,
+            mipmaps: Mipmaps::None,
+        }
+    }
+
+    #[test]
+    fn hull_surrounds_the_opaque_pixels() {
+        // 4x2 with the middle two pixels of the top row opaque
+        let mut pixels = vec![0; 4 * 2 * 4];
+        pixels[4 + 3] = 255;
+        pixels[8 + 3] = 255;
+
+        let hull = opaque_hull("sprites://bar.png", &data(4, 2, pixels), 0).unwrap();
+        assert_eq!(
+            hull,
+            vec![[0.25, 0.0], [0.75, 0.0], [0.75, 0.5], [0.25, 0.5]]
+        );
+    }
+
+    #[test]
+    fn hull_rejects_empty_and_missized_pixels() {
+        let path = "sprites://broken.png";
+        assert!(opaque_hull(path, &data(0, 4, vec![]), 0).is_err());
+        assert!(opaque_hull(path, &data(4, 0, vec![]), 0).is_err());
+        assert!(opaque_hull(path, &data(2, 2, vec![255; 15]), 0).is_err());
+        assert!(opaque_hull(path, &data(2, 2, vec![255; 17]), 0).is_err());
+        assert!(opaque_hull(path, &data(2, 2, vec![0; 16]), 0)
+            .unwrap()
+            .is_empty());
+    }
 }
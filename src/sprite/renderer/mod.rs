@@ -1,16 +1,34 @@
+use std::cell::RefCell;
 use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 
+use crate::material::Material;
 use crate::terra::data::Color;
 use crate::transform::Transform;
 
+use super::slice::NineSlice;
 use super::Sprite;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrawMode {
+    Simple,
+    Sliced(NineSlice),
+}
+
 pub struct SpriteRenderer {
     id: u64,
     transform: Transform,
     sprite: Sprite,
     color: Color,
+    draw_mode: DrawMode,
+    material: Option<Rc<RefCell<Material>>>,
+    lit: bool,
+    sorting_order: i32,
+    flip_x: bool,
+    flip_y: bool,
+    uv_offset: [f32; 2],
+    uv_scale: [f32; 2],
 }
 
 impl SpriteRenderer {
@@ -23,6 +41,14 @@ impl SpriteRenderer {
             transform: Transform::new(),
             sprite,
             color: Color::new(),
+            draw_mode: DrawMode::Simple,
+            material: None,
+            lit: false,
+            sorting_order: 0,
+            flip_x: false,
+            flip_y: false,
+            uv_offset: [0.0; 2],
+            uv_scale: [1.0; 2],
         }
     }
 
@@ -49,4 +75,93 @@ impl SpriteRenderer {
     pub fn color_mut(&mut self) -> &mut Color {
         &mut self.color
     }
+
+    pub fn draw_mode(&self) -> &DrawMode {
+        &self.draw_mode
+    }
+
+    pub fn set_draw_mode(&mut self, draw_mode: DrawMode) {
+        self.draw_mode = draw_mode;
+    }
+
+    pub fn material(&self) -> Option<&Rc<RefCell<Material>>> {
+        self.material.as_ref()
+    }
+
+    // Sprites without a material are drawn with the built-in sprite shaders
+    pub fn set_material(&mut self, material: Option<Rc<RefCell<Material>>>) {
+        self.material = material;
+    }
+
+    pub fn is_lit(&self) -> bool {
+        self.lit
+    }
+
+    // Lit sprites are shaded by the lights in the graphics context. Materials take precedence.
+    pub fn set_lit(&mut self, lit: bool) {
+        self.lit = lit;
+    }
+
+    // Lower orders are drawn first. Within the same order sprites are drawn first, then meshes
+    // and then shapes.
+    pub fn sorting_order(&self) -> i32 {
+        self.sorting_order
+    }
+
+    pub fn set_sorting_order(&mut self, sorting_order: i32) {
+        self.sorting_order = sorting_order;
+    }
+
+    pub fn flip_x(&self) -> bool {
+        self.flip_x
+    }
+
+    pub fn set_flip_x(&mut self, flip_x: bool) {
+        self.flip_x = flip_x;
+    }
+
+    pub fn flip_y(&self) -> bool {
+        self.flip_y
+    }
+
+    pub fn set_flip_y(&mut self, flip_y: bool) {
+        self.flip_y = flip_y;
+    }
+
+    pub fn uv_offset(&self) -> [f32; 2] {
+        self.uv_offset
+    }
+
+    // Animating the offset scrolls the texture, wrapping according to the sprite's sampler
+    pub fn set_uv_offset(&mut self, uv_offset: [f32; 2]) {
+        self.uv_offset = uv_offset;
+    }
+
+    pub fn uv_scale(&self) -> [f32; 2] {
+        self.uv_scale
+    }
+
+    // Scales above 1.0 tile the texture when the sprite's sampler repeats
+    pub fn set_uv_scale(&mut self, uv_scale: [f32; 2]) {
+        self.uv_scale = uv_scale;
+    }
+
+    // Flips, then scales and offsets the texture coordinates, as [scale.x, scale.y, offset.x,
+    // offset.y]. Flipping happens within the scaled area so it doesn't move the texture.
+    pub fn uv_transform(&self) -> [f32; 4] {
+        let [mut scale_x, mut scale_y] = self.uv_scale;
+        let [mut offset_x, mut offset_y] = self.uv_offset;
+
+        if self.flip_x {
+            offset_x += scale_x;
+            scale_x = -scale_x;
+        }
+
+        if self.flip_y {
+            offset_y += scale_y;
+            scale_y = -scale_y;
+        }
+
+        [scale_x, scale_y, offset_x, offset_y]
+    }
 }
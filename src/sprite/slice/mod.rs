@@ -0,0 +1,291 @@
+use super::Sprite;
+use crate::terra::data::Vertex;
+
+// Most tiles a tiled edge or centre is split into along each axis
+const MAX_TILES: usize = 256;
+
+// Border insets in pixels, measured from the edges of the source image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpriteBorder {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+impl SpriteBorder {
+    pub fn new(left: u32, right: u32, top: u32, bottom: u32) -> SpriteBorder {
+        SpriteBorder {
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+
+    pub fn uniform(inset: u32) -> SpriteBorder {
+        SpriteBorder::new(inset, inset, inset, inset)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.left == 0 && self.right == 0 && self.top == 0 && self.bottom == 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliceMode {
+    Stretch,
+    Tile,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NineSlice {
+    pub width: f32,
+    pub height: f32,
+    pub left: SliceMode,
+    pub right: SliceMode,
+    pub top: SliceMode,
+    pub bottom: SliceMode,
+    pub center: SliceMode,
+}
+
+impl NineSlice {
+    pub fn new(width: f32, height: f32) -> NineSlice {
+        NineSlice {
+            width,
+            height,
+            left: SliceMode::Stretch,
+            right: SliceMode::Stretch,
+            top: SliceMode::Stretch,
+            bottom: SliceMode::Stretch,
+            center: SliceMode::Stretch,
+        }
+    }
+
+    pub fn set_edges(&mut self, mode: SliceMode) {
+        self.left = mode;
+        self.right = mode;
+        self.top = mode;
+        self.bottom = mode;
+    }
+
+    // Builds the quads for a sprite of the given pixel dimensions, placed around its pivot like
+    // the unsliced quad. The borders keep the size they have on that quad, which is set by the
+    // sprite's pixels per unit, and only the edges and the centre grow to fill the requested size.
+    pub fn mesh(&self, sprite: &Sprite, dimensions: [u32; 2]) -> (Vec<Vertex>, Vec<u32>) {
+        let border = sprite.border();
+        let [native_width, native_height] = sprite.local_size(dimensions);
+        let pivot = sprite.pivot();
+
+        let u_left = border.left as f32 / dimensions[0].max(1) as f32;
+        let u_right = border.right as f32 / dimensions[0].max(1) as f32;
+        let v_top = border.top as f32 / dimensions[1].max(1) as f32;
+        let v_bottom = border.bottom as f32 / dimensions[1].max(1) as f32;
+
+        let x_scale = fit_borders((u_left + u_right) * native_width, self.width);
+        let y_scale = fit_borders((v_top + v_bottom) * native_height, self.height);
+
+        let left = u_left * native_width * x_scale;
+        let right = u_right * native_width * x_scale;
+        let top = v_top * native_height * y_scale;
+        let bottom = v_bottom * native_height * y_scale;
+
+        let x = -self.width * pivot[0];
+        let y = -self.height * pivot[1];
+
+        // (start, size, uv start, uv size) for each column and row
+        let columns = [
+            (x, left, 0.0, u_left),
+            (
+                x + left,
+                self.width - left - right,
+                u_left,
+                1.0 - u_left - u_right,
+            ),
+            (x + self.width - right, right, 1.0 - u_right, u_right),
+        ];
+        let rows = [
+            (y, top, 0.0, v_top),
+            (
+                y + top,
+                self.height - top - bottom,
+                v_top,
+                1.0 - v_top - v_bottom,
+            ),
+            (y + self.height - bottom, bottom, 1.0 - v_bottom, v_bottom),
+        ];
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+
+        for (row_index, row) in rows.iter().enumerate() {
+            for (column_index, column) in columns.iter().enumerate() {
+                let (x_mode, y_mode) = self.region_modes(column_index, row_index);
+
+                let x_spans = spans(*column, native_width, x_mode);
+                let y_spans = spans(*row, native_height, y_mode);
+
+                for y_span in y_spans.iter() {
+                    for x_span in x_spans.iter() {
+                        push_quad(&mut vertices, &mut indices, x_span, y_span);
+                    }
+                }
+            }
+        }
+
+        (vertices, indices)
+    }
+
+    // Corners are never repeated, edges repeat along their length and the centre in both directions
+    fn region_modes(&self, column: usize, row: usize) -> (SliceMode, SliceMode) {
+        match (column, row) {
+            (1, 0) => (self.top, SliceMode::Stretch),
+            (1, 2) => (self.bottom, SliceMode::Stretch),
+            (0, 1) => (SliceMode::Stretch, self.left),
+            (2, 1) => (SliceMode::Stretch, self.right),
+            (1, 1) => (self.center, self.center),
+            _ => (SliceMode::Stretch, SliceMode::Stretch),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    start: f32,
+    end: f32,
+    uv_start: f32,
+    uv_end: f32,
+}
+
+fn fit_borders(borders: f32, size: f32) -> f32 {
+    if borders > size && borders > 0.0 {
+        size.max(0.0) / borders
+    } else {
+        1.0
+    }
+}
+
+// Tiles are as long as the region is on the sprite's native size quad, `native` long for the
+// whole texture
+fn spans(
+    (start, size, uv_start, uv_size): (f32, f32, f32, f32),
+    native: f32,
+    mode: SliceMode,
+) -> Vec<Span> {
+    if size <= f32::EPSILON {
+        return vec![];
+    }
+
+    match mode {
+        SliceMode::Tile if uv_size * native > f32::EPSILON => {
+            // Tiles are stretched rather than repeated past MAX_TILES, so a tiny border on a
+            // large sprite can't produce millions of quads
+            let tile = (uv_size * native).max(size / MAX_TILES as f32);
+            let mut spans = vec![];
+            let mut offset = 0.0;
+
+            while size - offset > f32::EPSILON {
+                let length = tile.min(size - offset);
+                spans.push(Span {
+                    start: start + offset,
+                    end: start + offset + length,
+                    uv_start,
+                    uv_end: uv_start + uv_size * length / tile,
+                });
+                offset += length;
+            }
+
+            spans
+        }
+        _ => vec![Span {
+            start,
+            end: start + size,
+            uv_start,
+            uv_end: uv_start + uv_size,
+        }],
+    }
+}
+
+fn push_quad(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, x: &Span, y: &Span) {
+    let base = vertices.len() as u32;
+
+    vertices.extend([
+        Vertex {
+            vertex: [x.start, y.end, x.uv_start, y.uv_end],
+        },
+        Vertex {
+            vertex: [x.end, y.start, x.uv_end, y.uv_start],
+        },
+        Vertex {
+            vertex: [x.start, y.start, x.uv_start, y.uv_start],
+        },
+        Vertex {
+            vertex: [x.end, y.end, x.uv_end, y.uv_end],
+        },
+    ]);
+
+    indices.extend([0, 1, 2, 1, 0, 3].map(|index| base + index));
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    // Smallest and largest x and y of the mesh
+    fn bounds(vertices: &[Vertex]) -> [f32; 4] {
+        vertices.iter().fold(
+            [f32::MAX, f32::MAX, f32::MIN, f32::MIN],
+            |[min_x, min_y, max_x, max_y], vertex| {
+                let [x, y, _, _] = vertex.vertex;
+                [min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y)]
+            },
+        )
+    }
+
+    fn sprite(pivot: [f32; 2], pixels_per_unit: Option<f32>) -> Sprite {
+        let mut sprite = Sprite::new(Uuid::nil(), "sprites://panel.png".into());
+        sprite.set_pivot(pivot);
+        sprite.set_pixels_per_unit(pixels_per_unit);
+        sprite.set_border(SpriteBorder::uniform(16));
+        sprite
+    }
+
+    #[test]
+    fn mesh_is_placed_around_the_pivot() {
+        let slice = NineSlice::new(4.0, 3.0);
+
+        let (vertices, _) = slice.mesh(&sprite([0.5, 0.5], None), [64, 64]);
+        assert_eq!(bounds(&vertices), [-2.0, -1.5, 2.0, 1.5]);
+
+        let (vertices, _) = slice.mesh(&sprite([0.0, 1.0], None), [64, 64]);
+        assert_eq!(bounds(&vertices), [0.0, -3.0, 4.0, 0.0]);
+    }
+
+    #[test]
+    fn mesh_borders_follow_pixels_per_unit() {
+        let slice = NineSlice::new(4.0, 3.0);
+        let (vertices, indices) = slice.mesh(&sprite([0.0, 0.0], Some(32.0)), [64, 64]);
+        assert_eq!(indices.len(), 9 * 6);
+
+        // 16 pixels at 32 pixels per unit, so the top left corner is half a unit wide
+        let corner = bounds(&vertices[..4]);
+        assert_eq!(corner, [0.0, 0.0, 0.5, 0.5]);
+
+        // Without pixels per unit the texture is a unit quad and the border a quarter of it
+        let (vertices, _) = slice.mesh(&sprite([0.0, 0.0], None), [64, 64]);
+        assert_eq!(bounds(&vertices[..4]), [0.0, 0.0, 0.25, 0.25]);
+    }
+
+    #[test]
+    fn mesh_tiles_at_the_native_size() {
+        let mut slice = NineSlice::new(4.0, 4.0);
+        slice.center = SliceMode::Tile;
+
+        // The centre is 32 pixels, a unit at 32 pixels per unit, across 3 units
+        let (vertices, indices) = slice.mesh(&sprite([0.5, 0.5], Some(32.0)), [64, 64]);
+        assert_eq!(indices.len(), (8 + 3 * 3) * 6);
+        assert_eq!(bounds(&vertices), [-2.0, -2.0, 2.0, 2.0]);
+    }
+}
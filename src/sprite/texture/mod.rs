@@ -0,0 +1,234 @@
+use ddsfile::{D3DFormat, Dds, DxgiFormat};
+use ktx2::{Format, Reader};
+
+use crate::terra::{
+    data::{Mipmaps, SpriteData, TextureFormat},
+    error::{TerraError, TerraResult},
+};
+
+// Block sizes of the ASTC formats, in the order KTX2 numbers them
+const ASTC_BLOCKS: [(u32, u32); 14] = [
+    (4, 4),
+    (5, 4),
+    (5, 5),
+    (6, 5),
+    (6, 6),
+    (8, 5),
+    (8, 6),
+    (8, 8),
+    (10, 5),
+    (10, 6),
+    (10, 8),
+    (10, 10),
+    (12, 10),
+    (12, 12),
+];
+
+// Width, height, format and every mip level stored in a file, largest first
+pub type TextureLevels = (u32, u32, TextureFormat, Vec<Vec<u8>>);
+
+// Only the first layer of array textures and cube maps is read
+pub fn read_ktx2(path: &str, bytes: &[u8]) -> TerraResult<TextureLevels> {
+    let reader = Reader::new(bytes).map_err(|e| TerraError::image(path, format!("{e:?}")))?;
+    let header = reader.header();
+
+    if header.supercompression_scheme.is_some() {
+        return Err(TerraError::image(
+            path,
+            "supercompressed KTX2 files aren't supported",
+        ));
+    }
+
+    let format = header.format.and_then(ktx2_format).ok_or_else(|| {
+        TerraError::image(path, format!("unsupported KTX2 format {:?}", header.format))
+    })?;
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+
+    let levels = reader
+        .levels()
+        .enumerate()
+        .map_while(|(level, data)| {
+            let size = format.data_size((width >> level).max(1), (height >> level).max(1));
+            data.data.get(..size).map(|data| data.to_vec())
+        })
+        .collect();
+
+    Ok((width, height, format, levels))
+}
+
+pub fn read_dds(path: &str, bytes: &[u8]) -> TerraResult<TextureLevels> {
+    let dds = Dds::read(bytes).map_err(|e| TerraError::image(path, e))?;
+
+    let format =
+        dds_format(&dds).ok_or_else(|| TerraError::image(path, "unsupported DDS format"))?;
+    let (width, height) = (dds.get_width(), dds.get_height());
+    let data = dds.get_data(0).map_err(|e| TerraError::image(path, e))?;
+
+    // Levels are stored back to back, largest first
+    let mut offset = 0;
+    let levels = (0..dds.get_num_mipmap_levels().max(1))
+        .map_while(|level| {
+            let size = format.data_size((width >> level).max(1), (height >> level).max(1));
+            let level = data.get(offset..offset + size)?.to_vec();
+            offset += size;
+            Some(level)
+        })
+        .collect();
+
+    Ok((width, height, format, levels))
+}
+
+// Decodes block compressed textures to RGBA8, for devices that can't sample their format
+pub fn decompress(path: &str, data: SpriteData) -> TerraResult<SpriteData> {
+    let format = data.format;
+    if !format.is_compressed() {
+        return Ok(data);
+    }
+
+    let pixels = decode(format, data.width, data.height, &data.pixels)
+        .map_err(|e| TerraError::image(path, e))?;
+    let mipmaps = match data.mipmaps {
+        Mipmaps::Levels(levels) => Mipmaps::Levels(
+            levels
+                .iter()
+                .zip(1..)
+                .map(|(level, i)| {
+                    let (width, height) = ((data.width >> i).max(1), (data.height >> i).max(1));
+                    decode(format, width, height, level).map_err(|e| TerraError::image(path, e))
+                })
+                .collect::<TerraResult<_>>()?,
+        ),
+        mipmaps => mipmaps,
+    };
+
+    Ok(SpriteData {
+        format: TextureFormat::Rgba8,
+        pixels,
+        mipmaps,
+        ..data
+    })
+}
+
+fn decode(format: TextureFormat, width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>, String> {
+    let (width, height) = (width as usize, height as usize);
+    let mut image = vec![0u32; width * height];
+
+    let result = match format {
+        TextureFormat::Rgba8 => return Ok(data.to_vec()),
+        TextureFormat::Bc1 => texture2ddecoder::decode_bc1a(data, width, height, &mut image),
+        TextureFormat::Bc3 => texture2ddecoder::decode_bc3(data, width, height, &mut image),
+        TextureFormat::Bc7 => texture2ddecoder::decode_bc7(data, width, height, &mut image),
+        TextureFormat::Astc(block_width, block_height) => texture2ddecoder::decode_astc(
+            data,
+            width,
+            height,
+            block_width as usize,
+            block_height as usize,
+            &mut image,
+        ),
+    };
+    result.map_err(|e| format!("failed to decompress {format:?} texture: {e}"))?;
+
+    // The decoder writes BGRA pixels
+    Ok(image
+        .into_iter()
+        .flat_map(|pixel| {
+            let [b, g, r, a] = pixel.to_le_bytes();
+            [r, g, b, a]
+        })
+        .collect())
+}
+
+fn ktx2_format(format: Format) -> Option<TextureFormat> {
+    let format = match format {
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => TextureFormat::Rgba8,
+        Format::BC1_RGB_UNORM_BLOCK
+        | Format::BC1_RGB_SRGB_BLOCK
+        | Format::BC1_RGBA_UNORM_BLOCK
+        | Format::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1,
+        Format::BC3_UNORM_BLOCK | Format::BC3_SRGB_BLOCK => TextureFormat::Bc3,
+        Format::BC7_UNORM_BLOCK | Format::BC7_SRGB_BLOCK => TextureFormat::Bc7,
+        _ => {
+            // UNORM and SRGB variants of every ASTC block size alternate
+            let index = format
+                .value()
+                .checked_sub(Format::ASTC_4x4_UNORM_BLOCK.value())?;
+            let (width, height) = ASTC_BLOCKS.get(index as usize / 2)?;
+            TextureFormat::Astc(*width, *height)
+        }
+    };
+
+    Some(format)
+}
+
+fn dds_format(dds: &Dds) -> Option<TextureFormat> {
+    if let Some(format) = dds.get_dxgi_format() {
+        return match format {
+            DxgiFormat::R8G8B8A8_UNorm | DxgiFormat::R8G8B8A8_UNorm_sRGB => {
+                Some(TextureFormat::Rgba8)
+            }
+            DxgiFormat::BC1_UNorm | DxgiFormat::BC1_UNorm_sRGB => Some(TextureFormat::Bc1),
+            DxgiFormat::BC3_UNorm | DxgiFormat::BC3_UNorm_sRGB => Some(TextureFormat::Bc3),
+            DxgiFormat::BC7_UNorm | DxgiFormat::BC7_UNorm_sRGB => Some(TextureFormat::Bc7),
+            _ => None,
+        };
+    }
+
+    match dds.get_d3d_format()? {
+        D3DFormat::A8B8G8R8 => Some(TextureFormat::Rgba8),
+        D3DFormat::DXT1 => Some(TextureFormat::Bc1),
+        D3DFormat::DXT5 => Some(TextureFormat::Bc3),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ktx2_format_maps_astc_block_sizes() {
+        assert_eq!(
+            ktx2_format(Format::ASTC_4x4_UNORM_BLOCK),
+            Some(TextureFormat::Astc(4, 4))
+        );
+        assert_eq!(
+            ktx2_format(Format::ASTC_4x4_SRGB_BLOCK),
+            Some(TextureFormat::Astc(4, 4))
+        );
+        assert_eq!(
+            ktx2_format(Format::ASTC_8x5_SRGB_BLOCK),
+            Some(TextureFormat::Astc(8, 5))
+        );
+        assert_eq!(
+            ktx2_format(Format::ASTC_10x6_UNORM_BLOCK),
+            Some(TextureFormat::Astc(10, 6))
+        );
+        assert_eq!(
+            ktx2_format(Format::ASTC_12x12_UNORM_BLOCK),
+            Some(TextureFormat::Astc(12, 12))
+        );
+        assert_eq!(
+            ktx2_format(Format::ASTC_12x12_SRGB_BLOCK),
+            Some(TextureFormat::Astc(12, 12))
+        );
+    }
+
+    #[test]
+    fn ktx2_format_rejects_formats_around_astc() {
+        assert_eq!(ktx2_format(Format::EAC_R11G11_SNORM_BLOCK), None);
+        assert_eq!(ktx2_format(Format::R16G16B16A16_SFLOAT), None);
+    }
+
+    // A BC1 block whose first color is pure red, which every pixel uses
+    #[test]
+    fn decode_swaps_bgra_to_rgba() {
+        let block = [0x00, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let pixels = decode(TextureFormat::Bc1, 4, 4, &block).unwrap();
+
+        assert_eq!(pixels.len(), 4 * 4 * 4);
+        for pixel in pixels.chunks(4) {
+            assert_eq!(pixel, [255, 0, 0, 255]);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::terra::error::TerraResult;
+
+type Job<K, T> = (K, Box<dyn FnOnce() -> TerraResult<T> + Send>);
+
+// Runs jobs on a pool of worker threads. Results are picked up by polling, so nothing runs on
+// the caller's thread. Failed jobs are reported by their error message.
+pub struct Loader<K, T> {
+    jobs: Sender<Job<K, T>>,
+    results: Receiver<(K, Result<T, String>)>,
+}
+
+impl<K, T> Loader<K, T>
+where
+    K: Send + 'static,
+    T: Send + 'static,
+{
+    // One worker per core, keeping one free for the render thread
+    pub fn new() -> Loader<K, T> {
+        let workers = thread::available_parallelism()
+            .map(|count| count.get().saturating_sub(1).max(1))
+            .unwrap_or(1);
+
+        let (jobs, job_receiver) = mpsc::channel::<Job<K, T>>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        for i in 0..workers {
+            let jobs = job_receiver.clone();
+            let results = result_sender.clone();
+
+            thread::Builder::new()
+                .name(format!("asset-loader-{i}"))
+                .spawn(move || loop {
+                    // The lock is released before the job runs so other workers can pick up jobs
+                    let job = jobs.lock().unwrap().recv();
+                    let (key, job) = match job {
+                        Ok(job) => job,
+                        Err(_) => return,
+                    };
+
+                    // A panicking job fails its asset rather than taking the worker down
+                    let result = panic::catch_unwind(AssertUnwindSafe(job))
+                        .map_err(panic_message)
+                        .and_then(|result| result.map_err(|e| e.to_string()));
+                    if results.send((key, result)).is_err() {
+                        return;
+                    }
+                })
+                .expect("Failed to spawn asset loader thread");
+        }
+
+        Loader { jobs, results }
+    }
+
+    pub fn spawn(&self, key: K, job: impl FnOnce() -> TerraResult<T> + Send + 'static) {
+        self.jobs
+            .send((key, Box::new(job)))
+            .expect("Asset loader threads have stopped");
+    }
+
+    // Every job that finished since the last call
+    pub fn finished(&self) -> Vec<(K, Result<T, String>)> {
+        self.results.try_iter().collect()
+    }
+}
+
+impl<K, T> Default for Loader<K, T>
+where
+    K: Send + 'static,
+    T: Send + 'static,
+{
+    fn default() -> Loader<K, T> {
+        Loader::new()
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "Unknown error".to_string(),
+        },
+    }
+}
@@ -0,0 +1,308 @@
+pub mod loader;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::Add,
+    rc::{Rc, Weak},
+};
+
+// Keeps an asset in its Assets alive. Clones share the same count, the asset is freed by the
+// next collect after the last one is dropped.
+pub struct Handle<T> {
+    id: u64,
+    refs: Rc<()>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Handle<T> {
+        Handle {
+            id: self.id,
+            refs: self.refs.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Handle<T>) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Handle").field(&self.id).finish()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String),
+}
+
+// Counts of assets in each load state, for loading screens
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    pub loading: usize,
+    pub loaded: usize,
+    pub failed: usize,
+}
+
+impl LoadProgress {
+    pub fn total(&self) -> usize {
+        self.loading + self.loaded + self.failed
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.loading == 0
+    }
+
+    // Failed assets count as done so a loading screen can't get stuck on them
+    pub fn fraction(&self) -> f32 {
+        match self.total() {
+            0 => 1.0,
+            total => (self.loaded + self.failed) as f32 / total as f32,
+        }
+    }
+}
+
+impl Add for LoadProgress {
+    type Output = LoadProgress;
+
+    fn add(self, other: LoadProgress) -> LoadProgress {
+        LoadProgress {
+            loading: self.loading + other.loading,
+            loaded: self.loaded + other.loaded,
+            failed: self.failed + other.failed,
+        }
+    }
+}
+
+struct Entry<T> {
+    refs: Weak<()>,
+    state: LoadState,
+    asset: Option<T>,
+}
+
+pub struct Assets<T> {
+    entries: HashMap<u64, Entry<T>>,
+}
+
+impl<T> Assets<T> {
+    pub fn new() -> Assets<T> {
+        Assets {
+            entries: HashMap::new(),
+        }
+    }
+
+    // None until the asset has finished loading
+    pub fn get(&self, id: &u64) -> Option<&T> {
+        self.entries.get(id)?.asset.as_ref()
+    }
+
+    pub fn state(&self, id: &u64) -> Option<&LoadState> {
+        self.entries.get(id).map(|entry| &entry.state)
+    }
+
+    pub fn progress(&self) -> LoadProgress {
+        let mut progress = LoadProgress::default();
+
+        for entry in self.entries.values() {
+            match entry.state {
+                LoadState::Loading => progress.loading += 1,
+                LoadState::Loaded => progress.loaded += 1,
+                LoadState::Failed(_) => progress.failed += 1,
+            }
+        }
+
+        progress
+    }
+
+    // New handle to a known asset. Assets whose handles were all dropped are revived as long as
+    // they haven't been collected yet.
+    pub fn handle(&mut self, id: &u64) -> Option<Handle<T>> {
+        let entry = self.entries.get_mut(id)?;
+
+        let refs = entry.refs.upgrade().unwrap_or_else(|| {
+            let refs = Rc::new(());
+            entry.refs = Rc::downgrade(&refs);
+            refs
+        });
+
+        Some(Handle {
+            id: *id,
+            refs,
+            marker: PhantomData,
+        })
+    }
+
+    // Handle to an asset that is still being loaded, finished by insert or fail
+    pub fn reserve(&mut self, id: u64) -> Handle<T> {
+        if let Some(handle) = self.handle(&id) {
+            return handle;
+        }
+
+        let refs = Rc::new(());
+        let entry = Entry {
+            refs: Rc::downgrade(&refs),
+            state: LoadState::Loading,
+            asset: None,
+        };
+        self.entries.insert(id, entry);
+
+        Handle {
+            id,
+            refs,
+            marker: PhantomData,
+        }
+    }
+
+    // Replaces any asset with the same id, existing handles then point at the new one
+    pub fn insert(&mut self, id: u64, asset: T) -> Handle<T> {
+        let handle = self.reserve(id);
+
+        let entry = self.entries.get_mut(&id).unwrap();
+        entry.state = LoadState::Loaded;
+        entry.asset = Some(asset);
+
+        handle
+    }
+
+    pub fn fail(&mut self, id: &u64, error: String) {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.state = LoadState::Failed(error);
+        }
+    }
+
+    // Frees every asset without handles and returns their ids
+    pub fn collect(&mut self) -> Vec<u64> {
+        let mut freed = vec![];
+
+        self.entries.retain(|id, entry| {
+            let alive = entry.refs.strong_count() > 0;
+            if !alive {
+                freed.push(*id);
+            }
+            alive
+        });
+
+        freed
+    }
+}
+
+impl<T> Default for Assets<T> {
+    fn default() -> Assets<T> {
+        Assets::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_lives_while_any_handle_does() {
+        let mut assets = Assets::new();
+        let handle = assets.insert(1, "one");
+        let clone = handle.clone();
+
+        drop(handle);
+        assert!(assets.collect().is_empty());
+        assert_eq!(assets.get(&1), Some(&"one"));
+
+        drop(clone);
+        assert_eq!(assets.collect(), vec![1]);
+        assert_eq!(assets.get(&1), None);
+        assert_eq!(assets.state(&1), None);
+    }
+
+    #[test]
+    fn handle_revives_an_asset_before_collect() {
+        let mut assets = Assets::new();
+        drop(assets.insert(1, "one"));
+
+        let handle = assets.handle(&1).unwrap();
+        assert!(assets.collect().is_empty());
+        assert_eq!(assets.get(&handle.id()), Some(&"one"));
+
+        drop(handle);
+        assert_eq!(assets.collect(), vec![1]);
+        assert!(assets.handle(&1).is_none());
+    }
+
+    #[test]
+    fn reserve_shares_the_existing_count() {
+        let mut assets = Assets::<&str>::new();
+        let first = assets.reserve(1);
+        let second = assets.reserve(1);
+        assert_eq!(first, second);
+
+        drop(first);
+        assert!(assets.collect().is_empty());
+        assert_eq!(assets.state(&1), Some(&LoadState::Loading));
+
+        // Inserting keeps the handles of a reserved asset
+        assets.insert(1, "one");
+        assert!(assets.collect().is_empty());
+        assert_eq!(assets.get(&second.id()), Some(&"one"));
+    }
+
+    #[test]
+    fn progress_counts_each_state() {
+        let mut assets = Assets::new();
+        let _loading = assets.reserve(1);
+        let _loaded = assets.insert(2, "two");
+        let _failed = assets.reserve(3);
+        let _also_loading = assets.reserve(4);
+        assets.fail(&3, "missing".to_string());
+
+        let progress = assets.progress();
+        assert_eq!(
+            progress,
+            LoadProgress {
+                loading: 2,
+                loaded: 1,
+                failed: 1,
+            }
+        );
+        assert_eq!(progress.total(), 4);
+        assert!(!progress.is_done());
+        assert_eq!(progress.fraction(), 0.5);
+
+        let sum = progress
+            + LoadProgress {
+                loading: 0,
+                loaded: 4,
+                failed: 0,
+            };
+        assert_eq!(sum.total(), 8);
+        assert_eq!(sum.fraction(), 0.75);
+    }
+
+    #[test]
+    fn empty_progress_is_done() {
+        let progress = Assets::<()>::new().progress();
+        assert!(progress.is_done());
+        assert_eq!(progress.fraction(), 1.0);
+    }
+}
@@ -0,0 +1,62 @@
+use vulkano::{
+    device::physical::PhysicalDeviceType, format::Format, swapchain::PresentMode, Version,
+};
+
+// Settings for creating the instance, picking the device and opening the window, passed to
+// Terra::init. Start from the default and override what's needed:
+//
+//   TerraConfig { window_title: "Game".into(), ..Default::default() }
+#[derive(Clone, Debug)]
+pub struct TerraConfig {
+    pub app_name: String,
+    pub app_version: Version,
+    pub window_title: String,
+    // Logical size, scaled by the monitor's DPI
+    pub window_size: [u32; 2],
+    pub resizable: bool,
+    // Borderless on the current monitor
+    pub fullscreen: bool,
+    pub gpu: GpuPreference,
+    // Enables the Khronos validation layer when it's installed
+    pub validation: bool,
+    // Falls back to Fifo, which every device supports. Can be changed with Terra::set_present_mode.
+    pub present_mode: PresentMode,
+    // Frames the CPU can record ahead of the GPU, each with its own uniform buffers. At least 1.
+    pub frames_in_flight: usize,
+    // MSAA samples per pixel, 1, 2, 4 or 8. 1 turns it off and higher counts are capped by what
+    // the device supports.
+    pub samples: u32,
+    // Swapchain formats to try in order, the surface's first format is used if none are supported
+    pub color_formats: Vec<Format>,
+}
+
+impl Default for TerraConfig {
+    fn default() -> Self {
+        Self {
+            app_name: "Terra".into(),
+            app_version: Version::major_minor(1, 0),
+            window_title: "Terra".into(),
+            window_size: [1024, 768],
+            resizable: true,
+            fullscreen: false,
+            gpu: GpuPreference::Any,
+            validation: cfg!(debug_assertions),
+            present_mode: PresentMode::Fifo,
+            frames_in_flight: 2,
+            samples: 1,
+            color_formats: vec![Format::B8G8R8A8_SRGB, Format::R8G8B8A8_SRGB],
+        }
+    }
+}
+
+// Which GPU to draw with. When the preferred one can't be found or can't draw to the window,
+// the best supported device is used instead, discrete GPUs first.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GpuPreference {
+    Any,
+    // Part of the device name, ignoring case, as in "nvidia" or "radeon"
+    Name(String),
+    // Position in the list of devices the driver reports
+    Index(usize),
+    Type(PhysicalDeviceType),
+}
@@ -1,23 +1,52 @@
 use std::{
     cell::RefCell,
-    collections::{hash_map::Iter, HashMap},
+    collections::{hash_map::Iter, BTreeSet, HashMap},
     rc::Rc,
 };
 
-use crate::{camera::Camera, sprite::SpriteRenderer};
+use crate::{
+    camera::Camera,
+    light::{occluder::Occluder, Light},
+    mesh::MeshRenderer,
+    particle::ParticleEmitter,
+    shape::Shape,
+    sprite::{Sprite, SpriteRenderer},
+};
 
-use super::resources::graphics::GraphicsResources;
+use super::{
+    assets::{Handle, LoadProgress, LoadState},
+    data::Color,
+    resources::graphics::{GraphicsResources, SpriteResources},
+};
 
 pub struct GraphicsContext {
     resources: Rc<RefCell<GraphicsResources>>,
     sprite_renderers: HashMap<u64, Vec<Rc<RefCell<SpriteRenderer>>>>,
+    // Keyed by renderer or emitter id, keeps their sprite resident while they're in the context
+    sprite_handles: HashMap<u64, Handle<SpriteResources>>,
+    mesh_renderers: Vec<Rc<RefCell<MeshRenderer>>>,
+    particle_emitters: Vec<Rc<RefCell<ParticleEmitter>>>,
+    lights: Vec<Rc<RefCell<Light>>>,
+    occluders: Vec<Rc<RefCell<Occluder>>>,
+    shapes: Vec<Rc<RefCell<Shape>>>,
+    ambient: Color,
     camera: Rc<RefCell<Camera>>,
 }
 
 impl<'a> GraphicsContext {
     pub fn new(resources: &Rc<RefCell<GraphicsResources>>) -> GraphicsContext {
+        let mut ambient = Color::new();
+        ambient.set(0.2, 0.2, 0.2);
+
         GraphicsContext {
             sprite_renderers: HashMap::new(),
+            sprite_handles: HashMap::new(),
+            mesh_renderers: vec![],
+            particle_emitters: vec![],
+            lights: vec![],
+            occluders: vec![],
+            shapes: vec![],
+            ambient,
             resources: resources.clone(),
             camera: Rc::new(RefCell::new(Camera::new())),
         }
@@ -31,6 +60,73 @@ impl<'a> GraphicsContext {
         self.sprite_renderers.iter()
     }
 
+    pub fn mesh_renderers(&self) -> &Vec<Rc<RefCell<MeshRenderer>>> {
+        &self.mesh_renderers
+    }
+
+    pub fn particle_emitters(&self) -> &Vec<Rc<RefCell<ParticleEmitter>>> {
+        &self.particle_emitters
+    }
+
+    pub fn lights(&self) -> &Vec<Rc<RefCell<Light>>> {
+        &self.lights
+    }
+
+    pub fn occluders(&self) -> &Vec<Rc<RefCell<Occluder>>> {
+        &self.occluders
+    }
+
+    pub fn shapes(&self) -> &Vec<Rc<RefCell<Shape>>> {
+        &self.shapes
+    }
+
+    // Every sorting order used by a sprite, mesh or shape, lowest first
+    pub fn sorting_orders(&self) -> BTreeSet<i32> {
+        let sprites = self
+            .sprite_renderers
+            .values()
+            .flatten()
+            .map(|renderer| renderer.borrow().sorting_order());
+        let meshes = self
+            .mesh_renderers
+            .iter()
+            .map(|renderer| renderer.borrow().sorting_order());
+        let shapes = self
+            .shapes
+            .iter()
+            .map(|shape| shape.borrow().sorting_order());
+
+        sprites.chain(meshes).chain(shapes).collect()
+    }
+
+    // Only affects lit sprites, unlit ones are always drawn at full brightness
+    pub fn ambient(&self) -> &Color {
+        &self.ambient
+    }
+
+    pub fn ambient_mut(&mut self) -> &mut Color {
+        &mut self.ambient
+    }
+
+    // Starts loading a sprite in the background, so it's ready by the time a renderer uses it.
+    // The sprite stays loaded as long as the handle is held.
+    pub fn load_sprite(&self, sprite: &Sprite) -> Handle<SpriteResources> {
+        self.resources.borrow_mut().load_sprite(sprite)
+    }
+
+    pub fn load_state(&self, handle: &Handle<SpriteResources>) -> LoadState {
+        let resources = self.resources.borrow();
+        resources
+            .sprite_state(&handle.id())
+            .cloned()
+            .unwrap_or(LoadState::Loading)
+    }
+
+    // Includes the normal maps and material textures currently in use
+    pub fn load_progress(&self) -> LoadProgress {
+        self.resources.borrow().load_progress()
+    }
+
     pub fn add_sprite_renderer(&mut self, renderer: &Rc<RefCell<SpriteRenderer>>) {
         let instance = renderer.borrow();
         let sprite = instance.sprite();
@@ -43,17 +139,81 @@ impl<'a> GraphicsContext {
             self.sprite_renderers.insert(sprite.id(), renderers);
         }
 
-        self.resources
-            .borrow_mut()
-            .add_sprite(sprite.id(), sprite.load());
+        let handle = self.resources.borrow_mut().load_sprite(sprite);
+        self.sprite_handles.insert(instance.id(), handle);
     }
 
+    // The sprite is freed after the frame once no other renderer or emitter uses it
     pub fn remove_sprite_renderer(&mut self, renderer: &Rc<RefCell<SpriteRenderer>>) {
         let instance = renderer.borrow();
         let sprite = instance.sprite();
 
         if let Some(renderers) = self.sprite_renderers.get_mut(&sprite.id()) {
             renderers.retain(|r| r.borrow().id() != instance.id());
+
+            if renderers.is_empty() {
+                self.sprite_renderers.remove(&sprite.id());
+            }
+        }
+
+        self.sprite_handles.remove(&instance.id());
+    }
+
+    pub fn add_mesh_renderer(&mut self, renderer: &Rc<RefCell<MeshRenderer>>) {
+        self.mesh_renderers.push(renderer.clone());
+    }
+
+    pub fn remove_mesh_renderer(&mut self, renderer: &Rc<RefCell<MeshRenderer>>) {
+        let id = renderer.borrow().id();
+        self.mesh_renderers.retain(|r| r.borrow().id() != id);
+    }
+
+    pub fn add_particle_emitter(&mut self, emitter: &Rc<RefCell<ParticleEmitter>>) {
+        let instance = emitter.borrow();
+        let sprite = instance.sprite();
+
+        self.particle_emitters.push(emitter.clone());
+
+        let handle = self.resources.borrow_mut().load_sprite(sprite);
+        self.sprite_handles.insert(instance.id(), handle);
+    }
+
+    pub fn remove_particle_emitter(&mut self, emitter: &Rc<RefCell<ParticleEmitter>>) {
+        let id = emitter.borrow().id();
+        self.particle_emitters.retain(|e| e.borrow().id() != id);
+        self.sprite_handles.remove(&id);
+    }
+
+    pub fn add_light(&mut self, light: &Rc<RefCell<Light>>) {
+        self.lights.push(light.clone());
+    }
+
+    pub fn remove_light(&mut self, light: &Rc<RefCell<Light>>) {
+        let id = light.borrow().id();
+        self.lights.retain(|l| l.borrow().id() != id);
+    }
+
+    pub fn add_occluder(&mut self, occluder: &Rc<RefCell<Occluder>>) {
+        self.occluders.push(occluder.clone());
+    }
+
+    pub fn remove_occluder(&mut self, occluder: &Rc<RefCell<Occluder>>) {
+        let id = occluder.borrow().id();
+        self.occluders.retain(|o| o.borrow().id() != id);
+    }
+
+    pub fn add_shape(&mut self, shape: &Rc<RefCell<Shape>>) {
+        self.shapes.push(shape.clone());
+    }
+
+    pub fn remove_shape(&mut self, shape: &Rc<RefCell<Shape>>) {
+        let id = shape.borrow().id();
+        self.shapes.retain(|s| s.borrow().id() != id);
+    }
+
+    pub fn update(&self, delta: f32) {
+        for emitter in self.particle_emitters.iter() {
+            emitter.borrow_mut().update(delta);
         }
     }
 }
@@ -1,8 +1,20 @@
-use nalgebra_glm::Mat4;
+use std::{cell::RefCell, rc::Rc};
+
+use nalgebra_glm::{self as glm, Mat4, Vec2};
 use vulkano::buffer::BufferContents;
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendState,
+};
 use vulkano::pipeline::graphics::viewport::Viewport as VkViewport;
 
 use super::util::mat4_to_array;
+use crate::light::{Light, LightKind, Shadows};
+
+// Must match MAX_LIGHTS in shaders/include/lights.glsl
+pub const MAX_LIGHTS: usize = 32;
+
+// Angular resolution of the shadow map, which has one row per light
+pub const SHADOW_MAP_RESOLUTION: u32 = 512;
 
 #[derive(BufferContents, vulkano::pipeline::graphics::vertex_input::Vertex, Clone, Copy, Debug)]
 #[repr(C)]
@@ -11,12 +23,106 @@ pub struct Vertex {
     pub vertex: [f32; 4],
 }
 
+#[derive(BufferContents, vulkano::pipeline::graphics::vertex_input::Vertex, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct DebugVertex {
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+#[derive(BufferContents, vulkano::pipeline::graphics::vertex_input::Vertex, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct ShapeVertex {
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+    #[format(R32G32_SFLOAT)]
+    pub coverage: [f32; 2],
+}
+
+#[derive(BufferContents, vulkano::pipeline::graphics::vertex_input::Vertex, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MeshVertex {
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    pub uv: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+impl MeshVertex {
+    pub fn new(position: [f32; 2], uv: [f32; 2], color: [f32; 4]) -> MeshVertex {
+        MeshVertex {
+            position,
+            uv,
+            color,
+        }
+    }
+}
+
+// Mip levels a texture is uploaded with
+pub enum Mipmaps {
+    None,
+    // Blitted down from the full size image on the GPU
+    Generate,
+    // Every level below the full size one, smallest last, as stored in the file
+    Levels(Vec<Vec<u8>>),
+}
+
+// Layout of a texture's pixel data. Compression is independent of the color space, which is
+// picked by what the texture is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureFormat {
+    Rgba8,
+    Bc1,
+    Bc3,
+    Bc7,
+    // Block width and height in pixels
+    Astc(u32, u32),
+}
+
+impl TextureFormat {
+    pub fn is_compressed(&self) -> bool {
+        *self != TextureFormat::Rgba8
+    }
+
+    // Width and height in pixels of the blocks the data is stored in
+    pub fn block_extent(&self) -> [u32; 2] {
+        match self {
+            TextureFormat::Rgba8 => [1, 1],
+            TextureFormat::Bc1 | TextureFormat::Bc3 | TextureFormat::Bc7 => [4, 4],
+            TextureFormat::Astc(width, height) => [*width, *height],
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        match self {
+            TextureFormat::Rgba8 => 4,
+            TextureFormat::Bc1 => 8,
+            TextureFormat::Bc3 | TextureFormat::Bc7 | TextureFormat::Astc(_, _) => 16,
+        }
+    }
+
+    // Bytes taken by an image of the given size, partial blocks count as whole ones
+    pub fn data_size(&self, width: u32, height: u32) -> usize {
+        let [block_width, block_height] = self.block_extent();
+        let blocks = width.div_ceil(block_width) * height.div_ceil(block_height);
+        blocks as usize * self.block_size()
+    }
+}
+
 pub struct SpriteData {
     pub vertices: [Vertex; 4],
     pub indices: [u32; 6],
     pub width: u32,
     pub height: u32,
+    pub format: TextureFormat,
     pub pixels: Vec<u8>,
+    pub mipmaps: Mipmaps,
 }
 
 #[derive(BufferContents)]
@@ -42,6 +148,91 @@ impl GlobalData {
     }
 }
 
+#[derive(BufferContents, Clone, Copy, Default)]
+#[repr(C)]
+pub struct LightData {
+    // xy position, z height, w radius
+    position: [f32; 4],
+    // rgb color, a intensity
+    color: [f32; 4],
+    // xy direction, z cosine of the inner angle, w cosine of the outer angle
+    cone: [f32; 4],
+    // x falloff exponent
+    falloff: [f32; 4],
+    // x shadow mode, y soft shadow size
+    shadow: [f32; 4],
+}
+
+impl LightData {
+    pub fn new(light: &Light) -> LightData {
+        let model = light.transform().matrix();
+        let position = model * glm::vec4(0.0, 0.0, 0.0, 1.0);
+        let color = light.color().get();
+
+        // Point lights use a cone that covers every direction
+        let cone = match light.kind() {
+            LightKind::Point => [1.0, 0.0, -1.0, -2.0],
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            } => {
+                let axis = model * glm::vec4(1.0, 0.0, 0.0, 0.0);
+                let direction = match axis.xy().try_normalize(f32::EPSILON) {
+                    Some(direction) => direction,
+                    None => Vec2::new(1.0, 0.0),
+                };
+
+                let inner = (inner_angle * 0.5).to_radians().cos();
+                let outer = (outer_angle * 0.5).to_radians().cos().min(inner - 0.0001);
+                [direction.x, direction.y, inner, outer]
+            }
+        };
+
+        // Modes must match the SHADOWS_ defines in the shaders
+        let shadow = match light.shadows() {
+            Shadows::None => [0.0; 4],
+            Shadows::Hard => [1.0, 0.0, 0.0, 0.0],
+            Shadows::Soft { size } => [2.0, size.max(0.0), 0.0, 0.0],
+        };
+
+        LightData {
+            position: [position.x, position.y, light.height(), light.radius()],
+            color: [color[0], color[1], color[2], light.intensity()],
+            cone,
+            falloff: [light.falloff(), 0.0, 0.0, 0.0],
+            shadow,
+        }
+    }
+}
+
+// Lights past MAX_LIGHTS are ignored
+#[derive(BufferContents)]
+#[repr(C)]
+pub struct LightsData {
+    ambient: [f32; 4],
+    count: u32,
+    _padding: [u32; 3],
+    lights: [LightData; MAX_LIGHTS],
+}
+
+impl LightsData {
+    pub fn new(ambient: &Color, lights: &[Rc<RefCell<Light>>]) -> LightsData {
+        let ambient = ambient.get();
+        let mut data = [LightData::default(); MAX_LIGHTS];
+
+        for (data, light) in data.iter_mut().zip(lights.iter()) {
+            *data = LightData::new(&light.borrow());
+        }
+
+        LightsData {
+            ambient: [ambient[0], ambient[1], ambient[2], 1.0],
+            count: lights.len().min(MAX_LIGHTS) as u32,
+            _padding: [0; 3],
+            lights: data,
+        }
+    }
+}
+
 pub struct Color([f32; 3]);
 
 impl Color {
@@ -130,3 +321,101 @@ impl Default for ClippingPlanes {
         }
     }
 }
+
+// Sprites blend with alpha by default. Before blend modes existed they were drawn opaque, so
+// transparent pixels showed up black; use Opaque to get that back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Opaque,
+    #[default]
+    Alpha,
+    Additive,
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn color_blend_state(&self) -> ColorBlendState {
+        let state = ColorBlendState::new(1);
+
+        match self {
+            BlendMode::Opaque => state,
+            BlendMode::Alpha => state.blend_alpha(),
+            BlendMode::Additive => state.blend(AttachmentBlend {
+                color_op: BlendOp::Add,
+                color_source: BlendFactor::SrcAlpha,
+                color_destination: BlendFactor::One,
+                alpha_op: BlendOp::Max,
+                alpha_source: BlendFactor::One,
+                alpha_destination: BlendFactor::One,
+            }),
+            BlendMode::Multiply => state.blend(AttachmentBlend {
+                color_op: BlendOp::Add,
+                color_source: BlendFactor::DstColor,
+                color_destination: BlendFactor::Zero,
+                alpha_op: BlendOp::Add,
+                alpha_source: BlendFactor::Zero,
+                alpha_destination: BlendFactor::One,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FilterMode {
+    // Keeps pixel art sharp
+    Nearest,
+    #[default]
+    Linear,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WrapMode {
+    Clamp,
+    #[default]
+    Repeat,
+    Mirror,
+}
+
+// How a texture is sampled. Every distinct combination gets its own cached sampler.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerSettings {
+    pub filter: FilterMode,
+    pub wrap: WrapMode,
+    // Maximum anisotropic filtering samples, 1 disables it. Clamped to what the device supports.
+    pub anisotropy: u8,
+}
+
+impl SamplerSettings {
+    pub fn nearest() -> SamplerSettings {
+        SamplerSettings {
+            filter: FilterMode::Nearest,
+            ..Default::default()
+        }
+    }
+}
+
+impl Default for SamplerSettings {
+    fn default() -> Self {
+        Self {
+            filter: FilterMode::Linear,
+            wrap: WrapMode::Repeat,
+            anisotropy: 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_size_counts_partial_blocks() {
+        assert_eq!(TextureFormat::Rgba8.data_size(3, 5), 3 * 5 * 4);
+        assert_eq!(TextureFormat::Bc1.data_size(4, 4), 8);
+        assert_eq!(TextureFormat::Bc1.data_size(5, 4), 2 * 8);
+        assert_eq!(TextureFormat::Bc3.data_size(1, 1), 16);
+        assert_eq!(TextureFormat::Bc7.data_size(8, 9), 2 * 3 * 16);
+        assert_eq!(TextureFormat::Astc(6, 5).data_size(12, 11), 2 * 3 * 16);
+        assert_eq!(TextureFormat::Astc(12, 12).data_size(1, 1), 16);
+    }
+}
@@ -0,0 +1,108 @@
+use std::{error::Error, fmt, io};
+
+pub type TerraResult<T> = Result<T, TerraError>;
+
+// Everything that can go wrong setting up the renderer, loading assets or drawing a frame
+#[derive(Debug)]
+pub enum TerraError {
+    // No Vulkan driver, or no GPU that can draw to the window
+    NoDevice(String),
+    // The GPU was reset or removed, so the renderer has to be created again
+    DeviceLost,
+    // A Vulkan call failed, along with what it was trying to do
+    Vulkan {
+        action: &'static str,
+        message: String,
+    },
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    // An image or texture that couldn't be decoded
+    Image {
+        path: String,
+        message: String,
+    },
+    // A sprite meta file that can't be parsed
+    Meta {
+        path: String,
+        message: String,
+    },
+    // A shape whose path lyon couldn't turn into triangles, along with the part that failed
+    Tessellation {
+        part: &'static str,
+        message: String,
+    },
+    // A shader that's missing, invalid or doesn't fit the pipeline it's used in
+    Shader {
+        name: String,
+        message: String,
+    },
+}
+
+impl TerraError {
+    // For map_err, as in `.map_err(TerraError::vulkan("create the swapchain"))`
+    pub fn vulkan<E: fmt::Display>(action: &'static str) -> impl FnOnce(E) -> TerraError {
+        move |e| TerraError::Vulkan {
+            action,
+            message: e.to_string(),
+        }
+    }
+
+    pub fn image(path: &str, message: impl fmt::Display) -> TerraError {
+        TerraError::Image {
+            path: path.to_owned(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn meta(path: &str, message: impl fmt::Display) -> TerraError {
+        TerraError::Meta {
+            path: path.to_owned(),
+            message: message.to_string(),
+        }
+    }
+
+    // For map_err, as in `.map_err(TerraError::tessellation("fill"))`
+    pub fn tessellation<E: fmt::Debug>(part: &'static str) -> impl FnOnce(E) -> TerraError {
+        move |e| TerraError::Tessellation {
+            part,
+            message: format!("{e:?}"),
+        }
+    }
+
+    pub fn shader(name: &str, message: impl fmt::Display) -> TerraError {
+        TerraError::Shader {
+            name: name.to_owned(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for TerraError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerraError::NoDevice(reason) => write!(f, "No usable GPU: {reason}"),
+            TerraError::DeviceLost => write!(f, "The GPU was lost"),
+            TerraError::Vulkan { action, message } => write!(f, "Failed to {action}: {message}"),
+            TerraError::Io { path, source } => write!(f, "Failed to read {path}: {source}"),
+            TerraError::Image { path, message } => write!(f, "Failed to load {path}: {message}"),
+            TerraError::Meta { path, message } => {
+                write!(f, "Invalid sprite meta {path}: {message}")
+            }
+            TerraError::Tessellation { part, message } => {
+                write!(f, "Failed to tessellate the shape {part}: {message}")
+            }
+            TerraError::Shader { name, message } => write!(f, "Shader {name}: {message}"),
+        }
+    }
+}
+
+impl Error for TerraError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TerraError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
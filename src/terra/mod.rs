@@ -1,101 +1,174 @@
+pub mod assets;
+pub mod config;
 pub mod context;
 pub mod data;
+pub mod error;
 pub mod programs;
 pub mod resources;
 pub mod shader;
 pub mod util;
+#[cfg(feature = "hot-reload")]
+pub mod watcher;
+
+use crate::material::Material;
 
 use self::{
+    config::TerraConfig,
     context::GraphicsContext,
-    programs::sprite::SpriteRenderProgram,
+    data::{GlobalData, LightsData},
+    error::{TerraError, TerraResult},
+    programs::{
+        mesh::MeshRenderProgram, particle::ParticleRenderProgram, shadow::ShadowMapProgram,
+        shape::ShapeRenderProgram, sprite::SpriteRenderProgram, CommandBuilder,
+    },
     resources::{gpu::GpuResources, graphics::GraphicsResources},
+    shader::ShaderKind,
 };
-use std::{cell::RefCell, rc::Rc, sync::Arc};
+use std::{cell::RefCell, rc::Rc, sync::Arc, time::Instant};
+
 use vulkano::{
+    command_buffer::{PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents},
     instance::Instance,
-    swapchain::{self, AcquireError, SwapchainAcquireFuture, SwapchainPresentInfo},
-    sync::{FlushError, GpuFuture},
+    render_pass::Framebuffer,
+    swapchain::{self, AcquireError, PresentMode, SwapchainAcquireFuture, SwapchainPresentInfo},
+    sync::{self, future::FenceSignalFuture, FlushError, GpuFuture},
 };
 use winit::{event_loop::EventLoop, window::WindowId};
+#[cfg(feature = "debug-draw")]
+use {self::programs::debug::DebugRenderProgram, crate::debug_draw};
 
 type AcquireImageResult = (u32, bool, SwapchainAcquireFuture);
+type FrameFence = Arc<FenceSignalFuture<Box<dyn GpuFuture + Send + Sync>>>;
 
 pub struct Terra {
     _instance: Arc<Instance>,
     gpu_resources: Rc<RefCell<GpuResources>>,
-    _graphics_resources: Rc<RefCell<GraphicsResources>>,
+    graphics_resources: Rc<RefCell<GraphicsResources>>,
     graphics_context: Rc<RefCell<GraphicsContext>>,
+    shadow_program: ShadowMapProgram,
     sprite_program: SpriteRenderProgram,
+    mesh_program: MeshRenderProgram,
+    shape_program: ShapeRenderProgram,
+    particle_program: ParticleRenderProgram,
+    #[cfg(feature = "debug-draw")]
+    debug_program: DebugRenderProgram,
+    last_frame: Instant,
+    // Signalled when the GPU finishes the last submission of each frame in flight
+    frame_fences: Vec<Option<FrameFence>>,
+    previous_frame_end: Option<FrameFence>,
 }
 
 impl Terra {
-    pub fn init(events: &EventLoop<()>) -> Terra {
-        let instance = util::create_instance(&util::create_library());
-        let surface = util::create_surface(&instance, events);
-        let gpu_resources = Rc::new(RefCell::new(GpuResources::init(&instance, &surface)));
-        let graphics_resources = Rc::new(RefCell::new(GraphicsResources::new(&gpu_resources)));
+    pub fn init(events: &EventLoop<()>, config: &TerraConfig) -> TerraResult<Terra> {
+        let instance = util::create_instance(&util::create_library()?, config)?;
+        let surface = util::create_surface(&instance, events, config)?;
+        let gpu_resources = Rc::new(RefCell::new(GpuResources::init(
+            &instance, &surface, config,
+        )?));
+        let graphics_resources = Rc::new(RefCell::new(GraphicsResources::new(&gpu_resources)?));
         let graphics_context = Rc::new(RefCell::new(GraphicsContext::new(&graphics_resources)));
+        let shadow_program =
+            ShadowMapProgram::new(&graphics_context, &graphics_resources, &gpu_resources)?;
         let sprite_program =
-            SpriteRenderProgram::new(&graphics_context, &graphics_resources, &gpu_resources);
+            SpriteRenderProgram::new(&graphics_context, &graphics_resources, &gpu_resources)?;
+        let mesh_program =
+            MeshRenderProgram::new(&graphics_context, &graphics_resources, &gpu_resources)?;
+        let shape_program = ShapeRenderProgram::new(&graphics_context, &gpu_resources)?;
+        let particle_program =
+            ParticleRenderProgram::new(&graphics_context, &graphics_resources, &gpu_resources)?;
+        #[cfg(feature = "debug-draw")]
+        let debug_program = DebugRenderProgram::new(&gpu_resources)?;
+        let frame_fences = vec![None; gpu_resources.borrow().frames_in_flight()];
 
-        Terra {
+        Ok(Terra {
             _instance: instance,
             gpu_resources,
-            _graphics_resources: graphics_resources,
+            graphics_resources,
             graphics_context,
+            shadow_program,
             sprite_program,
-        }
+            mesh_program,
+            shape_program,
+            particle_program,
+            #[cfg(feature = "debug-draw")]
+            debug_program,
+            last_frame: Instant::now(),
+            frame_fences,
+            previous_frame_end: None,
+        })
     }
 }
 
 impl Terra {
-    pub fn render(&mut self) {
-        if let Some((image, mut suboptimal, acquire_future)) = self.acquire_swapchain_image() {
-            let resources = self.gpu_resources.borrow();
-            let framebuffer = &resources.frame_buffers()[image as usize];
-
-            if let Some(command_buffer) = self.sprite_program.draw(&framebuffer) {
-                let future = acquire_future
-                    .then_execute(resources.queue().clone(), command_buffer)
-                    .expect("Failed to execute command buffer")
-                    .then_swapchain_present(
-                        resources.queue().clone(),
-                        SwapchainPresentInfo::swapchain_image_index(
-                            resources.swapchain().clone(),
-                            image,
-                        ),
-                    )
-                    .then_signal_fence_and_flush();
-
-                match future {
-                    Ok(future) => future
-                        .wait(None)
-                        .expect("Fence failed to signal completion."),
-                    Err(FlushError::OutOfDate) => suboptimal = true,
-                    Err(FlushError::ResourceAccessError { error, use_ref }) => {
-                        let resource = use_ref.unwrap().resource_in_command;
-                        panic!("Access to resource denied {:?} {:?}", error, resource);
-                    }
-                    Err(e) => panic!("Failed to flush future: {e}"),
+    // An out of date swapchain is recreated here, any error returned means the frame was lost
+    pub fn render(&mut self) -> TerraResult<()> {
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+        self.graphics_context.borrow().update(delta);
+
+        let drawn = self.draw_frame();
+
+        // Timed debug shapes run out whether or not the frame could be drawn
+        #[cfg(feature = "debug-draw")]
+        debug_draw::end_frame(delta);
+
+        drawn
+    }
+
+    fn draw_frame(&mut self) -> TerraResult<()> {
+        self.reload_shaders();
+        self.update_assets()?;
+
+        if let Some((image, mut suboptimal, acquire_future)) = self.acquire_swapchain_image()? {
+            // Only waits when the CPU is a full set of frames ahead, the buffers of this frame
+            // can't be rewritten before the GPU is done with them
+            let frame = self.gpu_resources.borrow().frame_index();
+            if let Some(fence) = self.frame_fences[frame].take() {
+                fence.wait(None).map_err(util::flush_error)?;
+            }
+
+            let framebuffer = self.gpu_resources.borrow().frame_buffers()[image as usize].clone();
+            let command_buffer = self.record(&framebuffer)?;
+
+            let mut resources = self.gpu_resources.borrow_mut();
+            let previous_frame_end = match self.previous_frame_end.take() {
+                Some(fence) => fence.boxed_send_sync(),
+                None => sync::now(resources.device().clone()).boxed_send_sync(),
+            };
+
+            let future = previous_frame_end
+                .join(acquire_future)
+                .then_execute(resources.queue().clone(), command_buffer)
+                .map_err(TerraError::vulkan("execute the frame's command buffer"))?
+                .then_swapchain_present(
+                    resources.queue().clone(),
+                    SwapchainPresentInfo::swapchain_image_index(
+                        resources.swapchain().clone(),
+                        image,
+                    ),
+                )
+                .boxed_send_sync()
+                .then_signal_fence_and_flush();
+
+            match future {
+                Ok(future) => {
+                    let fence = Arc::new(future);
+                    self.frame_fences[frame] = Some(fence.clone());
+                    self.previous_frame_end = Some(fence);
                 }
-            } else {
-                let present_info = SwapchainPresentInfo::swapchain_image_index(
-                    resources.swapchain().clone(),
-                    image,
-                );
-                let _ = acquire_future
-                    .then_swapchain_present(resources.queue().clone(), present_info)
-                    .then_signal_fence_and_flush()
-                    .unwrap()
-                    .wait(None);
+                Err(FlushError::OutOfDate) => suboptimal = true,
+                Err(e) => return Err(util::flush_error(e)),
             }
 
+            resources.next_frame();
+
             if !suboptimal {
-                return;
+                return Ok(());
             }
         }
 
-        self.gpu_resources.borrow_mut().recreate_swapchain();
+        self.gpu_resources.borrow_mut().recreate_swapchain()
     }
 
     pub fn window_id(&self) -> WindowId {
@@ -106,19 +179,191 @@ impl Terra {
         &self.graphics_context
     }
 
+    pub fn create_material(&self, fragment_shader: &str) -> TerraResult<Material> {
+        Material::new(self.gpu_resources.borrow().shaders(), fragment_shader)
+    }
+
+    pub fn create_material_with_vertex_shader(
+        &self,
+        vertex_shader: &str,
+        fragment_shader: &str,
+    ) -> TerraResult<Material> {
+        let resources = self.gpu_resources.borrow();
+        Material::with_vertex_shader(resources.shaders(), vertex_shader, fragment_shader)
+    }
+
+    fn reload_shaders(&mut self) {
+        let changed = {
+            let mut resources = self.gpu_resources.borrow_mut();
+            let device = resources.device().clone();
+            resources.shaders_mut().reload(&device)
+        };
+
+        if changed.is_empty() || self.reload_programs(&changed) {
+            return;
+        }
+
+        // Pipelines that did link are rebuilt again with the previous shaders, so every pipeline
+        // including the ones built later uses the same modules
+        eprintln!("Keeping the previous shaders until the changes link");
+        let reverted = self.gpu_resources.borrow_mut().shaders_mut().revert();
+        self.reload_programs(&reverted);
+    }
+
+    // False if any program couldn't rebuild its pipelines with the changed shaders
+    fn reload_programs(&mut self, changed: &[(ShaderKind, String)]) -> bool {
+        let mut linked = self.shadow_program.reload(changed);
+        linked &= self.sprite_program.reload(changed);
+        linked &= self.mesh_program.reload(changed);
+        linked &= self.shape_program.reload(changed);
+        linked &= self.particle_program.reload(changed);
+
+        #[cfg(feature = "debug-draw")]
+        {
+            linked &= self.debug_program.reload(changed);
+        }
+
+        linked
+    }
+
+    // Uploads assets that finished loading or were changed on disk and frees the ones that lost
+    // their last handle
+    fn update_assets(&mut self) -> TerraResult<()> {
+        let released = {
+            let mut graphics_resources = self.graphics_resources.borrow_mut();
+            graphics_resources.reload_changed();
+
+            let mut released = graphics_resources.upload_loaded()?;
+            released.extend(graphics_resources.collect());
+            released
+        };
+
+        if !released.is_empty() {
+            self.sprite_program.release(&released);
+            self.mesh_program.release(&released);
+            self.particle_program.release(&released);
+        }
+
+        Ok(())
+    }
+
+    // Records the shadow map pass, then every other program into a single render pass
+    fn record(&mut self, framebuffer: &Arc<Framebuffer>) -> TerraResult<PrimaryAutoCommandBuffer> {
+        self.update_global_buffer()?;
+        self.update_lights_buffer()?;
+
+        let mut builder: CommandBuilder = {
+            let resources = self.gpu_resources.borrow();
+            util::create_command_builder(resources.frame_command_buffer_alloc(), resources.queue())?
+        };
+
+        self.shadow_program.dispatch(&mut builder)?;
+
+        let clear = *self
+            .graphics_context
+            .borrow()
+            .camera()
+            .borrow()
+            .clear()
+            .get();
+        // Only the first attachment is cleared, a resolve target is overwritten by the resolve
+        let mut clear_values = vec![None; framebuffer.attachments().len()];
+        clear_values[0] = Some([clear[0], clear[1], clear[2], 1.0].into());
+
+        let render_pass_begin_info = RenderPassBeginInfo {
+            clear_values,
+            ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
+        };
+
+        builder
+            .begin_render_pass(render_pass_begin_info, SubpassContents::Inline)
+            .map_err(TerraError::vulkan("begin the render pass"))?
+            .set_viewport(0, [self.gpu_resources.borrow().viewport().clone()]);
+
+        // Sprites, meshes and shapes are interleaved by sorting order
+        let orders = self.graphics_context.borrow().sorting_orders();
+        self.sprite_program.begin_frame();
+        self.mesh_program.begin_frame();
+        self.shape_program.begin_frame();
+
+        for order in orders {
+            self.sprite_program.draw(&mut builder, order)?;
+            self.mesh_program.draw(&mut builder, order)?;
+            self.shape_program.draw(&mut builder, order)?;
+        }
+
+        self.sprite_program.end_frame();
+        self.mesh_program.end_frame();
+        self.shape_program.end_frame();
+        self.particle_program.draw(&mut builder)?;
+
+        #[cfg(feature = "debug-draw")]
+        self.debug_program.draw(&mut builder)?;
+
+        builder
+            .end_render_pass()
+            .map_err(TerraError::vulkan("end the render pass"))?;
+
+        builder
+            .build()
+            .map_err(TerraError::vulkan("build the frame's command buffer"))
+    }
+
+    fn update_global_buffer(&self) -> TerraResult<()> {
+        let resources = self.gpu_resources.borrow();
+        let context = self.graphics_context.borrow();
+        let camera = context.camera().borrow();
+
+        let extent = resources.swapchain().image_extent();
+        let aspect = (extent[0] as f32) / (extent[1] as f32);
+        let ortho = camera.ortho(aspect);
+        let view = camera.transform().matrix();
+        let global_data = GlobalData::new(ortho, view);
+
+        *resources
+            .global_buffer()
+            .write()
+            .map_err(TerraError::vulkan("write the global buffer"))? = global_data;
+        Ok(())
+    }
+
+    fn update_lights_buffer(&self) -> TerraResult<()> {
+        let context = self.graphics_context.borrow();
+        let data = LightsData::new(context.ambient(), context.lights());
+
+        self.graphics_resources.borrow_mut().update_lights(data)
+    }
+
     // Returns None if the swapchain needs to be recreated
-    fn acquire_swapchain_image(&self) -> Option<AcquireImageResult> {
+    fn acquire_swapchain_image(&self) -> TerraResult<Option<AcquireImageResult>> {
         let resources = self.gpu_resources.borrow();
         let swapchain = resources.swapchain();
 
         match swapchain::acquire_next_image(swapchain.clone(), None) {
-            Ok(r) => Some(r),
-            Err(AcquireError::OutOfDate) => None,
-            Err(e) => panic!("Failed to acquire next swapchain image: {e}"),
+            Ok(r) => Ok(Some(r)),
+            Err(AcquireError::OutOfDate) => Ok(None),
+            Err(AcquireError::DeviceLost) => Err(TerraError::DeviceLost),
+            Err(e) => Err(TerraError::vulkan("acquire the next swapchain image")(e)),
         }
     }
 
-    pub fn recreate_swapchain(&mut self) {
+    pub fn recreate_swapchain(&mut self) -> TerraResult<()> {
         self.gpu_resources.borrow_mut().recreate_swapchain()
     }
+
+    // MSAA samples per pixel actually used, which can be fewer than TerraConfig asked for
+    pub fn samples(&self) -> u32 {
+        self.gpu_resources.borrow().samples() as u32
+    }
+
+    // The mode actually used, which is Fifo when the one asked for isn't supported
+    pub fn present_mode(&self) -> PresentMode {
+        self.gpu_resources.borrow().swapchain().present_mode()
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> TerraResult<()> {
+        self.gpu_resources
+            .borrow_mut()
+            .set_present_mode(present_mode)
+    }
 }
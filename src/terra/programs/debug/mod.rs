@@ -0,0 +1,153 @@
+use crate::{
+    debug_draw,
+    terra::{
+        data::{BlendMode, DebugVertex},
+        error::{TerraError, TerraResult},
+        programs::CommandBuilder,
+        resources::gpu::GpuResources,
+        shader::ShaderKind,
+        util,
+    },
+};
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+use vulkano::{
+    buffer::BufferUsage,
+    descriptor_set::PersistentDescriptorSet,
+    memory::allocator::MemoryUsage,
+    pipeline::{
+        graphics::{
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            vertex_input::Vertex as BaseVertex,
+            viewport::ViewportState,
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+};
+
+// Draws the shapes queued through debug_draw on top of everything else
+pub struct DebugRenderProgram {
+    gpu_resources: Rc<RefCell<GpuResources>>,
+    line_pipeline: Arc<GraphicsPipeline>,
+    triangle_pipeline: Arc<GraphicsPipeline>,
+    global_descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+}
+
+impl DebugRenderProgram {
+    pub fn new(gpu_resources: &Rc<RefCell<GpuResources>>) -> TerraResult<DebugRenderProgram> {
+        let line_pipeline = create_pipeline(gpu_resources, PrimitiveTopology::LineList)?;
+        let triangle_pipeline = create_pipeline(gpu_resources, PrimitiveTopology::TriangleList)?;
+        let layout = &line_pipeline.layout().set_layouts()[0];
+        let global_descriptor_sets = gpu_resources
+            .borrow()
+            .create_global_descriptor_sets(layout)?;
+
+        Ok(DebugRenderProgram {
+            gpu_resources: gpu_resources.clone(),
+            line_pipeline,
+            triangle_pipeline,
+            global_descriptor_sets,
+        })
+    }
+
+    pub fn draw(&self, builder: &mut CommandBuilder) -> TerraResult<()> {
+        let (lines, triangles) = debug_draw::vertices();
+        let frame = self.gpu_resources.borrow().frame_index();
+
+        // Filled shapes go first so outlines drawn over them stay visible
+        for (pipeline, vertices) in [
+            (&self.triangle_pipeline, triangles),
+            (&self.line_pipeline, lines),
+        ] {
+            if vertices.is_empty() {
+                continue;
+            }
+
+            let vertex_count = vertices.len() as u32;
+            let vertex_buffer = util::buffer_from_iter(
+                self.gpu_resources.borrow().memory_alloc(),
+                vertices,
+                BufferUsage::VERTEX_BUFFER,
+                MemoryUsage::Upload,
+            )?;
+
+            builder
+                .bind_pipeline_graphics(pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    pipeline.layout().clone(),
+                    0,
+                    self.global_descriptor_sets[frame].clone(),
+                )
+                .bind_vertex_buffers(0, vertex_buffer);
+
+            builder
+                .draw(vertex_count, 1, 0, 0)
+                .map_err(TerraError::vulkan("record a debug draw"))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn reload(&mut self, changed: &[(ShaderKind, String)]) -> bool {
+        let affected = changed.iter().any(|(kind, name)| match kind {
+            ShaderKind::Vertex => name == "debug",
+            ShaderKind::Fragment => name == "debug",
+            ShaderKind::Compute => false,
+        });
+
+        if !affected {
+            return true;
+        }
+
+        let pipelines =
+            create_pipeline(&self.gpu_resources, PrimitiveTopology::LineList).and_then(|lines| {
+                let triangles =
+                    create_pipeline(&self.gpu_resources, PrimitiveTopology::TriangleList)?;
+                let layout = &lines.layout().set_layouts()[0];
+                let set = self
+                    .gpu_resources
+                    .borrow()
+                    .create_global_descriptor_sets(layout)?;
+                Ok((lines, triangles, set))
+            });
+
+        match pipelines {
+            Ok((line_pipeline, triangle_pipeline, global_descriptor_sets)) => {
+                self.global_descriptor_sets = global_descriptor_sets;
+                self.line_pipeline = line_pipeline;
+                self.triangle_pipeline = triangle_pipeline;
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to rebuild debug pipelines: {e}");
+                false
+            }
+        }
+    }
+}
+
+fn create_pipeline(
+    resources: &Rc<RefCell<GpuResources>>,
+    topology: PrimitiveTopology,
+) -> TerraResult<Arc<GraphicsPipeline>> {
+    let resources = resources.borrow();
+    let device = resources.device();
+    let render_pass = resources.render_pass();
+    let shaders = resources.shaders();
+
+    let vs = shaders.entry_point(ShaderKind::Vertex, "debug")?;
+    let fs = shaders.entry_point(ShaderKind::Fragment, "debug")?;
+    let subpass = render_pass.clone().first_subpass();
+
+    GraphicsPipeline::start()
+        .vertex_input_state(DebugVertex::per_vertex())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .multisample_state(util::multisample_state(&subpass))
+        .render_pass(subpass)
+        .vertex_shader(vs, ())
+        .fragment_shader(fs, ())
+        .input_assembly_state(InputAssemblyState::new().topology(topology))
+        .color_blend_state(BlendMode::Alpha.color_blend_state())
+        .build(device.clone())
+        .map_err(TerraError::vulkan("build the debug pipeline"))
+}
@@ -0,0 +1,11 @@
+use vulkano::buffer::Subbuffer;
+
+use crate::terra::data::MeshVertex;
+
+pub type MeshBuffers = (Subbuffer<[MeshVertex]>, Subbuffer<[u32]>);
+
+// Uploaded copy of a renderer's mesh, replaced when the renderer's revision changes
+pub struct UploadedMesh {
+    pub revision: u64,
+    pub buffers: Option<MeshBuffers>,
+}
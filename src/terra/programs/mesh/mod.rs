@@ -0,0 +1,340 @@
+pub mod data;
+
+use crate::{
+    mesh::MeshRenderer,
+    terra::{
+        assets::Handle,
+        context::GraphicsContext,
+        data::{BlendMode, MeshVertex, SamplerSettings},
+        error::{TerraError, TerraResult},
+        programs::{
+            mesh::data::{MeshBuffers, UploadedMesh},
+            sprite::data::PerObject,
+            CommandBuilder,
+        },
+        resources::{
+            gpu::GpuResources,
+            graphics::{GraphicsResources, SpriteResources},
+        },
+        shader::ShaderKind,
+        util,
+    },
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+use vulkano::{
+    buffer::BufferUsage,
+    descriptor_set::PersistentDescriptorSet,
+    memory::allocator::MemoryUsage,
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState, vertex_input::Vertex as BaseVertex,
+            viewport::ViewportState,
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+};
+
+pub struct MeshRenderProgram {
+    context: Rc<RefCell<GraphicsContext>>,
+    graphics_resources: Rc<RefCell<GraphicsResources>>,
+    gpu_resources: Rc<RefCell<GpuResources>>,
+    pipelines: HashMap<BlendMode, Arc<GraphicsPipeline>>,
+    global_descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    // Keyed by sprite id, None is the white texture used by meshes without a sprite
+    image_sets: HashMap<(Option<u64>, SamplerSettings), Arc<PersistentDescriptorSet>>,
+    meshes: HashMap<u64, UploadedMesh>,
+    queue: Vec<Rc<RefCell<MeshRenderer>>>,
+    // Keeps the sprites of this frame's meshes resident
+    textures: Vec<Handle<SpriteResources>>,
+}
+
+impl MeshRenderProgram {
+    pub fn new(
+        context: &Rc<RefCell<GraphicsContext>>,
+        graphics_resources: &Rc<RefCell<GraphicsResources>>,
+        gpu_resources: &Rc<RefCell<GpuResources>>,
+    ) -> TerraResult<MeshRenderProgram> {
+        let pipeline = create_pipeline(gpu_resources, BlendMode::default())?;
+        let layout = &pipeline.layout().set_layouts()[0];
+        let global_descriptor_sets = gpu_resources
+            .borrow()
+            .create_global_descriptor_sets(layout)?;
+
+        let mut pipelines = HashMap::new();
+        pipelines.insert(BlendMode::default(), pipeline);
+
+        Ok(MeshRenderProgram {
+            context: context.clone(),
+            graphics_resources: graphics_resources.clone(),
+            gpu_resources: gpu_resources.clone(),
+            pipelines,
+            global_descriptor_sets,
+            image_sets: HashMap::new(),
+            meshes: HashMap::new(),
+            queue: vec![],
+            textures: vec![],
+        })
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.queue = self.context.borrow().mesh_renderers().clone();
+        self.queue
+            .sort_by_key(|renderer| renderer.borrow().sorting_order());
+
+        self.load_textures();
+    }
+
+    // Draws the meshes in one sorting order
+    pub fn draw(&mut self, builder: &mut CommandBuilder, order: i32) -> TerraResult<()> {
+        let queue = std::mem::take(&mut self.queue);
+        let start = queue.partition_point(|renderer| renderer.borrow().sorting_order() < order);
+        let end = queue.partition_point(|renderer| renderer.borrow().sorting_order() <= order);
+
+        let result = self.draw_queued(builder, &queue[start..end]);
+        self.queue = queue;
+        result
+    }
+
+    fn draw_queued(
+        &mut self,
+        builder: &mut CommandBuilder,
+        queue: &[Rc<RefCell<MeshRenderer>>],
+    ) -> TerraResult<()> {
+        let frame = self.gpu_resources.borrow().frame_index();
+        let mut bound_pipeline = None;
+        let mut bound_texture = None;
+
+        for renderer in queue.iter() {
+            let renderer = renderer.borrow();
+            let (vertex_buffer, index_buffer) = match self.get_or_create_mesh(&renderer)? {
+                Some(buffers) => buffers.clone(),
+                None => continue,
+            };
+
+            let texture = match renderer.sprite() {
+                Some(sprite) => (Some(sprite.id()), sprite.sampler()),
+                None => (None, SamplerSettings::default()),
+            };
+            let set = match self.get_or_create_image_set(texture)? {
+                Some(set) => set,
+                None => continue,
+            };
+
+            let blend_mode = renderer
+                .sprite()
+                .map(|sprite| sprite.blend_mode())
+                .unwrap_or_default();
+            let pipeline = self.get_or_create_pipeline(blend_mode)?;
+            let layout = pipeline.layout().clone();
+
+            if bound_pipeline != Some(blend_mode) {
+                builder.bind_pipeline_graphics(pipeline);
+                bound_pipeline = Some(blend_mode);
+                bound_texture = None;
+            }
+
+            if bound_texture != Some(texture) {
+                builder.bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    layout.clone(),
+                    0,
+                    vec![self.global_descriptor_sets[frame].clone(), set],
+                );
+                bound_texture = Some(texture);
+            }
+
+            let model = util::mat4_to_array(renderer.transform().matrix());
+            let index_count = index_buffer.len() as u32;
+
+            builder
+                .push_constants(layout, 0, PerObject::new(model, renderer.color()))
+                .bind_vertex_buffers(0, vertex_buffer)
+                .bind_index_buffer(index_buffer);
+
+            builder
+                .draw_indexed(index_count, 1, 0, 0, 0)
+                .map_err(TerraError::vulkan("record a mesh draw"))?;
+        }
+
+        Ok(())
+    }
+
+    // Drops the meshes of renderers that were removed
+    pub fn end_frame(&mut self) {
+        let queue = std::mem::take(&mut self.queue);
+        self.meshes
+            .retain(|id, _| queue.iter().any(|renderer| renderer.borrow().id() == *id));
+    }
+
+    pub fn reload(&mut self, changed: &[(ShaderKind, String)]) -> bool {
+        let affected = changed.iter().any(|(kind, name)| match kind {
+            ShaderKind::Vertex => name == "mesh",
+            ShaderKind::Fragment => name == "sprite",
+            ShaderKind::Compute => false,
+        });
+
+        if !affected {
+            return true;
+        }
+
+        let rebuilt =
+            create_pipeline(&self.gpu_resources, BlendMode::default()).and_then(|pipeline| {
+                let layout = &pipeline.layout().set_layouts()[0];
+                let set = self
+                    .gpu_resources
+                    .borrow()
+                    .create_global_descriptor_sets(layout)?;
+                Ok((pipeline, set))
+            });
+
+        match rebuilt {
+            Ok((pipeline, global_descriptor_sets)) => {
+                self.global_descriptor_sets = global_descriptor_sets;
+                self.pipelines.clear();
+                self.pipelines.insert(BlendMode::default(), pipeline);
+                self.image_sets.clear();
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to rebuild mesh pipeline: {e}");
+                false
+            }
+        }
+    }
+
+    // Sprite textures are uploaded the first time a mesh using them is drawn and freed once no
+    // renderer uses them anymore
+    fn load_textures(&mut self) {
+        let mut graphics_resources = self.graphics_resources.borrow_mut();
+
+        self.textures = self
+            .queue
+            .iter()
+            .filter_map(|renderer| {
+                let renderer = renderer.borrow();
+                let sprite = renderer.sprite()?;
+                Some(graphics_resources.load_sprite(sprite))
+            })
+            .collect();
+    }
+
+    // Drops the descriptor sets of sprites that were freed or replaced
+    pub fn release(&mut self, ids: &[u64]) {
+        self.image_sets
+            .retain(|(id, _), _| !id.is_some_and(|id| ids.contains(&id)));
+    }
+
+    fn get_or_create_pipeline(
+        &mut self,
+        blend_mode: BlendMode,
+    ) -> TerraResult<Arc<GraphicsPipeline>> {
+        if let Some(pipeline) = self.pipelines.get(&blend_mode) {
+            return Ok(pipeline.clone());
+        }
+
+        let pipeline = create_pipeline(&self.gpu_resources, blend_mode)?;
+        self.pipelines.insert(blend_mode, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    // None while the mesh's sprite is still loading
+    fn get_or_create_image_set(
+        &mut self,
+        texture: (Option<u64>, SamplerSettings),
+    ) -> TerraResult<Option<Arc<PersistentDescriptorSet>>> {
+        if let Some(set) = self.image_sets.get(&texture) {
+            return Ok(Some(set.clone()));
+        }
+
+        let graphics_resources = self.graphics_resources.borrow();
+        let image = match texture.0 {
+            Some(id) => match graphics_resources.sprite(&id) {
+                Some(sprite) => sprite.image(),
+                None => return Ok(None),
+            },
+            None => graphics_resources.white_texture(),
+        };
+
+        let resources = self.gpu_resources.borrow();
+        let layout = &self.pipelines[&BlendMode::default()].layout().set_layouts()[1];
+        let set = util::create_image_descriptor_set(
+            resources.descriptor_set_alloc(),
+            layout,
+            image,
+            &resources.sampler(texture.1)?,
+        )?;
+
+        self.image_sets.insert(texture, set.clone());
+        Ok(Some(set))
+    }
+
+    // Meshes are uploaded again whenever their renderer's revision changes. Empty meshes have no
+    // buffers.
+    fn get_or_create_mesh(&mut self, renderer: &MeshRenderer) -> TerraResult<Option<&MeshBuffers>> {
+        let valid = self
+            .meshes
+            .get(&renderer.id())
+            .is_some_and(|mesh| mesh.revision == renderer.revision());
+
+        if !valid {
+            let mesh = renderer.mesh();
+
+            let buffers = if mesh.indices.is_empty() || mesh.vertices.is_empty() {
+                None
+            } else {
+                let resources = self.gpu_resources.borrow();
+                let allocator = resources.memory_alloc();
+
+                Some((
+                    util::buffer_from_iter(
+                        allocator,
+                        mesh.vertices.iter().copied(),
+                        BufferUsage::VERTEX_BUFFER,
+                        MemoryUsage::Upload,
+                    )?,
+                    util::buffer_from_iter(
+                        allocator,
+                        mesh.indices.iter().copied(),
+                        BufferUsage::INDEX_BUFFER,
+                        MemoryUsage::Upload,
+                    )?,
+                ))
+            };
+
+            let mesh = UploadedMesh {
+                revision: renderer.revision(),
+                buffers,
+            };
+            self.meshes.insert(renderer.id(), mesh);
+        }
+
+        Ok(self.meshes[&renderer.id()].buffers.as_ref())
+    }
+}
+
+// Meshes reuse the sprite fragment shader, so they sample their texture exactly like sprites
+fn create_pipeline(
+    resources: &Rc<RefCell<GpuResources>>,
+    blend_mode: BlendMode,
+) -> TerraResult<Arc<GraphicsPipeline>> {
+    let resources = resources.borrow();
+    let device = resources.device();
+    let render_pass = resources.render_pass();
+    let shaders = resources.shaders();
+
+    let vs = shaders.entry_point(ShaderKind::Vertex, "mesh")?;
+    let fs = shaders.entry_point(ShaderKind::Fragment, "sprite")?;
+    let subpass = render_pass.clone().first_subpass();
+
+    GraphicsPipeline::start()
+        .vertex_input_state(MeshVertex::per_vertex())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .multisample_state(util::multisample_state(&subpass))
+        .render_pass(subpass)
+        .vertex_shader(vs, ())
+        .fragment_shader(fs, ())
+        .input_assembly_state(InputAssemblyState::default())
+        .color_blend_state(blend_mode.color_blend_state())
+        .build(device.clone())
+        .map_err(TerraError::vulkan("build the mesh pipeline"))
+}
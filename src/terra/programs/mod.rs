@@ -1 +1,15 @@
+#[cfg(feature = "debug-draw")]
+pub mod debug;
+pub mod mesh;
+pub mod particle;
+pub mod shadow;
+pub mod shape;
 pub mod sprite;
+
+use std::sync::Arc;
+use vulkano::command_buffer::{
+    allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, PrimaryAutoCommandBuffer,
+};
+
+pub type CommandBuilder =
+    AutoCommandBufferBuilder<PrimaryAutoCommandBuffer, Arc<StandardCommandBufferAllocator>>;
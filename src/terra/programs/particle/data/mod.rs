@@ -0,0 +1,21 @@
+use vulkano::buffer::BufferContents;
+use vulkano::pipeline::graphics::vertex_input::Vertex;
+
+#[derive(BufferContents, Vertex, Clone, Copy, Debug)]
+#[repr(C)]
+pub struct ParticleInstance {
+    #[format(R32G32_SFLOAT)]
+    pub position: [f32; 2],
+    #[format(R32_SFLOAT)]
+    pub rotation: f32,
+    #[format(R32_SFLOAT)]
+    pub size: f32,
+    #[format(R32G32B32A32_SFLOAT)]
+    pub color: [f32; 4],
+}
+
+#[derive(BufferContents)]
+#[repr(C)]
+pub struct PerEmitter {
+    pub model: [f32; 16],
+}
@@ -0,0 +1,252 @@
+pub mod data;
+
+use crate::{
+    particle::emitter::SimulationSpace,
+    terra::{
+        context::GraphicsContext,
+        data::{BlendMode, SamplerSettings, Vertex},
+        error::{TerraError, TerraResult},
+        programs::{
+            particle::data::{ParticleInstance, PerEmitter},
+            CommandBuilder,
+        },
+        resources::{gpu::GpuResources, graphics::GraphicsResources},
+        shader::ShaderKind,
+        util,
+    },
+};
+use nalgebra_glm::Mat4;
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+use vulkano::{
+    buffer::BufferUsage,
+    descriptor_set::PersistentDescriptorSet,
+    image::{view::ImageView, ImmutableImage},
+    memory::allocator::MemoryUsage,
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState, vertex_input::Vertex as BaseVertex,
+            viewport::ViewportState,
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+};
+
+pub struct ParticleRenderProgram {
+    context: Rc<RefCell<GraphicsContext>>,
+    graphics_resources: Rc<RefCell<GraphicsResources>>,
+    gpu_resources: Rc<RefCell<GpuResources>>,
+    pipelines: HashMap<BlendMode, Arc<GraphicsPipeline>>,
+    global_descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    sprite_descriptor_sets: HashMap<(u64, SamplerSettings), Arc<PersistentDescriptorSet>>,
+}
+
+impl ParticleRenderProgram {
+    pub fn new(
+        context: &Rc<RefCell<GraphicsContext>>,
+        graphics_resources: &Rc<RefCell<GraphicsResources>>,
+        gpu_resources: &Rc<RefCell<GpuResources>>,
+    ) -> TerraResult<ParticleRenderProgram> {
+        let pipeline = create_pipeline(gpu_resources, BlendMode::default())?;
+        let layout = &pipeline.layout().set_layouts()[0];
+        let global_descriptor_sets = gpu_resources
+            .borrow()
+            .create_global_descriptor_sets(layout)?;
+
+        let mut pipelines = HashMap::new();
+        pipelines.insert(BlendMode::default(), pipeline);
+
+        Ok(ParticleRenderProgram {
+            context: context.clone(),
+            graphics_resources: graphics_resources.clone(),
+            gpu_resources: gpu_resources.clone(),
+            pipelines,
+            global_descriptor_sets,
+            sprite_descriptor_sets: HashMap::new(),
+        })
+    }
+
+    // Drops the descriptor sets of sprites that were freed or replaced
+    pub fn release(&mut self, ids: &[u64]) {
+        self.sprite_descriptor_sets
+            .retain(|(id, _), _| !ids.contains(id));
+    }
+
+    pub fn draw(&mut self, builder: &mut CommandBuilder) -> TerraResult<()> {
+        let graphics_resources = self.graphics_resources.clone();
+        let graphics_resources = graphics_resources.borrow();
+        let index_buffer = graphics_resources.sprite_index_buffer();
+        let frame = self.gpu_resources.borrow().frame_index();
+
+        let context = self.context.clone();
+        let context = context.borrow();
+
+        for emitter in context.particle_emitters().iter() {
+            let emitter = emitter.borrow();
+            let sprite = emitter.sprite();
+
+            if emitter.particles().is_empty() {
+                continue;
+            }
+
+            let resources = match graphics_resources.sprite(&sprite.id()) {
+                Some(resources) => resources,
+                None => continue,
+            };
+
+            let instances = emitter
+                .particles()
+                .iter()
+                .map(|particle| {
+                    let progress = particle.progress();
+
+                    ParticleInstance {
+                        position: [particle.position.x, particle.position.y],
+                        rotation: particle.rotation,
+                        size: particle.size * emitter.size_over_lifetime().evaluate(progress),
+                        color: emitter.color_over_lifetime().evaluate(progress),
+                    }
+                })
+                .collect::<Vec<_>>();
+            let instance_count = instances.len() as u32;
+
+            let instance_buffer = util::buffer_from_iter(
+                self.gpu_resources.borrow().memory_alloc(),
+                instances,
+                BufferUsage::VERTEX_BUFFER,
+                MemoryUsage::Upload,
+            )?;
+
+            let model = match emitter.simulation_space() {
+                SimulationSpace::Local => emitter.transform().matrix(),
+                SimulationSpace::World => Mat4::identity(),
+            };
+
+            let set =
+                self.get_or_create_image_set(&sprite.id(), sprite.sampler(), resources.image())?;
+            let pipeline = self.get_or_create_pipeline(sprite.blend_mode())?;
+            let layout = pipeline.layout().clone();
+
+            builder
+                .bind_pipeline_graphics(pipeline)
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Graphics,
+                    layout.clone(),
+                    0,
+                    vec![self.global_descriptor_sets[frame].clone(), set],
+                )
+                .push_constants(
+                    layout,
+                    0,
+                    PerEmitter {
+                        model: util::mat4_to_array(model),
+                    },
+                )
+                .bind_vertex_buffers(0, (resources.vertex_buffer().clone(), instance_buffer))
+                .bind_index_buffer(index_buffer.clone());
+
+            builder
+                .draw_indexed(6, instance_count, 0, 0, 0)
+                .map_err(TerraError::vulkan("record a particle draw"))?;
+        }
+
+        Ok(())
+    }
+
+    fn get_or_create_pipeline(
+        &mut self,
+        blend_mode: BlendMode,
+    ) -> TerraResult<Arc<GraphicsPipeline>> {
+        if let Some(pipeline) = self.pipelines.get(&blend_mode) {
+            return Ok(pipeline.clone());
+        }
+
+        let pipeline = create_pipeline(&self.gpu_resources, blend_mode)?;
+        self.pipelines.insert(blend_mode, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    // Rebuilds the pipelines after their shaders were hot reloaded, keeping the old ones if the
+    // new shaders can't be linked
+    pub fn reload(&mut self, changed: &[(ShaderKind, String)]) -> bool {
+        let affected = changed.iter().any(|(kind, name)| match kind {
+            ShaderKind::Vertex => name == "particle",
+            ShaderKind::Fragment => name == "sprite",
+            ShaderKind::Compute => false,
+        });
+
+        if !affected {
+            return true;
+        }
+
+        let rebuilt =
+            create_pipeline(&self.gpu_resources, BlendMode::default()).and_then(|pipeline| {
+                let layout = &pipeline.layout().set_layouts()[0];
+                let set = self
+                    .gpu_resources
+                    .borrow()
+                    .create_global_descriptor_sets(layout)?;
+                Ok((pipeline, set))
+            });
+
+        match rebuilt {
+            Ok((pipeline, global_descriptor_sets)) => {
+                self.global_descriptor_sets = global_descriptor_sets;
+                self.pipelines.clear();
+                self.pipelines.insert(BlendMode::default(), pipeline);
+                self.sprite_descriptor_sets.clear();
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to rebuild particle pipeline: {e}");
+                false
+            }
+        }
+    }
+
+    fn get_or_create_image_set(
+        &mut self,
+        id: &u64,
+        settings: SamplerSettings,
+        image: &Arc<ImageView<ImmutableImage>>,
+    ) -> TerraResult<Arc<PersistentDescriptorSet>> {
+        if let Some(set) = self.sprite_descriptor_sets.get(&(*id, settings)) {
+            Ok(set.clone())
+        } else {
+            let resources = self.gpu_resources.borrow();
+            let allocator = resources.descriptor_set_alloc();
+            let layout = &self.pipelines[&BlendMode::default()].layout().set_layouts()[1];
+            let sampler = resources.sampler(settings)?;
+
+            let set = util::create_image_descriptor_set(allocator, layout, image, &sampler)?;
+            self.sprite_descriptor_sets
+                .insert((*id, settings), set.clone());
+            Ok(set)
+        }
+    }
+}
+
+fn create_pipeline(
+    resources: &Rc<RefCell<GpuResources>>,
+    blend_mode: BlendMode,
+) -> TerraResult<Arc<GraphicsPipeline>> {
+    let resources = resources.borrow();
+    let device = resources.device();
+    let render_pass = resources.render_pass();
+    let shaders = resources.shaders();
+
+    let vs = shaders.entry_point(ShaderKind::Vertex, "particle")?;
+    let fs = shaders.entry_point(ShaderKind::Fragment, "sprite")?;
+    let subpass = render_pass.clone().first_subpass();
+
+    GraphicsPipeline::start()
+        .vertex_input_state([Vertex::per_vertex(), ParticleInstance::per_instance()])
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .multisample_state(util::multisample_state(&subpass))
+        .render_pass(subpass)
+        .vertex_shader(vs, ())
+        .fragment_shader(fs, ())
+        .input_assembly_state(InputAssemblyState::default())
+        .color_blend_state(blend_mode.color_blend_state())
+        .build(device.clone())
+        .map_err(TerraError::vulkan("build the particle pipeline"))
+}
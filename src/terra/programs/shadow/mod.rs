@@ -0,0 +1,135 @@
+use crate::{
+    light::Shadows,
+    terra::{
+        context::GraphicsContext,
+        data::{MAX_LIGHTS, SHADOW_MAP_RESOLUTION},
+        error::{TerraError, TerraResult},
+        programs::CommandBuilder,
+        resources::{gpu::GpuResources, graphics::GraphicsResources},
+        shader::ShaderKind,
+        util,
+    },
+};
+use std::{cell::RefCell, rc::Rc, sync::Arc};
+use vulkano::{
+    buffer::{BufferContents, BufferUsage},
+    descriptor_set::WriteDescriptorSet,
+    memory::allocator::MemoryUsage,
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+};
+
+// Must match local_size_x in shadow.cs
+const GROUP_SIZE: u32 = 64;
+
+#[derive(BufferContents)]
+#[repr(C)]
+struct ShadowConstants {
+    segment_count: u32,
+}
+
+// Renders the occluders into the shadow map before the lit sprites sample it. Every light gets
+// a row, so the cost grows with lights times occluder edges rather than with screen size.
+pub struct ShadowMapProgram {
+    context: Rc<RefCell<GraphicsContext>>,
+    graphics_resources: Rc<RefCell<GraphicsResources>>,
+    gpu_resources: Rc<RefCell<GpuResources>>,
+    pipeline: Arc<ComputePipeline>,
+}
+
+impl ShadowMapProgram {
+    pub fn new(
+        context: &Rc<RefCell<GraphicsContext>>,
+        graphics_resources: &Rc<RefCell<GraphicsResources>>,
+        gpu_resources: &Rc<RefCell<GpuResources>>,
+    ) -> TerraResult<ShadowMapProgram> {
+        let pipeline = gpu_resources.borrow().create_compute_pipeline("shadow")?;
+
+        Ok(ShadowMapProgram {
+            context: context.clone(),
+            graphics_resources: graphics_resources.clone(),
+            gpu_resources: gpu_resources.clone(),
+            pipeline,
+        })
+    }
+
+    // Has to be recorded outside of the render pass
+    pub fn dispatch(&self, builder: &mut CommandBuilder) -> TerraResult<()> {
+        let context = self.context.borrow();
+        let lights = &context.lights()[..context.lights().len().min(MAX_LIGHTS)];
+
+        if lights
+            .iter()
+            .all(|light| light.borrow().shadows() == Shadows::None)
+        {
+            return Ok(());
+        }
+
+        let mut segments: Vec<[f32; 4]> = context
+            .occluders()
+            .iter()
+            .flat_map(|occluder| occluder.borrow().segments())
+            .collect();
+        let segment_count = segments.len() as u32;
+
+        // Storage buffers can't be empty
+        if segments.is_empty() {
+            segments.push([0.0; 4]);
+        }
+
+        let graphics_resources = self.graphics_resources.borrow();
+        let resources = self.gpu_resources.borrow();
+        let segment_buffer = util::buffer_from_iter(
+            resources.memory_alloc(),
+            segments,
+            BufferUsage::STORAGE_BUFFER,
+            MemoryUsage::Upload,
+        )?;
+
+        let set = resources.create_compute_descriptor_set(
+            &self.pipeline,
+            0,
+            [
+                WriteDescriptorSet::buffer(0, graphics_resources.lights_buffer().clone()),
+                WriteDescriptorSet::buffer(1, segment_buffer),
+                WriteDescriptorSet::image_view(2, graphics_resources.shadow_map().clone()),
+            ],
+        )?;
+
+        let layout = self.pipeline.layout().clone();
+        let group_count = SHADOW_MAP_RESOLUTION.div_ceil(GROUP_SIZE);
+
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, layout.clone(), 0, set)
+            .push_constants(layout, 0, ShadowConstants { segment_count })
+            .dispatch([group_count, lights.len() as u32, 1])
+            .map_err(TerraError::vulkan("record the shadow map dispatch"))?;
+
+        Ok(())
+    }
+
+    pub fn reload(&mut self, changed: &[(ShaderKind, String)]) -> bool {
+        let affected = changed
+            .iter()
+            .any(|(kind, name)| *kind == ShaderKind::Compute && name == "shadow");
+
+        if !affected {
+            return true;
+        }
+
+        match self
+            .gpu_resources
+            .borrow()
+            .create_compute_pipeline("shadow")
+        {
+            Ok(pipeline) => {
+                self.pipeline = pipeline;
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to rebuild shadow map pipeline: {e}");
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use vulkano::buffer::{BufferContents, Subbuffer};
+
+use crate::terra::data::ShapeVertex;
+
+#[derive(BufferContents)]
+#[repr(C)]
+pub struct PerShape {
+    pub model: [f32; 16],
+}
+
+pub type ShapeBuffers = (Subbuffer<[ShapeVertex]>, Subbuffer<[u32]>);
+
+// Tessellated geometry, rebuilt when the shape's revision changes
+pub struct ShapeMesh {
+    pub revision: u64,
+    pub buffers: Option<ShapeBuffers>,
+}
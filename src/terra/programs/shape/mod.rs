@@ -0,0 +1,232 @@
+pub mod data;
+
+use crate::{
+    shape::{tessellate, Shape},
+    terra::{
+        context::GraphicsContext,
+        data::{BlendMode, ShapeVertex},
+        error::{TerraError, TerraResult},
+        programs::{
+            shape::data::{PerShape, ShapeBuffers, ShapeMesh},
+            CommandBuilder,
+        },
+        resources::gpu::GpuResources,
+        shader::ShaderKind,
+        util,
+    },
+};
+use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+use vulkano::{
+    buffer::BufferUsage,
+    descriptor_set::PersistentDescriptorSet,
+    memory::allocator::MemoryUsage,
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState, vertex_input::Vertex as BaseVertex,
+            viewport::ViewportState,
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+};
+
+pub struct ShapeRenderProgram {
+    context: Rc<RefCell<GraphicsContext>>,
+    gpu_resources: Rc<RefCell<GpuResources>>,
+    pipeline: Arc<GraphicsPipeline>,
+    global_descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    meshes: HashMap<u64, ShapeMesh>,
+    queue: Vec<Rc<RefCell<Shape>>>,
+}
+
+impl ShapeRenderProgram {
+    pub fn new(
+        context: &Rc<RefCell<GraphicsContext>>,
+        gpu_resources: &Rc<RefCell<GpuResources>>,
+    ) -> TerraResult<ShapeRenderProgram> {
+        let pipeline = create_pipeline(gpu_resources)?;
+        let layout = &pipeline.layout().set_layouts()[0];
+        let global_descriptor_sets = gpu_resources
+            .borrow()
+            .create_global_descriptor_sets(layout)?;
+
+        Ok(ShapeRenderProgram {
+            context: context.clone(),
+            gpu_resources: gpu_resources.clone(),
+            pipeline,
+            global_descriptor_sets,
+            meshes: HashMap::new(),
+            queue: vec![],
+        })
+    }
+
+    pub fn begin_frame(&mut self) {
+        self.queue = self.context.borrow().shapes().clone();
+        self.queue
+            .sort_by_key(|shape| shape.borrow().sorting_order());
+    }
+
+    // Draws the shapes in one sorting order
+    pub fn draw(&mut self, builder: &mut CommandBuilder, order: i32) -> TerraResult<()> {
+        let queue = std::mem::take(&mut self.queue);
+        let start = queue.partition_point(|shape| shape.borrow().sorting_order() < order);
+        let end = queue.partition_point(|shape| shape.borrow().sorting_order() <= order);
+
+        let result = if start < end {
+            self.draw_queued(builder, &queue[start..end])
+        } else {
+            Ok(())
+        };
+
+        self.queue = queue;
+        result
+    }
+
+    fn draw_queued(
+        &mut self,
+        builder: &mut CommandBuilder,
+        queue: &[Rc<RefCell<Shape>>],
+    ) -> TerraResult<()> {
+        let layout = self.pipeline.layout().clone();
+        let frame = self.gpu_resources.borrow().frame_index();
+
+        builder
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                layout.clone(),
+                0,
+                self.global_descriptor_sets[frame].clone(),
+            );
+
+        for shape in queue.iter() {
+            let shape = shape.borrow();
+            let (vertex_buffer, index_buffer) = match self.get_or_create_mesh(&shape)? {
+                Some(buffers) => buffers.clone(),
+                None => continue,
+            };
+
+            let model = util::mat4_to_array(shape.transform().matrix());
+            let index_count = index_buffer.len() as u32;
+
+            builder
+                .push_constants(layout.clone(), 0, PerShape { model })
+                .bind_vertex_buffers(0, vertex_buffer)
+                .bind_index_buffer(index_buffer);
+
+            builder
+                .draw_indexed(index_count, 1, 0, 0, 0)
+                .map_err(TerraError::vulkan("record a shape draw"))?;
+        }
+
+        Ok(())
+    }
+
+    // Drops the meshes of shapes that were removed
+    pub fn end_frame(&mut self) {
+        let queue = std::mem::take(&mut self.queue);
+        self.meshes
+            .retain(|id, _| queue.iter().any(|shape| shape.borrow().id() == *id));
+    }
+
+    pub fn reload(&mut self, changed: &[(ShaderKind, String)]) -> bool {
+        let affected = changed.iter().any(|(kind, name)| match kind {
+            ShaderKind::Vertex => name == "shape",
+            ShaderKind::Fragment => name == "shape",
+            ShaderKind::Compute => false,
+        });
+
+        if !affected {
+            return true;
+        }
+
+        let rebuilt = create_pipeline(&self.gpu_resources).and_then(|pipeline| {
+            let layout = &pipeline.layout().set_layouts()[0];
+            let set = self
+                .gpu_resources
+                .borrow()
+                .create_global_descriptor_sets(layout)?;
+            Ok((pipeline, set))
+        });
+
+        match rebuilt {
+            Ok((pipeline, global_descriptor_sets)) => {
+                self.global_descriptor_sets = global_descriptor_sets;
+                self.pipeline = pipeline;
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to rebuild shape pipeline: {e}");
+                false
+            }
+        }
+    }
+
+    // Shapes are tessellated again whenever their geometry changes. Empty shapes have no buffers,
+    // and neither do shapes that can't be tessellated, which are reported once per change.
+    fn get_or_create_mesh(&mut self, shape: &Shape) -> TerraResult<Option<&ShapeBuffers>> {
+        let valid = self
+            .meshes
+            .get(&shape.id())
+            .is_some_and(|mesh| mesh.revision == shape.revision());
+
+        if !valid {
+            let (vertices, indices) = tessellate::tessellate(shape).unwrap_or_else(|e| {
+                eprintln!("Skipping shape {}: {e}", shape.id());
+                (vec![], vec![])
+            });
+
+            let buffers = if indices.is_empty() {
+                None
+            } else {
+                let resources = self.gpu_resources.borrow();
+                let allocator = resources.memory_alloc();
+
+                Some((
+                    util::buffer_from_iter(
+                        allocator,
+                        vertices,
+                        BufferUsage::VERTEX_BUFFER,
+                        MemoryUsage::Upload,
+                    )?,
+                    util::buffer_from_iter(
+                        allocator,
+                        indices,
+                        BufferUsage::INDEX_BUFFER,
+                        MemoryUsage::Upload,
+                    )?,
+                ))
+            };
+
+            let mesh = ShapeMesh {
+                revision: shape.revision(),
+                buffers,
+            };
+            self.meshes.insert(shape.id(), mesh);
+        }
+
+        Ok(self.meshes[&shape.id()].buffers.as_ref())
+    }
+}
+
+fn create_pipeline(resources: &Rc<RefCell<GpuResources>>) -> TerraResult<Arc<GraphicsPipeline>> {
+    let resources = resources.borrow();
+    let device = resources.device();
+    let render_pass = resources.render_pass();
+    let shaders = resources.shaders();
+
+    let vs = shaders.entry_point(ShaderKind::Vertex, "shape")?;
+    let fs = shaders.entry_point(ShaderKind::Fragment, "shape")?;
+    let subpass = render_pass.clone().first_subpass();
+
+    GraphicsPipeline::start()
+        .vertex_input_state(ShapeVertex::per_vertex())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .multisample_state(util::multisample_state(&subpass))
+        .render_pass(subpass)
+        .vertex_shader(vs, ())
+        .fragment_shader(fs, ())
+        .input_assembly_state(InputAssemblyState::default())
+        .color_blend_state(BlendMode::Alpha.color_blend_state())
+        .build(device.clone())
+        .map_err(TerraError::vulkan("build the shape pipeline"))
+}
@@ -1,21 +1,64 @@
-use vulkano::buffer::BufferContents;
+use std::sync::Arc;
 
-use crate::terra::data::Color;
+use vulkano::{
+    buffer::{BufferContents, Subbuffer},
+    descriptor_set::PersistentDescriptorSet,
+};
+
+use crate::{
+    sprite::{
+        slice::{NineSlice, SpriteBorder},
+        Sprite,
+    },
+    terra::data::{Color, Vertex},
+};
 
 #[derive(BufferContents)]
 #[repr(C)]
 pub struct PerObject {
     pub model: [f32; 16],
     pub color: [f32; 4],
+    // xy scale, zw offset applied to the texture coordinates
+    pub uv: [f32; 4],
 }
 
 impl PerObject {
     pub fn new(model: [f32; 16], color: &Color) -> PerObject {
+        PerObject::with_uv(model, color, [1.0, 1.0, 0.0, 0.0])
+    }
+
+    pub fn with_uv(model: [f32; 16], color: &Color, uv: [f32; 4]) -> PerObject {
         let color = color.get();
 
         PerObject {
             model,
             color: [color[0] as f32, color[1] as f32, color[2] as f32, 1.0],
+            uv,
         }
     }
 }
+
+pub struct SlicedMesh {
+    pub border: SpriteBorder,
+    pub pivot: [f32; 2],
+    pub pixels_per_unit: Option<f32>,
+    pub slice: NineSlice,
+    pub dimensions: [u32; 2],
+    pub vertex_buffer: Subbuffer<[Vertex]>,
+    pub index_buffer: Subbuffer<[u32]>,
+}
+
+impl SlicedMesh {
+    pub fn is_valid(&self, sprite: &Sprite, slice: &NineSlice, dimensions: [u32; 2]) -> bool {
+        self.border == *sprite.border()
+            && self.pivot == sprite.pivot()
+            && self.pixels_per_unit == sprite.pixels_per_unit()
+            && self.slice == *slice
+            && self.dimensions == dimensions
+    }
+}
+
+pub struct MaterialSet {
+    pub revision: u64,
+    pub descriptor_set: Arc<PersistentDescriptorSet>,
+}
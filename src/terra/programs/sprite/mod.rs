@@ -1,37 +1,77 @@
 pub mod data;
 
-use crate::terra::{
-    context::GraphicsContext,
-    data::{GlobalData, Vertex},
-    programs::sprite::data::PerObject,
-    resources::{gpu::GpuResources, graphics::GraphicsResources},
-    util,
+use crate::{
+    material::{Material, MATERIAL_SET},
+    sprite::{slice::NineSlice, DrawMode, Sprite, SpriteRenderer},
+    terra::{
+        assets::Handle,
+        context::GraphicsContext,
+        data::{BlendMode, SamplerSettings, Vertex},
+        error::{TerraError, TerraResult},
+        programs::{
+            sprite::data::{MaterialSet, PerObject, SlicedMesh},
+            CommandBuilder,
+        },
+        resources::{
+            gpu::GpuResources,
+            graphics::{GraphicsResources, SpriteResources},
+        },
+        shader::ShaderKind,
+        util,
+    },
+};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
 };
-use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
 use vulkano::{
-    command_buffer::{
-        AutoCommandBufferBuilder, ClearDepthStencilImageInfo, CommandBufferUsage,
-        PrimaryAutoCommandBuffer, RenderPassBeginInfo, SubpassContents,
-    },
-    descriptor_set::PersistentDescriptorSet,
+    buffer::BufferUsage,
+    descriptor_set::{layout::DescriptorSetLayout, PersistentDescriptorSet, WriteDescriptorSet},
     image::{view::ImageView, ImmutableImage},
+    memory::allocator::MemoryUsage,
     pipeline::{
         graphics::{
             input_assembly::InputAssemblyState, vertex_input::Vertex as BaseVertex,
             viewport::ViewportState,
         },
-        GraphicsPipeline, Pipeline, PipelineBindPoint,
+        GraphicsPipeline, Pipeline, PipelineBindPoint, PipelineLayout,
     },
-    render_pass::Framebuffer,
 };
 
+// Lit pipelines read the lights from this set instead of material parameters
+const LIGHTS_SET: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Shading {
+    Unlit,
+    Lit(Option<u64>),
+    Material(u64),
+}
+
 pub struct SpriteRenderProgram {
     context: Rc<RefCell<GraphicsContext>>,
     graphics_resources: Rc<RefCell<GraphicsResources>>,
     gpu_resources: Rc<RefCell<GpuResources>>,
-    pipeline: Arc<GraphicsPipeline>,
-    global_descriptor_set: Arc<PersistentDescriptorSet>,
-    sprite_descriptor_sets: HashMap<u64, Arc<PersistentDescriptorSet>>,
+    pipelines: HashMap<BlendMode, Arc<GraphicsPipeline>>,
+    // None for pipelines that failed to build, until the shaders are reloaded
+    material_pipelines: HashMap<(u64, BlendMode), Option<Arc<GraphicsPipeline>>>,
+    lit_pipelines: HashMap<BlendMode, Option<Arc<GraphicsPipeline>>>,
+    global_descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+    // Keyed by set layout too, since material shaders can declare their own image set
+    sprite_descriptor_sets:
+        HashMap<(u64, SamplerSettings, Arc<DescriptorSetLayout>), Arc<PersistentDescriptorSet>>,
+    lit_descriptor_sets: HashMap<(u64, Option<u64>, SamplerSettings), Arc<PersistentDescriptorSet>>,
+    sliced_meshes: HashMap<u64, SlicedMesh>,
+    material_sets: HashMap<u64, MaterialSet>,
+    queue: Vec<(i32, u64, Rc<RefCell<SpriteRenderer>>)>,
+    lights_set: Option<Arc<PersistentDescriptorSet>>,
+    drawn_sliced: HashSet<u64>,
+    drawn_materials: HashSet<u64>,
+    // Keep the normal maps and material textures of this frame's renderers resident
+    normal_maps: Vec<Handle<Arc<ImageView<ImmutableImage>>>>,
+    textures: Vec<Handle<SpriteResources>>,
 }
 
 impl SpriteRenderProgram {
@@ -39,161 +79,613 @@ impl SpriteRenderProgram {
         context: &Rc<RefCell<GraphicsContext>>,
         graphics_resources: &Rc<RefCell<GraphicsResources>>,
         gpu_resources: &Rc<RefCell<GpuResources>>,
-    ) -> SpriteRenderProgram {
-        let pipeline = create_pipeline(gpu_resources);
+    ) -> TerraResult<SpriteRenderProgram> {
+        let pipeline = create_pipeline(gpu_resources, "sprite", "sprite", BlendMode::default())?;
         let layout = &pipeline.layout().set_layouts()[0];
-        let global_descriptor_set = gpu_resources.borrow().create_global_descriptor_set(layout);
+        let global_descriptor_sets = gpu_resources
+            .borrow()
+            .create_global_descriptor_sets(layout)?;
 
-        SpriteRenderProgram {
+        let mut pipelines = HashMap::new();
+        pipelines.insert(BlendMode::default(), pipeline);
+
+        Ok(SpriteRenderProgram {
             context: context.clone(),
             graphics_resources: graphics_resources.clone(),
             gpu_resources: gpu_resources.clone(),
-            pipeline,
-            global_descriptor_set,
+            pipelines,
+            material_pipelines: HashMap::new(),
+            lit_pipelines: HashMap::new(),
+            global_descriptor_sets,
             sprite_descriptor_sets: HashMap::new(),
-        }
+            lit_descriptor_sets: HashMap::new(),
+            sliced_meshes: HashMap::new(),
+            material_sets: HashMap::new(),
+            queue: vec![],
+            lights_set: None,
+            drawn_sliced: HashSet::new(),
+            drawn_materials: HashSet::new(),
+            normal_maps: vec![],
+            textures: vec![],
+        })
     }
 
-    pub fn draw(&mut self, framebuffer: &Arc<Framebuffer>) -> Option<PrimaryAutoCommandBuffer> {
-        let mut builder = AutoCommandBufferBuilder::primary(
-            self.gpu_resources.borrow().command_buffer_alloc(),
-            self.gpu_resources.borrow().queue().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        )
-        .expect("Failed to allocate command buffer.");
-
-        let layout = self.pipeline.layout().clone();
-        let context = self.context.clone();
-        let context = context.borrow();
-        let camera = context.camera().borrow();
-        let clear = camera.clear().get();
-
-        let clear_values = vec![Some([clear[0], clear[1], clear[2], 1.0].into())];
-        let render_pass_begin_info = RenderPassBeginInfo {
-            clear_values,
-            ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
-        };
+    // Queues this frame's renderers by sorting order. Renderers of the same sprite are kept
+    // together so they can share bindings.
+    pub fn begin_frame(&mut self) {
+        self.load_textures();
 
-        builder
-            .begin_render_pass(render_pass_begin_info, SubpassContents::Inline)
-            .expect("Failed to start render pass.");
+        let context = self.context.borrow();
+        for (id, renderers) in context.sprite_renderers() {
+            for renderer in renderers.iter() {
+                let order = renderer.borrow().sorting_order();
+                self.queue.push((order, *id, renderer.clone()));
+            }
+        }
 
-        builder
-            .set_viewport(0, [self.gpu_resources.borrow().viewport().clone()])
-            .bind_pipeline_graphics(self.pipeline.clone());
+        self.queue.sort_by_key(|(order, id, _)| (*order, *id));
+        self.lights_set = None;
+    }
 
-        self.update_global_buffer();
+    // Draws the renderers in one sorting order. Bindings are reset on every call since other
+    // programs draw in between.
+    pub fn draw(&mut self, builder: &mut CommandBuilder, order: i32) -> TerraResult<()> {
+        let queue = std::mem::take(&mut self.queue);
+        let start = queue.partition_point(|(o, _, _)| *o < order);
+        let end = queue.partition_point(|(o, _, _)| *o <= order);
 
-        builder.bind_descriptor_sets(
-            PipelineBindPoint::Graphics,
-            layout.clone(),
-            0,
-            [self.global_descriptor_set.clone()]
-                .into_iter()
-                .collect::<Vec<_>>(),
-        );
+        let result = self.draw_queued(builder, &queue[start..end]);
+        self.queue = queue;
+        result
+    }
 
+    fn draw_queued(
+        &mut self,
+        builder: &mut CommandBuilder,
+        queue: &[(i32, u64, Rc<RefCell<SpriteRenderer>>)],
+    ) -> TerraResult<()> {
         let graphics_resources = self.graphics_resources.clone();
         let graphics_resources = graphics_resources.borrow();
         let index_buffer = graphics_resources.sprite_index_buffer();
+        let frame = self.gpu_resources.borrow().frame_index();
 
-        let context = self.context.clone();
-        let context = context.borrow();
+        let mut bound_pipeline = None;
+        let mut bound_sprite = None;
+        let mut sets_bound = false;
 
-        for (id, renderers) in context.sprite_renderers() {
-            match graphics_resources.sprite(id) {
-                Some(resources) => {
+        for (_, id, renderer) in queue.iter() {
+            let resources = match graphics_resources.sprite(id) {
+                Some(resources) => resources,
+                None => continue,
+            };
+
+            let renderer = renderer.borrow();
+            let sampler = renderer.sprite().sampler();
+
+            // Renderers of the same sprite can still sample it differently
+            if bound_sprite != Some((*id, sampler)) {
+                bound_sprite = Some((*id, sampler));
+                sets_bound = false;
+            }
+
+            let blend_mode = renderer.sprite().blend_mode();
+            let mut material = renderer.material().map(|material| material.borrow());
+
+            let mut shading = match &material {
+                Some(material) => Shading::Material(material.id()),
+                None if renderer.is_lit() => {
+                    Shading::Lit(renderer.sprite().normal_map().map(|map| map.id()))
+                }
+                None => Shading::Unlit,
+            };
+
+            // Renderers whose material or lit pipeline can't be built are drawn unlit instead
+            let pipeline = match (&material, shading) {
+                (Some(material), _) => self.get_or_create_material_pipeline(material, blend_mode),
+                (None, Shading::Lit(_)) => self.get_or_create_lit_pipeline(blend_mode),
+                (None, _) => None,
+            };
+            let pipeline = match pipeline {
+                Some(pipeline) => pipeline,
+                None => {
+                    material = None;
+                    shading = Shading::Unlit;
+                    self.get_or_create_pipeline(blend_mode)?
+                }
+            };
+            let key = (shading, blend_mode);
+            let layout = pipeline.layout().clone();
+
+            if bound_pipeline != Some(key) {
+                builder.bind_pipeline_graphics(pipeline);
+                bound_pipeline = Some(key);
+                sets_bound = false;
+            }
+
+            if !sets_bound {
+                let mut sets = vec![self.global_descriptor_sets[frame].clone()];
+
+                match (&material, shading) {
+                    (Some(material), _) => {
+                        sets.extend(self.get_or_create_image_set(
+                            id,
+                            sampler,
+                            resources.image(),
+                            &layout,
+                        )?);
+                        self.drawn_materials.insert(material.id());
+
+                        if let Some(material_set) =
+                            self.get_or_create_material_set(material, &layout, &graphics_resources)?
+                        {
+                            sets.push(material_set);
+                        }
+                    }
+                    (None, Shading::Lit(normal_map)) => {
+                        sets.push(self.get_or_create_lit_set(
+                            *id,
+                            normal_map,
+                            sampler,
+                            resources.image(),
+                            &layout,
+                            &graphics_resources,
+                        )?);
+
+                        // Shared by every lit sprite this frame
+                        if self.lights_set.is_none() {
+                            self.lights_set =
+                                Some(self.create_lights_set(&layout, &graphics_resources)?);
+                        }
+                        sets.extend(self.lights_set.clone());
+                    }
+                    (None, _) => sets.extend(self.get_or_create_image_set(
+                        id,
+                        sampler,
+                        resources.image(),
+                        &layout,
+                    )?),
+                }
+
+                builder.bind_descriptor_sets(PipelineBindPoint::Graphics, layout.clone(), 0, sets);
+                sets_bound = true;
+            }
+
+            let model = util::mat4_to_array(renderer.transform().matrix());
+            let data = PerObject::with_uv(model, renderer.color(), renderer.uv_transform());
+            builder.push_constants(layout, 0, data);
+
+            match renderer.draw_mode() {
+                DrawMode::Simple => {
                     builder
                         .bind_vertex_buffers(0, resources.vertex_buffer().clone())
                         .bind_index_buffer(index_buffer.clone());
 
-                    let set = self.get_or_create_image_set(id, resources.image());
-                    builder.bind_descriptor_sets(
-                        PipelineBindPoint::Graphics,
-                        layout.clone(),
-                        1,
-                        [set].into_iter().collect::<Vec<_>>(),
-                    );
+                    builder
+                        .draw_indexed(6, 1, 0, 0, 0)
+                        .map_err(TerraError::vulkan("record a sprite draw"))?;
+                }
+                DrawMode::Sliced(slice) => {
+                    let mesh = self.get_or_create_sliced_mesh(
+                        renderer.id(),
+                        renderer.sprite(),
+                        slice,
+                        resources.dimensions(),
+                    )?;
+                    let index_count = mesh.index_buffer.len() as u32;
 
-                    for renderer in renderers.iter() {
-                        let model = util::mat4_to_array(renderer.borrow().transform().matrix());
-                        let data = PerObject::new(model, renderer.borrow().color());
-                        builder.push_constants(layout.clone(), 0, data);
+                    builder
+                        .bind_vertex_buffers(0, mesh.vertex_buffer.clone())
+                        .bind_index_buffer(mesh.index_buffer.clone());
 
-                        let _ = builder.draw_indexed(6, 1, 0, 0, 0);
-                    }
+                    builder
+                        .draw_indexed(index_count, 1, 0, 0, 0)
+                        .map_err(TerraError::vulkan("record a sliced sprite draw"))?;
+                    self.drawn_sliced.insert(renderer.id());
                 }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Drops cached meshes and material sets that weren't drawn this frame
+    pub fn end_frame(&mut self) {
+        let drawn_sliced = std::mem::take(&mut self.drawn_sliced);
+        let drawn_materials = std::mem::take(&mut self.drawn_materials);
+
+        self.sliced_meshes.retain(|id, _| drawn_sliced.contains(id));
+        self.material_sets
+            .retain(|id, _| drawn_materials.contains(id));
+        self.queue.clear();
+    }
+
+    fn get_or_create_pipeline(
+        &mut self,
+        blend_mode: BlendMode,
+    ) -> TerraResult<Arc<GraphicsPipeline>> {
+        if let Some(pipeline) = self.pipelines.get(&blend_mode) {
+            return Ok(pipeline.clone());
+        }
+
+        let pipeline = create_pipeline(&self.gpu_resources, "sprite", "sprite", blend_mode)?;
+        self.pipelines.insert(blend_mode, pipeline.clone());
+        Ok(pipeline)
+    }
+
+    // A failure is reported once and the renderer falls back to the unlit pipeline
+    fn get_or_create_lit_pipeline(
+        &mut self,
+        blend_mode: BlendMode,
+    ) -> Option<Arc<GraphicsPipeline>> {
+        if let Some(pipeline) = self.lit_pipelines.get(&blend_mode) {
+            return pipeline.clone();
+        }
+
+        let pipeline = create_pipeline(&self.gpu_resources, "sprite_lit", "sprite_lit", blend_mode)
+            .inspect_err(|e| {
+                eprintln!("Failed to create the lit sprite pipeline, drawing unlit: {e}")
+            })
+            .ok();
+        self.lit_pipelines.insert(blend_mode, pipeline.clone());
+        pipeline
+    }
 
-                _ => continue,
+    // Like lit pipelines, materials whose shaders don't link are drawn unlit
+    fn get_or_create_material_pipeline(
+        &mut self,
+        material: &Material,
+        blend_mode: BlendMode,
+    ) -> Option<Arc<GraphicsPipeline>> {
+        let key = (material.id(), blend_mode);
+        if let Some(pipeline) = self.material_pipelines.get(&key) {
+            return pipeline.clone();
+        }
+
+        let pipeline = create_pipeline(
+            &self.gpu_resources,
+            material.vertex_shader(),
+            material.fragment_shader(),
+            blend_mode,
+        )
+        .inspect_err(|e| {
+            eprintln!(
+                "Failed to create the pipeline for material {}/{}, drawing it unlit: {e}",
+                material.vertex_shader(),
+                material.fragment_shader()
+            )
+        })
+        .ok();
+        self.material_pipelines.insert(key, pipeline.clone());
+        pipeline
+    }
+
+    // Rebuilt whenever the material's parameters change. Shaders that don't declare the
+    // material set get no set at all.
+    fn get_or_create_material_set(
+        &mut self,
+        material: &Material,
+        layout: &Arc<PipelineLayout>,
+        graphics_resources: &GraphicsResources,
+    ) -> TerraResult<Option<Arc<PersistentDescriptorSet>>> {
+        let set_layout = match layout.set_layouts().get(MATERIAL_SET as usize) {
+            Some(set_layout) => set_layout,
+            None => return Ok(None),
+        };
+
+        if let Some(set) = self.material_sets.get(&material.id()) {
+            if set.revision == material.revision() {
+                return Ok(Some(set.descriptor_set.clone()));
             }
         }
 
-        builder
-            .end_render_pass()
-            .expect("Failed to end render pass.");
+        let resources = self.gpu_resources.borrow();
+
+        let mut writes = vec![];
+        if let Some(block) = material.layout().uniform_block() {
+            let uniform_buffer = util::buffer_from_iter(
+                resources.memory_alloc(),
+                material.uniform_data().iter().copied(),
+                BufferUsage::UNIFORM_BUFFER,
+                MemoryUsage::Upload,
+            )?;
+            writes.push(WriteDescriptorSet::buffer(block.binding, uniform_buffer));
+        }
+
+        // Samplers that were never given a texture are bound as white. So are textures still
+        // loading, and the set is built again next frame.
+        let mut complete = true;
+        for texture in material.layout().textures() {
+            let sprite = material
+                .textures()
+                .find(|(binding, _)| *binding == texture.binding)
+                .map(|(_, sprite)| sprite);
 
-        Some(builder.build().expect("Failed to build command buffer"))
+            let (image, settings) = match sprite {
+                Some(sprite) => match graphics_resources.sprite(&sprite.id()) {
+                    Some(resources) => (resources.image(), sprite.sampler()),
+                    None => {
+                        complete = false;
+                        (graphics_resources.white_texture(), sprite.sampler())
+                    }
+                },
+                None => (
+                    graphics_resources.white_texture(),
+                    SamplerSettings::default(),
+                ),
+            };
+            writes.push(WriteDescriptorSet::image_view_sampler(
+                texture.binding,
+                image.clone(),
+                resources.sampler(settings)?,
+            ));
+        }
+
+        let descriptor_set = PersistentDescriptorSet::new(
+            resources.descriptor_set_alloc(),
+            set_layout.clone(),
+            writes,
+        )
+        .map_err(TerraError::vulkan("create a material descriptor set"))?;
+
+        if complete {
+            self.material_sets.insert(
+                material.id(),
+                MaterialSet {
+                    revision: material.revision(),
+                    descriptor_set: descriptor_set.clone(),
+                },
+            );
+        }
+
+        Ok(Some(descriptor_set))
     }
 
-    fn update_global_buffer(&self) {
-        let resources = self.gpu_resources.clone();
-        let resources = resources.borrow();
+    // The sprite image is bound together with its normal map, or a flat one if it has none.
+    // Sets are cached by the normal map actually bound, so a sprite whose map is still loading
+    // gets a new set once it's uploaded.
+    fn get_or_create_lit_set(
+        &mut self,
+        sprite: u64,
+        normal_map: Option<u64>,
+        settings: SamplerSettings,
+        image: &Arc<ImageView<ImmutableImage>>,
+        layout: &Arc<PipelineLayout>,
+        graphics_resources: &GraphicsResources,
+    ) -> TerraResult<Arc<PersistentDescriptorSet>> {
+        let normal_map = normal_map.filter(|id| graphics_resources.normal_map(id).is_some());
+        if let Some(set) = self
+            .lit_descriptor_sets
+            .get(&(sprite, normal_map, settings))
+        {
+            return Ok(set.clone());
+        }
+
+        let normal_image = normal_map
+            .and_then(|id| graphics_resources.normal_map(&id))
+            .unwrap_or(graphics_resources.flat_normal_map());
+
+        let resources = self.gpu_resources.borrow();
+        let sampler = resources.sampler(settings)?;
+
+        let set = PersistentDescriptorSet::new(
+            resources.descriptor_set_alloc(),
+            layout.set_layouts()[1].clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(0, image.clone(), sampler.clone()),
+                WriteDescriptorSet::image_view_sampler(1, normal_image.clone(), sampler.clone()),
+            ],
+        )
+        .map_err(TerraError::vulkan("create a lit sprite descriptor set"))?;
+
+        self.lit_descriptor_sets
+            .insert((sprite, normal_map, settings), set.clone());
+        Ok(set)
+    }
+
+    fn create_lights_set(
+        &self,
+        layout: &Arc<PipelineLayout>,
+        graphics_resources: &GraphicsResources,
+    ) -> TerraResult<Arc<PersistentDescriptorSet>> {
+        let resources = self.gpu_resources.borrow();
+
+        PersistentDescriptorSet::new(
+            resources.descriptor_set_alloc(),
+            layout.set_layouts()[LIGHTS_SET].clone(),
+            [
+                WriteDescriptorSet::buffer(0, graphics_resources.lights_buffer().clone()),
+                WriteDescriptorSet::image_view(1, graphics_resources.shadow_map().clone()),
+            ],
+        )
+        .map_err(TerraError::vulkan("create the lights descriptor set"))
+    }
 
+    // Material textures and normal maps are uploaded the first time they are drawn and freed
+    // once no renderer uses them anymore
+    fn load_textures(&mut self) {
         let context = self.context.borrow();
-        let camera = context.camera().borrow();
+        let mut graphics_resources = self.graphics_resources.borrow_mut();
+        let mut normal_maps = vec![];
+        let mut textures = vec![];
+
+        for (_, renderers) in context.sprite_renderers() {
+            for renderer in renderers.iter() {
+                let renderer = renderer.borrow();
+
+                if let Some(normal_map) = renderer.sprite().normal_map() {
+                    normal_maps.push(graphics_resources.load_normal_map(normal_map));
+                }
+
+                let material = match renderer.material() {
+                    Some(material) => material.borrow(),
+                    None => continue,
+                };
+
+                for (_, sprite) in material.textures() {
+                    textures.push(graphics_resources.load_sprite(sprite));
+                }
+            }
+        }
 
-        let extent = resources.swapchain().image_extent();
-        let aspect = (extent[0] as f32) / (extent[1] as f32);
-        let ortho = camera.ortho(aspect);
-        let view = camera.transform().matrix();
-        let global_data = GlobalData::new(ortho, view);
+        self.normal_maps = normal_maps;
+        self.textures = textures;
+    }
 
-        *resources
-            .global_buffer()
-            .write()
-            .expect("Failed to write to global buffer") = global_data;
+    // Drops the descriptor sets of sprites and normal maps that were freed or replaced. Material
+    // sets are cheap to rebuild, so they're dropped entirely.
+    pub fn release(&mut self, ids: &[u64]) {
+        self.sprite_descriptor_sets
+            .retain(|(id, _, _), _| !ids.contains(id));
+        self.lit_descriptor_sets.retain(|(id, normal_map, _), _| {
+            !ids.contains(id) && !normal_map.is_some_and(|id| ids.contains(&id))
+        });
+        self.material_sets.clear();
+    }
+
+    // Rebuilds the pipelines after their shaders were hot reloaded, keeping the old ones and
+    // returning false if the new shaders can't be linked
+    pub fn reload(&mut self, changed: &[(ShaderKind, String)]) -> bool {
+        // Lit and material pipelines are cheap to drop since they're only rebuilt when drawn again
+        self.material_pipelines.clear();
+        self.material_sets.clear();
+        self.lit_pipelines.clear();
+        self.lit_descriptor_sets.clear();
+        self.sprite_descriptor_sets.clear();
+
+        let affected = changed.iter().any(|(kind, name)| match kind {
+            ShaderKind::Vertex => name == "sprite",
+            ShaderKind::Fragment => name == "sprite",
+            ShaderKind::Compute => false,
+        });
+
+        if !affected {
+            return true;
+        }
+
+        let rebuilt = create_pipeline(
+            &self.gpu_resources,
+            "sprite",
+            "sprite",
+            BlendMode::default(),
+        )
+        .and_then(|pipeline| {
+            let layout = &pipeline.layout().set_layouts()[0];
+            let set = self
+                .gpu_resources
+                .borrow()
+                .create_global_descriptor_sets(layout)?;
+            Ok((pipeline, set))
+        });
+
+        match rebuilt {
+            Ok((pipeline, global_descriptor_sets)) => {
+                self.global_descriptor_sets = global_descriptor_sets;
+                self.pipelines.clear();
+                self.pipelines.insert(BlendMode::default(), pipeline);
+                true
+            }
+            Err(e) => {
+                eprintln!("Failed to rebuild sprite pipeline: {e}");
+                false
+            }
+        }
     }
 
     fn get_or_create_image_set(
         &mut self,
         id: &u64,
+        settings: SamplerSettings,
         image: &Arc<ImageView<ImmutableImage>>,
-    ) -> Arc<PersistentDescriptorSet> {
-        if let Some(set) = self.sprite_descriptor_sets.get(id) {
-            set.clone()
+        layout: &Arc<PipelineLayout>,
+    ) -> TerraResult<Option<Arc<PersistentDescriptorSet>>> {
+        // Material shaders that don't sample the sprite image can leave out the set entirely
+        let layout = match layout.set_layouts().get(1) {
+            Some(layout) => layout.clone(),
+            None => return Ok(None),
+        };
+        let key = (*id, settings, layout);
+
+        if let Some(set) = self.sprite_descriptor_sets.get(&key) {
+            Ok(Some(set.clone()))
         } else {
             let resources = self.gpu_resources.borrow();
             let allocator = resources.descriptor_set_alloc();
-            let layout = &self.pipeline.layout().set_layouts()[1];
-            let sampler = resources.sampler();
+            let sampler = resources.sampler(settings)?;
 
-            let set = util::create_image_descriptor_set(allocator, layout, image, sampler);
+            let set = util::create_image_descriptor_set(allocator, &key.2, image, &sampler)?;
             let clone = set.clone();
-            self.sprite_descriptor_sets.insert(*id, set);
-            clone
+            self.sprite_descriptor_sets.insert(key, set);
+            Ok(Some(clone))
         }
     }
+
+    fn get_or_create_sliced_mesh(
+        &mut self,
+        id: u64,
+        sprite: &Sprite,
+        slice: &NineSlice,
+        dimensions: [u32; 2],
+    ) -> TerraResult<&SlicedMesh> {
+        let valid = self
+            .sliced_meshes
+            .get(&id)
+            .is_some_and(|mesh| mesh.is_valid(sprite, slice, dimensions));
+
+        if !valid {
+            let resources = self.gpu_resources.borrow();
+            let allocator = resources.memory_alloc();
+            let (vertices, indices) = slice.mesh(sprite, dimensions);
+
+            let mesh = SlicedMesh {
+                border: *sprite.border(),
+                pivot: sprite.pivot(),
+                pixels_per_unit: sprite.pixels_per_unit(),
+                slice: *slice,
+                dimensions,
+                vertex_buffer: util::buffer_from_iter(
+                    allocator,
+                    vertices,
+                    BufferUsage::VERTEX_BUFFER,
+                    MemoryUsage::Upload,
+                )?,
+                index_buffer: util::buffer_from_iter(
+                    allocator,
+                    indices,
+                    BufferUsage::INDEX_BUFFER,
+                    MemoryUsage::Upload,
+                )?,
+            };
+            self.sliced_meshes.insert(id, mesh);
+        }
+
+        Ok(&self.sliced_meshes[&id])
+    }
 }
 
-fn create_pipeline(resources: &Rc<RefCell<GpuResources>>) -> Arc<GraphicsPipeline> {
+fn create_pipeline(
+    resources: &Rc<RefCell<GpuResources>>,
+    vertex_shader: &str,
+    fragment_shader: &str,
+    blend_mode: BlendMode,
+) -> TerraResult<Arc<GraphicsPipeline>> {
     let resources = resources.borrow();
     let device = resources.device();
     let render_pass = resources.render_pass();
     let shaders = resources.shaders();
 
-    let vs = shaders.vertex("sprite").unwrap();
-    let vs = util::get_shader_entry_point(vs);
-    let fs = shaders.fragment("sprite").unwrap();
-    let fs = util::get_shader_entry_point(fs);
+    let vs = shaders.entry_point(ShaderKind::Vertex, vertex_shader)?;
+    let fs = shaders.entry_point(ShaderKind::Fragment, fragment_shader)?;
     let subpass = render_pass.clone().first_subpass();
 
     GraphicsPipeline::start()
         .vertex_input_state(Vertex::per_vertex())
         .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .multisample_state(util::multisample_state(&subpass))
         .render_pass(subpass)
         .vertex_shader(vs, ())
         .fragment_shader(fs, ())
         .input_assembly_state(InputAssemblyState::default())
+        .color_blend_state(blend_mode.color_blend_state())
         .build(device.clone())
-        .expect("Failed to build graphics pipeline")
+        .map_err(|e| TerraError::shader(&format!("{vertex_shader} + {fragment_shader}"), e))
 }
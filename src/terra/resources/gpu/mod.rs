@@ -1,20 +1,29 @@
-use crate::terra::{data::GlobalData, shader::loader::ShaderLoader, util};
-use std::sync::Arc;
+use crate::terra::{
+    config::TerraConfig,
+    data::{GlobalData, SamplerSettings},
+    error::{TerraError, TerraResult},
+    shader::loader::ShaderLoader,
+    util,
+};
+#[cfg(feature = "hot-reload")]
+use crate::vfs;
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
 use vulkano::{
     buffer::{BufferUsage, Subbuffer},
-    command_buffer::allocator::StandardCommandBufferAllocator,
+    command_buffer::{allocator::StandardCommandBufferAllocator, CommandBufferExecFuture},
     descriptor_set::{
         allocator::StandardDescriptorSetAllocator, layout::DescriptorSetLayout,
         PersistentDescriptorSet, WriteDescriptorSet,
     },
     device::{Device, Queue},
-    image::SwapchainImage,
+    image::{SampleCount, SwapchainImage},
     instance::Instance,
     memory::allocator::{MemoryUsage, StandardMemoryAllocator},
-    pipeline::graphics::viewport::Viewport,
+    pipeline::{graphics::viewport::Viewport, ComputePipeline, Pipeline, PipelineBindPoint},
     render_pass::{Framebuffer, RenderPass},
     sampler::Sampler,
-    swapchain::{Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError},
+    swapchain::{PresentMode, Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError},
+    sync::future::{FenceSignalFuture, NowFuture},
 };
 
 pub struct GpuResources {
@@ -28,32 +37,53 @@ pub struct GpuResources {
     descriptor_set_alloc: Arc<StandardDescriptorSetAllocator>,
     viewport: Viewport,
     render_pass: Arc<RenderPass>,
+    samples: SampleCount,
     frame_buffers: Vec<Arc<Framebuffer>>,
     shaders: ShaderLoader,
+    frames: Vec<FrameResources>,
+    frame: usize,
+    samplers: RefCell<HashMap<SamplerSettings, Arc<Sampler>>>,
+}
+
+// Each frame in flight records into its own copy of these, so the CPU never writes to something
+// the GPU may still be reading for an earlier frame
+pub struct FrameResources {
     global_buffer: Subbuffer<GlobalData>,
-    sampler: Arc<Sampler>,
+    command_buffer_alloc: Arc<StandardCommandBufferAllocator>,
 }
 
 impl GpuResources {
-    pub fn init(instance: &Arc<Instance>, surface: &Arc<Surface>) -> GpuResources {
-        let (device, queue) = util::create_device(instance, surface);
-        let (swapchain, render_targets) = util::create_swap_chain(&device, &surface);
+    pub fn init(
+        instance: &Arc<Instance>,
+        surface: &Arc<Surface>,
+        config: &TerraConfig,
+    ) -> TerraResult<GpuResources> {
+        let (device, queue) = util::create_device(instance, surface, &config.gpu)?;
+        let (swapchain, render_targets) = util::create_swap_chain(&device, surface, config)?;
         let command_buffer_alloc = util::create_command_pool(&device);
         let memory_alloc = util::create_memory_pool(&device);
         let descriptor_set_alloc = util::create_descriptor_set_pool(&device);
         let viewport = util::create_viewport(util::get_surface_dimensions(surface));
-        let render_pass = util::create_render_pass(&device, swapchain.image_format());
-        let frame_buffers = util::create_frame_buffers(&render_targets, &render_pass);
-        let shaders = ShaderLoader::load(&device);
-        let global_buffer = util::buffer_from_data(
-            &memory_alloc,
-            GlobalData::identity(),
-            BufferUsage::UNIFORM_BUFFER,
-            MemoryUsage::Upload,
-        );
-        let sampler = util::create_sampler(&device);
-
-        GpuResources {
+        let samples = util::choose_sample_count(&device, config.samples);
+        let render_pass = util::create_render_pass(&device, swapchain.image_format(), samples)?;
+        let frame_buffers =
+            util::create_frame_buffers(&render_targets, &render_pass, &memory_alloc, samples)?;
+        let shaders = load_shaders(&device)?;
+        let frames = (0..config.frames_in_flight.max(1))
+            .map(|_| {
+                Ok(FrameResources {
+                    global_buffer: util::buffer_from_data(
+                        &memory_alloc,
+                        GlobalData::identity(),
+                        BufferUsage::UNIFORM_BUFFER,
+                        MemoryUsage::Upload,
+                    )?,
+                    command_buffer_alloc: util::create_command_pool(&device),
+                })
+            })
+            .collect::<TerraResult<_>>()?;
+
+        Ok(GpuResources {
             device,
             queue,
             surface: surface.clone(),
@@ -64,11 +94,13 @@ impl GpuResources {
             descriptor_set_alloc,
             viewport,
             render_pass,
+            samples,
             shaders,
-            global_buffer,
-            sampler,
+            frames,
+            frame: 0,
+            samplers: RefCell::new(HashMap::new()),
             frame_buffers,
-        }
+        })
     }
 
     pub fn device(&self) -> &Arc<Device> {
@@ -99,16 +131,51 @@ impl GpuResources {
         &self.render_pass
     }
 
+    // MSAA samples per pixel the render pass was created with
+    pub fn samples(&self) -> SampleCount {
+        self.samples
+    }
+
     pub fn shaders(&self) -> &ShaderLoader {
         &self.shaders
     }
 
+    pub fn shaders_mut(&mut self) -> &mut ShaderLoader {
+        &mut self.shaders
+    }
+
+    pub fn frames_in_flight(&self) -> usize {
+        self.frames.len()
+    }
+
+    // Which of the frames in flight is being recorded
+    pub fn frame_index(&self) -> usize {
+        self.frame
+    }
+
+    pub fn next_frame(&mut self) {
+        self.frame = (self.frame + 1) % self.frames.len();
+    }
+
     pub fn global_buffer(&self) -> &Subbuffer<GlobalData> {
-        &self.global_buffer
+        &self.frames[self.frame].global_buffer
     }
 
-    pub fn sampler(&self) -> &Arc<Sampler> {
-        &self.sampler
+    // For the frame's own command buffer, one-off uploads use command_buffer_alloc
+    pub fn frame_command_buffer_alloc(&self) -> &Arc<StandardCommandBufferAllocator> {
+        &self.frames[self.frame].command_buffer_alloc
+    }
+
+    // Samplers are created the first time their settings are used and shared afterwards
+    pub fn sampler(&self, settings: SamplerSettings) -> TerraResult<Arc<Sampler>> {
+        let mut samplers = self.samplers.borrow_mut();
+        if let Some(sampler) = samplers.get(&settings) {
+            return Ok(sampler.clone());
+        }
+
+        let sampler = util::create_sampler(&self.device, settings)?;
+        samplers.insert(settings, sampler.clone());
+        Ok(sampler)
     }
 
     pub fn swapchain(&self) -> &Arc<Swapchain> {
@@ -127,38 +194,146 @@ impl GpuResources {
         &self.surface
     }
 
-    pub fn create_global_descriptor_set(
+    // One set per frame in flight, indexed by frame_index
+    pub fn create_global_descriptor_sets(
         &self,
         layout: &Arc<DescriptorSetLayout>,
-    ) -> Arc<PersistentDescriptorSet> {
+    ) -> TerraResult<Vec<Arc<PersistentDescriptorSet>>> {
+        self.frames
+            .iter()
+            .map(|frame| {
+                PersistentDescriptorSet::new(
+                    &self.descriptor_set_alloc,
+                    layout.clone(),
+                    [WriteDescriptorSet::buffer(0, frame.global_buffer.clone())],
+                )
+                .map_err(TerraError::vulkan("create the global descriptor set"))
+            })
+            .collect()
+    }
+
+    pub fn create_compute_pipeline(&self, name: &str) -> TerraResult<Arc<ComputePipeline>> {
+        let shader = self
+            .shaders
+            .compute(name)
+            .ok_or_else(|| TerraError::shader(name, "not found"))?;
+
+        util::create_compute_pipeline(&self.device, shader, name)
+    }
+
+    pub fn create_compute_descriptor_set(
+        &self,
+        pipeline: &Arc<ComputePipeline>,
+        set: usize,
+        writes: impl IntoIterator<Item = WriteDescriptorSet>,
+    ) -> TerraResult<Arc<PersistentDescriptorSet>> {
         PersistentDescriptorSet::new(
             &self.descriptor_set_alloc,
-            layout.clone(),
-            [WriteDescriptorSet::buffer(0, self.global_buffer.clone())],
+            pipeline.layout().set_layouts()[set].clone(),
+            writes,
         )
-        .expect("Failed to create global descriptor set")
+        .map_err(TerraError::vulkan("create a compute descriptor set"))
+    }
+
+    // Submits a single dispatch on the graphics queue. Callers wait on the returned fence
+    // before reading results back on the CPU.
+    pub fn dispatch(
+        &self,
+        pipeline: &Arc<ComputePipeline>,
+        descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
+        group_counts: [u32; 3],
+    ) -> TerraResult<FenceSignalFuture<CommandBufferExecFuture<NowFuture>>> {
+        let mut builder = util::create_command_builder(&self.command_buffer_alloc, &self.queue)?;
+
+        builder
+            .bind_pipeline_compute(pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Compute,
+                pipeline.layout().clone(),
+                0,
+                descriptor_sets,
+            )
+            .dispatch(group_counts)
+            .map_err(TerraError::vulkan("record a compute dispatch"))?;
+
+        util::submit(builder, &self.queue)
     }
 }
 
 impl GpuResources {
-    pub fn recreate_swapchain(&mut self) {
-        let surface = &self.surface;
+    pub fn recreate_swapchain(&mut self) -> TerraResult<()> {
+        let create_info = SwapchainCreateInfo {
+            image_extent: util::get_surface_dimensions(&self.surface),
+            ..self.swapchain.create_info()
+        };
+
+        self.rebuild_swapchain(create_info)
+    }
+
+    // Falls back to Fifo when the mode isn't supported, the swapchain's present_mode has the
+    // one that's used
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) -> TerraResult<()> {
+        let present_mode = util::choose_present_mode(&self.device, &self.surface, present_mode)?;
+        let capabilities = self
+            .device
+            .physical_device()
+            .surface_capabilities(&self.surface, Default::default())
+            .map_err(TerraError::vulkan("get the surface capabilities"))?;
+
         let create_info = SwapchainCreateInfo {
-            image_extent: util::get_surface_dimensions(surface),
+            image_extent: util::get_surface_dimensions(&self.surface),
+            min_image_count: util::min_image_count(&capabilities, present_mode),
+            present_mode,
             ..self.swapchain.create_info()
         };
 
+        self.rebuild_swapchain(create_info)
+    }
+
+    fn rebuild_swapchain(&mut self, create_info: SwapchainCreateInfo) -> TerraResult<()> {
+        let surface = &self.surface;
+
         let (swapchain, render_targets) = match self.swapchain.recreate(create_info) {
             Ok(results) => results,
             Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => {
                 (self.swapchain.clone(), self.render_targets.clone())
             }
-            Err(e) => panic!("Failed to recreate the swapchain: {e}"),
+            Err(SwapchainCreationError::DeviceLost) => return Err(TerraError::DeviceLost),
+            Err(e) => return Err(TerraError::vulkan("recreate the swapchain")(e)),
         };
 
         self.swapchain = swapchain;
         self.render_targets = render_targets;
-        self.frame_buffers = util::create_frame_buffers(&self.render_targets, &self.render_pass);
+        self.frame_buffers = util::create_frame_buffers(
+            &self.render_targets,
+            &self.render_pass,
+            &self.memory_alloc,
+            self.samples,
+        )?;
         self.viewport = util::create_viewport(util::get_surface_dimensions(surface));
+
+        Ok(())
     }
 }
+
+#[cfg(feature = "hot-reload")]
+fn load_shaders(device: &Arc<Device>) -> TerraResult<ShaderLoader> {
+    let mut shaders = ShaderLoader::load(device)?;
+
+    // Only sources mounted from a directory can be watched, embedded shaders stay as built
+    let root = match vfs::local_path("shaders://") {
+        Some(root) => root,
+        None => return Ok(shaders),
+    };
+
+    if let Err(e) = shaders.watch(root.clone()) {
+        eprintln!("Failed to watch shaders at {}: {e}", root.display());
+    }
+
+    Ok(shaders)
+}
+
+#[cfg(not(feature = "hot-reload"))]
+fn load_shaders(device: &Arc<Device>) -> TerraResult<ShaderLoader> {
+    ShaderLoader::load(device)
+}
@@ -1,32 +1,50 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+use std::{cell::RefCell, rc::Rc, sync::Arc};
 
 use vulkano::{
     buffer::{BufferUsage, Subbuffer},
-    format::Format,
-    image::{view::ImageView, ImageDimensions, ImmutableImage},
+    command_buffer::{ClearColorImageInfo, CommandBufferExecFuture},
+    device::Device,
+    format::{ClearColorValue, Format},
+    image::{view::ImageView, ImageDimensions, ImmutableImage, MipmapsCount, StorageImage},
     memory::allocator::MemoryUsage,
+    sync::future::{FenceSignalFuture, NowFuture},
 };
 
-use crate::terra::{
-    data::{SpriteData, Vertex},
-    util,
+use crate::{
+    sprite::{texture, Sprite},
+    terra::{
+        assets::{loader::Loader, Assets, Handle, LoadProgress, LoadState},
+        data::{Color, LightsData, Mipmaps, SpriteData, Vertex, MAX_LIGHTS, SHADOW_MAP_RESOLUTION},
+        error::{TerraError, TerraResult},
+        programs::CommandBuilder,
+        util,
+    },
 };
 
 use super::gpu::GpuResources;
 
+#[cfg(feature = "hot-reload")]
+use {
+    crate::{terra::watcher::FileWatcher, vfs},
+    std::{collections::HashMap, fs, path::PathBuf},
+};
+
 pub struct SpriteResources {
     image: Arc<ImageView<ImmutableImage>>,
     vertex_buffer: Subbuffer<[Vertex]>,
+    dimensions: [u32; 2],
 }
 
 impl SpriteResources {
     pub fn new(
         image: Arc<ImageView<ImmutableImage>>,
         vertex_buffer: Subbuffer<[Vertex]>,
+        dimensions: [u32; 2],
     ) -> SpriteResources {
         SpriteResources {
             image,
             vertex_buffer,
+            dimensions,
         }
     }
 
@@ -37,16 +55,46 @@ impl SpriteResources {
     pub fn vertex_buffer(&self) -> &Subbuffer<[Vertex]> {
         &self.vertex_buffer
     }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.dimensions
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum TextureKind {
+    Sprite,
+    NormalMap,
+}
+
+// Textures copied to the GPU by one submission, added to their assets once its fence signals
+struct PendingUpload {
+    fence: FenceSignalFuture<CommandBufferExecFuture<NowFuture>>,
+    sprites: Vec<(u64, SpriteResources)>,
+    normal_maps: Vec<(u64, Arc<ImageView<ImmutableImage>>)>,
 }
 
 pub struct GraphicsResources {
     resources: Rc<RefCell<GpuResources>>,
-    sprites: HashMap<u64, SpriteResources>,
+    sprites: Assets<SpriteResources>,
     sprite_index_buffer: Subbuffer<[u32]>,
+    normal_maps: Assets<Arc<ImageView<ImmutableImage>>>,
+    loader: Loader<(TextureKind, u64), SpriteData>,
+    uploads: Vec<PendingUpload>,
+    #[cfg(feature = "hot-reload")]
+    watchers: Vec<(String, PathBuf, FileWatcher)>,
+    // Sprites behind every loaded texture, so they can be loaded again when their file changes
+    #[cfg(feature = "hot-reload")]
+    sources: HashMap<(TextureKind, u64), Sprite>,
+    flat_normal_map: Arc<ImageView<ImmutableImage>>,
+    white_texture: Arc<ImageView<ImmutableImage>>,
+    lights_buffer: Subbuffer<LightsData>,
+    // One per frame in flight, since each frame writes it before drawing
+    shadow_maps: Vec<Arc<ImageView<StorageImage>>>,
 }
 
 impl GraphicsResources {
-    pub fn new(resources: &Rc<RefCell<GpuResources>>) -> GraphicsResources {
+    pub fn new(resources: &Rc<RefCell<GpuResources>>) -> TerraResult<GraphicsResources> {
         let _resources = resources.borrow();
         let allocator = _resources.memory_alloc();
         let sprite_index_buffer = util::buffer_from_iter(
@@ -54,13 +102,91 @@ impl GraphicsResources {
             [0, 1, 2, 1, 0, 3].into_iter(),
             BufferUsage::INDEX_BUFFER,
             MemoryUsage::Upload,
-        );
+        )?;
+
+        // Used by lit sprites without a normal map, points straight out of the screen
+        let flat_normal_map = util::create_immutable_image(
+            allocator,
+            _resources.command_buffer_alloc(),
+            _resources.queue(),
+            [128u8, 128, 255, 255],
+            ImageDimensions::Dim2d {
+                width: 1,
+                height: 1,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_UNORM,
+        )?;
+
+        // Used by meshes without a sprite so only their vertex colors show
+        let white_texture = util::create_immutable_image(
+            allocator,
+            _resources.command_buffer_alloc(),
+            _resources.queue(),
+            [255u8, 255, 255, 255],
+            ImageDimensions::Dim2d {
+                width: 1,
+                height: 1,
+                array_layers: 1,
+            },
+            MipmapsCount::One,
+            Format::R8G8B8A8_SRGB,
+        )?;
+
+        let lights_buffer = util::buffer_from_data(
+            allocator,
+            LightsData::new(&Color::new(), &[]),
+            BufferUsage::UNIFORM_BUFFER,
+            MemoryUsage::Upload,
+        )?;
+
+        // Nearest occluder distance for each angle around each light
+        let shadow_maps = (0.._resources.frames_in_flight())
+            .map(|_| {
+                util::create_storage_image(
+                    allocator,
+                    _resources.queue(),
+                    ImageDimensions::Dim2d {
+                        width: SHADOW_MAP_RESOLUTION,
+                        height: MAX_LIGHTS as u32,
+                        array_layers: 1,
+                    },
+                    Format::R32_SFLOAT,
+                )
+            })
+            .collect::<TerraResult<Vec<_>>>()?;
+
+        // The shadow pass is skipped when no light casts shadows, so every map starts out as
+        // nothing blocking any light
+        let mut builder =
+            util::create_command_builder(_resources.command_buffer_alloc(), _resources.queue())?;
+        for shadow_map in shadow_maps.iter() {
+            builder
+                .clear_color_image(ClearColorImageInfo {
+                    clear_value: ClearColorValue::Float([f32::MAX; 4]),
+                    ..ClearColorImageInfo::image(shadow_map.image().clone())
+                })
+                .map_err(TerraError::vulkan("clear a shadow map"))?;
+        }
+        util::submit_and_wait(builder, _resources.queue())?;
 
-        GraphicsResources {
+        Ok(GraphicsResources {
             resources: resources.clone(),
-            sprites: HashMap::new(),
+            sprites: Assets::new(),
             sprite_index_buffer,
-        }
+            normal_maps: Assets::new(),
+            loader: Loader::new(),
+            uploads: vec![],
+            #[cfg(feature = "hot-reload")]
+            watchers: watch_assets(),
+            #[cfg(feature = "hot-reload")]
+            sources: HashMap::new(),
+            flat_normal_map,
+            white_texture,
+            lights_buffer,
+            shadow_maps,
+        })
     }
 
     pub fn sprite(&self, id: &u64) -> Option<&SpriteResources> {
@@ -71,37 +197,316 @@ impl GraphicsResources {
         &self.sprite_index_buffer
     }
 
-    pub fn add_sprite(&mut self, id: u64, data: SpriteData) {
+    // Sprites are decoded on the loader threads and uploaded by upload_loaded. The handle can
+    // be used right away, the sprite just isn't drawn until it's ready.
+    pub fn load_sprite(&mut self, sprite: &Sprite) -> Handle<SpriteResources> {
+        match self.sprites.handle(&sprite.id()) {
+            Some(handle) => handle,
+            None => {
+                self.spawn_load(TextureKind::Sprite, sprite);
+                self.sprites.reserve(sprite.id())
+            }
+        }
+    }
+
+    pub fn sprite_state(&self, id: &u64) -> Option<&LoadState> {
+        self.sprites.state(id)
+    }
+
+    pub fn normal_map(&self, id: &u64) -> Option<&Arc<ImageView<ImmutableImage>>> {
+        self.normal_maps.get(id)
+    }
+
+    pub fn flat_normal_map(&self) -> &Arc<ImageView<ImmutableImage>> {
+        &self.flat_normal_map
+    }
+
+    pub fn white_texture(&self) -> &Arc<ImageView<ImmutableImage>> {
+        &self.white_texture
+    }
+
+    pub fn load_normal_map(&mut self, sprite: &Sprite) -> Handle<Arc<ImageView<ImmutableImage>>> {
+        match self.normal_maps.handle(&sprite.id()) {
+            Some(handle) => handle,
+            None => {
+                self.spawn_load(TextureKind::NormalMap, sprite);
+                self.normal_maps.reserve(sprite.id())
+            }
+        }
+    }
+
+    // Sprites and normal maps still loading, loaded or failed
+    pub fn load_progress(&self) -> LoadProgress {
+        self.sprites.progress() + self.normal_maps.progress()
+    }
+
+    // Uploads everything the loader threads finished since the last call in a single submission,
+    // without waiting on it. Textures become available on a later call, once the GPU is done
+    // copying them. Returns the ids of textures that replaced an older version so their
+    // descriptor sets can be rebuilt. A texture that can't be uploaded fails its asset, only a
+    // failed submission is returned as an error.
+    pub fn upload_loaded(&mut self) -> TerraResult<Vec<u64>> {
+        let replaced = self.finish_uploads()?;
+        let finished = self.loader.finished();
+
+        if finished.is_empty() {
+            return Ok(replaced);
+        }
+
+        let resources = self.resources.clone();
+        let resources = resources.borrow();
+        let mut builder =
+            util::create_command_builder(resources.command_buffer_alloc(), resources.queue())?;
+        let mut sprites = vec![];
+        let mut normal_maps = vec![];
+
+        for ((kind, id), result) in finished {
+            let data = match result {
+                Ok(data) => data,
+                Err(e) => {
+                    match kind {
+                        TextureKind::Sprite => self.sprites.fail(&id, e),
+                        TextureKind::NormalMap => self.normal_maps.fail(&id, e),
+                    }
+                    continue;
+                }
+            };
+
+            // Anything collected while it was loading isn't needed anymore
+            match kind {
+                TextureKind::Sprite if self.sprites.state(&id).is_some() => {
+                    match upload_sprite(&resources, &mut builder, data) {
+                        Ok(sprite) => sprites.push((id, sprite)),
+                        Err(e) => self.sprites.fail(&id, e.to_string()),
+                    }
+                }
+                TextureKind::NormalMap if self.normal_maps.state(&id).is_some() => {
+                    match upload_texture(&resources, &mut builder, data, false) {
+                        Ok(image) => normal_maps.push((id, image)),
+                        Err(e) => self.normal_maps.fail(&id, e.to_string()),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if sprites.is_empty() && normal_maps.is_empty() {
+            return Ok(replaced);
+        }
+
+        self.uploads.push(PendingUpload {
+            fence: util::submit(builder, resources.queue())?,
+            sprites,
+            normal_maps,
+        });
+        Ok(replaced)
+    }
+
+    // Adds the textures of every submission the GPU finished, oldest first so a texture
+    // reloaded twice ends up with its latest version
+    fn finish_uploads(&mut self) -> TerraResult<Vec<u64>> {
+        let mut replaced = vec![];
+
+        while let Some(upload) = self.uploads.first() {
+            let signaled = upload
+                .fence
+                .is_signaled()
+                .map_err(TerraError::vulkan("check an upload fence"))?;
+            if !signaled {
+                break;
+            }
+
+            let upload = self.uploads.remove(0);
+            for (id, sprite) in upload.sprites {
+                if self.sprites.state(&id).is_none() {
+                    continue;
+                }
+                if self.sprites.get(&id).is_some() {
+                    replaced.push(id);
+                }
+                self.sprites.insert(id, sprite);
+            }
+            for (id, image) in upload.normal_maps {
+                if self.normal_maps.state(&id).is_none() {
+                    continue;
+                }
+                if self.normal_maps.get(&id).is_some() {
+                    replaced.push(id);
+                }
+                self.normal_maps.insert(id, image);
+            }
+        }
+
+        Ok(replaced)
+    }
+
+    // Loads sprites whose files changed on disk again. Renderers keep drawing the old texture
+    // until the new one is uploaded, or for good if it fails to load.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_changed(&mut self) {
+        let mut changed = vec![];
+        for (scheme, root, watcher) in self.watchers.iter() {
+            for path in watcher.changed() {
+                if let Ok(relative) = path.strip_prefix(root) {
+                    let relative = relative.to_string_lossy().replace('\\', "/");
+                    changed.push(format!("{scheme}://{relative}"));
+                }
+            }
+        }
+
+        let reloads: Vec<(TextureKind, Sprite)> = self
+            .sources
+            .iter()
+            .filter(|(_, sprite)| changed.iter().any(|path| path == sprite.path()))
+            .map(|((kind, _), sprite)| (*kind, sprite.clone()))
+            .collect();
+
+        for (kind, sprite) in reloads {
+            self.spawn_load(kind, &sprite);
+        }
+    }
+
+    #[cfg(not(feature = "hot-reload"))]
+    pub fn reload_changed(&mut self) {}
+
+    // Frees the sprites and normal maps nothing holds a handle to anymore. Frames still in flight
+    // keep what they use alive until they finish. Returns the ids of everything freed so cached
+    // descriptor sets can be dropped as well.
+    pub fn collect(&mut self) -> Vec<u64> {
+        let mut freed = self.sprites.collect();
+        freed.extend(self.normal_maps.collect());
+
+        #[cfg(feature = "hot-reload")]
+        self.sources.retain(|(kind, id), _| match kind {
+            TextureKind::Sprite => self.sprites.state(id).is_some(),
+            TextureKind::NormalMap => self.normal_maps.state(id).is_some(),
+        });
+
+        freed
+    }
+
+    pub fn lights_buffer(&self) -> &Subbuffer<LightsData> {
+        &self.lights_buffer
+    }
+
+    // Uploaded into a fresh buffer every frame so the previous frame can still read the old one
+    pub fn update_lights(&mut self, data: LightsData) -> TerraResult<()> {
         let resources = self.resources.borrow();
-        let allocator = resources.memory_alloc();
-        let command_buffer_alloc = resources.command_buffer_alloc();
-        let queue = resources.queue();
 
-        let vertex_buffer = util::buffer_from_iter(
-            allocator,
-            data.vertices.into_iter(),
-            BufferUsage::VERTEX_BUFFER,
+        self.lights_buffer = util::buffer_from_data(
+            resources.memory_alloc(),
+            data,
+            BufferUsage::UNIFORM_BUFFER,
             MemoryUsage::Upload,
-        );
+        )?;
+        Ok(())
+    }
 
-        let image = util::create_immutable_image(
-            allocator,
-            command_buffer_alloc,
-            queue,
-            data.pixels.into_iter(),
-            ImageDimensions::Dim2d {
-                width: data.width,
-                height: data.height,
-                array_layers: 1,
-            },
-            Format::R8G8B8A8_SRGB,
-        );
+    // The shadow map of the frame being recorded
+    pub fn shadow_map(&self) -> &Arc<ImageView<StorageImage>> {
+        &self.shadow_maps[self.resources.borrow().frame_index()]
+    }
+
+    fn spawn_load(&mut self, kind: TextureKind, sprite: &Sprite) {
+        let device = self.resources.borrow().device().clone();
+        let sprite = sprite.clone();
 
-        self.sprites
-            .insert(id, SpriteResources::new(image, vertex_buffer));
+        #[cfg(feature = "hot-reload")]
+        self.sources.insert((kind, sprite.id()), sprite.clone());
+
+        // Normal maps hold directions rather than colors, so they skip the sRGB conversion
+        let srgb = matches!(kind, TextureKind::Sprite);
+
+        self.loader.spawn((kind, sprite.id()), move || {
+            prepare_texture(&device, sprite.path(), sprite.load()?, srgb)
+        });
     }
+}
 
-    pub fn remove_sprite(&mut self, id: &u64) {
-        self.sprites.remove(id);
+// Watches every directory mounted in the VFS so changed sprites are picked up. Directories
+// mounted after the graphics resources are created aren't watched.
+#[cfg(feature = "hot-reload")]
+fn watch_assets() -> Vec<(String, PathBuf, FileWatcher)> {
+    vfs::directories()
+        .into_iter()
+        .filter_map(|(scheme, root)| {
+            // Changed paths are reported in canonical form
+            let root = fs::canonicalize(&root).ok()?;
+
+            match FileWatcher::new(&root) {
+                Ok(watcher) => Some((scheme, root, watcher)),
+                Err(e) => {
+                    eprintln!("Failed to watch {}: {e}", root.display());
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+// Compressed textures the device can't sample are decompressed first. Mipmaps can't be
+// generated for compressed formats since they can't be blitted. Runs on the loader threads.
+fn prepare_texture(
+    device: &Arc<Device>,
+    path: &str,
+    data: SpriteData,
+    srgb: bool,
+) -> TerraResult<SpriteData> {
+    let supported = util::texture_format(data.format, srgb)
+        .is_some_and(|format| util::is_texture_format_supported(device, format));
+    let mut data = match supported {
+        true => data,
+        false => texture::decompress(path, data)?,
+    };
+
+    if data.format.is_compressed() && matches!(data.mipmaps, Mipmaps::Generate) {
+        data.mipmaps = Mipmaps::None;
     }
+
+    Ok(data)
+}
+
+fn upload_sprite(
+    resources: &GpuResources,
+    builder: &mut CommandBuilder,
+    data: SpriteData,
+) -> TerraResult<SpriteResources> {
+    let vertices = data.vertices;
+    let dimensions = [data.width, data.height];
+    let image = upload_texture(resources, builder, data, true)?;
+
+    let vertex_buffer = util::buffer_from_iter(
+        resources.memory_alloc(),
+        vertices,
+        BufferUsage::VERTEX_BUFFER,
+        MemoryUsage::Upload,
+    )?;
+
+    Ok(SpriteResources::new(image, vertex_buffer, dimensions))
+}
+
+fn upload_texture(
+    resources: &GpuResources,
+    builder: &mut CommandBuilder,
+    data: SpriteData,
+    srgb: bool,
+) -> TerraResult<Arc<ImageView<ImmutableImage>>> {
+    // Formats without a Vulkan equivalent were decompressed by prepare_texture
+    let format = util::texture_format(data.format, srgb).ok_or_else(|| {
+        TerraError::vulkan("upload a texture")(format!("no format for {:?}", data.format))
+    })?;
+
+    util::create_texture(
+        resources.memory_alloc(),
+        builder,
+        resources.queue(),
+        data.pixels,
+        data.mipmaps,
+        ImageDimensions::Dim2d {
+            width: data.width,
+            height: data.height,
+            array_layers: 1,
+        },
+        format,
+    )
 }
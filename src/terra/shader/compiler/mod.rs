@@ -0,0 +1,106 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{include, ShaderKind};
+
+// Runtime counterpart of build.rs, used to recompile shader sources while the app is running
+pub struct ShaderCompiler {
+    root: PathBuf,
+    compiler: shaderc::Compiler,
+}
+
+pub struct CompiledShader {
+    pub kind: ShaderKind,
+    pub name: String,
+    pub spirv: Vec<u32>,
+}
+
+impl ShaderCompiler {
+    pub fn new(root: PathBuf) -> ShaderCompiler {
+        ShaderCompiler {
+            root,
+            compiler: shaderc::Compiler::new().expect("Failed to create shader compiler"),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn sources(&self) -> Vec<PathBuf> {
+        let mut sources = vec![];
+        if let Err(e) = visit_dirs(&self.root, &mut sources) {
+            eprintln!("Failed to read {}: {e}", self.root.display());
+        }
+
+        sources.retain(|path| shader_kind(path).is_some());
+        sources
+    }
+
+    pub fn compile(&self, path: &Path) -> Result<CompiledShader, String> {
+        let kind = shader_kind(path).ok_or_else(|| format!("{}: not a shader", path.display()))?;
+        let name = path
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("{}: invalid file name", path.display()))?
+            .to_owned();
+
+        let source = path.to_string_lossy().replace('\\', "/");
+        let contents = fs::read_to_string(path).map_err(|e| format!("{source}: {e}"))?;
+
+        let root = &self.root;
+        let mut options =
+            shaderc::CompileOptions::new().ok_or("Failed to create compile options")?;
+        options.add_macro_definition("EP", Some("main"));
+        options.set_include_callback(|requested, include_type, requesting, _| {
+            let relative = include_type == shaderc::IncludeType::Relative;
+            include::resolve(root, requested, relative, requesting).map(
+                |(resolved_name, content)| shaderc::ResolvedInclude {
+                    resolved_name,
+                    content,
+                },
+            )
+        });
+
+        let shader_kind = match kind {
+            ShaderKind::Vertex => shaderc::ShaderKind::Vertex,
+            ShaderKind::Fragment => shaderc::ShaderKind::Fragment,
+            ShaderKind::Compute => shaderc::ShaderKind::Compute,
+        };
+
+        let binary = self
+            .compiler
+            .compile_into_spirv(&contents, shader_kind, &source, "main", Some(&options))
+            .map_err(|e| e.to_string())?;
+
+        Ok(CompiledShader {
+            kind,
+            name,
+            spirv: binary.as_binary().to_vec(),
+        })
+    }
+}
+
+pub fn shader_kind(path: &Path) -> Option<ShaderKind> {
+    match path.extension()?.to_str()? {
+        "vs" => Some(ShaderKind::Vertex),
+        "fs" => Some(ShaderKind::Fragment),
+        "cs" | "comp" => Some(ShaderKind::Compute),
+        _ => None,
+    }
+}
+
+fn visit_dirs(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            visit_dirs(&path, paths)?;
+        } else {
+            paths.push(path);
+        }
+    }
+
+    Ok(())
+}
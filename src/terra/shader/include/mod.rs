@@ -0,0 +1,34 @@
+use std::{fs, path::Path};
+
+// Resolves an `#include` directive. Quoted includes are looked up next to the including file
+// first, and every include falls back to the shaders root so shared snippets can live anywhere
+// under it. Returns the resolved name used in diagnostics along with the file contents.
+pub fn resolve(
+    root: &Path,
+    requested: &str,
+    relative: bool,
+    requesting: &str,
+) -> Result<(String, String), String> {
+    let mut candidates = vec![];
+
+    if relative {
+        if let Some(parent) = Path::new(requesting).parent() {
+            candidates.push(parent.join(requested));
+        }
+    }
+    candidates.push(root.join(requested));
+
+    for candidate in candidates {
+        if candidate.is_file() {
+            let name = candidate.to_string_lossy().replace('\\', "/");
+
+            return fs::read_to_string(&candidate)
+                .map(|content| (name, content))
+                .map_err(|e| format!("Failed to read include {}: {e}", candidate.display()));
+        }
+    }
+
+    Err(format!(
+        "Failed to find include \"{requested}\" requested by {requesting}"
+    ))
+}
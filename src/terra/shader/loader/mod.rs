@@ -1,18 +1,71 @@
 use std::{collections::HashMap, sync::Arc};
 
-use vulkano::{device::Device, shader::ShaderModule};
+use vulkano::{
+    device::Device,
+    shader::{EntryPoint, ShaderModule},
+};
+
+use super::{reflect::ShaderReflection, ShaderKind, SHADERS};
+use crate::terra::{
+    error::{TerraError, TerraResult},
+    util,
+};
+
+#[cfg(feature = "hot-reload")]
+use {
+    super::compiler::{self, ShaderCompiler},
+    crate::terra::watcher::FileWatcher,
+    std::path::PathBuf,
+};
 
 pub struct ShaderLoader {
     vertex_shaders: HashMap<String, Arc<ShaderModule>>,
     fragment_shaders: HashMap<String, Arc<ShaderModule>>,
+    compute_shaders: HashMap<String, Arc<ShaderModule>>,
+    reflections: HashMap<(ShaderKind, String), ShaderReflection>,
+    #[cfg(feature = "hot-reload")]
+    hot_reload: Option<(FileWatcher, ShaderCompiler)>,
+    // What the last reload replaced, so it can be reverted if pipelines don't link
+    #[cfg(feature = "hot-reload")]
+    replaced: Vec<ReplacedShader>,
+}
+
+#[cfg(feature = "hot-reload")]
+struct ReplacedShader {
+    kind: ShaderKind,
+    name: String,
+    module: Option<Arc<ShaderModule>>,
+    reflection: Option<ShaderReflection>,
 }
 
 impl ShaderLoader {
-    pub fn load(device: &Arc<Device>) -> ShaderLoader {
-        ShaderLoader {
-            vertex_shaders: Self::load_shaders(device, "vertex"),
-            fragment_shaders: Self::load_shaders(device, "fragment"),
+    pub fn load(device: &Arc<Device>) -> TerraResult<ShaderLoader> {
+        let mut loader = ShaderLoader {
+            vertex_shaders: HashMap::new(),
+            fragment_shaders: HashMap::new(),
+            compute_shaders: HashMap::new(),
+            reflections: HashMap::new(),
+            #[cfg(feature = "hot-reload")]
+            hot_reload: None,
+            #[cfg(feature = "hot-reload")]
+            replaced: vec![],
+        };
+
+        for shader in SHADERS.iter() {
+            let module = unsafe { ShaderModule::from_bytes(device.clone(), shader.spirv) }
+                .map_err(|e| TerraError::shader(shader.source, e))?;
+            let reflection = ShaderReflection::from_bytes(shader.spirv)
+                .map_err(|e| TerraError::shader(shader.source, e))?;
+
+            loader
+                .shaders_mut(shader.kind)
+                .insert(shader.name.to_owned(), module);
+            loader
+                .reflections
+                .insert((shader.kind, shader.name.to_owned()), reflection);
         }
+
+        Ok(loader)
     }
 
     pub fn vertex(&self, name: &str) -> Option<&Arc<ShaderModule>> {
@@ -23,40 +76,142 @@ impl ShaderLoader {
         self.fragment_shaders.get(name)
     }
 
-    fn load_shaders(device: &Arc<Device>, kind: &str) -> HashMap<String, Arc<ShaderModule>> {
-        let mut shaders_path = String::from("./src/shaders/");
-        shaders_path.push_str(kind);
-
-        let mut shaders: HashMap<String, Arc<ShaderModule>> = HashMap::new();
-
-        std::fs::read_dir(shaders_path).unwrap().for_each(|e| {
-            if let Ok(e) = e {
-                if let Ok(_) = e.metadata() {
-                    let filename = e.file_name().to_str().unwrap().to_owned();
-                    let path = e.path();
-                    let ext = path
-                        .extension()
-                        .expect("Failed to get extension")
-                        .to_str()
-                        .expect("Failed to convert extension");
-                    if ext == "spv" {
-                        let path = e.path().to_str().unwrap().to_owned();
-                        if let Ok(contents) = std::fs::read(path) {
-                            unsafe {
-                                if let Ok(module) =
-                                    ShaderModule::from_bytes(device.clone(), &contents)
-                                {
-                                    let name = &filename[..filename.len() - 4];
-
-                                    shaders.insert(name.into(), module);
-                                }
-                            }
-                        }
-                    }
+    pub fn compute(&self, name: &str) -> Option<&Arc<ShaderModule>> {
+        self.compute_shaders.get(name)
+    }
+
+    pub fn reflection(&self, kind: ShaderKind, name: &str) -> Option<&ShaderReflection> {
+        self.reflections.get(&(kind, name.to_owned()))
+    }
+
+    // Used to build pipelines, fails for shaders that were never loaded
+    pub fn entry_point(&self, kind: ShaderKind, name: &str) -> TerraResult<EntryPoint<'_>> {
+        let shader = match kind {
+            ShaderKind::Vertex => self.vertex(name),
+            ShaderKind::Fragment => self.fragment(name),
+            ShaderKind::Compute => self.compute(name),
+        };
+
+        let shader = shader.ok_or_else(|| TerraError::shader(name, "not found"))?;
+        util::get_shader_entry_point(shader, name)
+    }
+
+    // Starts watching the GLSL sources under root. Changed files are recompiled by reload.
+    #[cfg(feature = "hot-reload")]
+    pub fn watch(&mut self, root: impl Into<PathBuf>) -> notify::Result<()> {
+        let root = root.into();
+        let watcher = FileWatcher::new(&root)?;
+        self.hot_reload = Some((watcher, ShaderCompiler::new(root)));
+
+        Ok(())
+    }
+
+    // Recompiles changed sources and returns the shaders that were replaced. A shader that fails
+    // to compile is logged and keeps its last good module. The replaced modules are kept until
+    // the next reload in case the new ones don't link, see revert.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload(&mut self, device: &Arc<Device>) -> Vec<(ShaderKind, String)> {
+        self.replaced.clear();
+
+        let (watcher, compiler) = match &self.hot_reload {
+            Some(hot_reload) => hot_reload,
+            None => return vec![],
+        };
+
+        let changed = watcher.changed();
+        if changed.is_empty() {
+            return vec![];
+        }
+
+        // An edited include can affect any shader, so everything is rebuilt
+        let sources = if changed
+            .iter()
+            .all(|path| compiler::shader_kind(path).is_some())
+        {
+            changed
+        } else {
+            compiler.sources()
+        };
+
+        let mut compiled = vec![];
+        for path in sources.iter() {
+            match compiler.compile(path) {
+                Ok(shader) => compiled.push(shader),
+                Err(e) => eprintln!("Failed to compile shader:\n{e}"),
+            }
+        }
+
+        let mut reloaded = vec![];
+        for shader in compiled {
+            let module = unsafe { ShaderModule::from_words(device.clone(), &shader.spirv) };
+            let reflection = ShaderReflection::new(&shader.spirv);
+
+            match (module, reflection) {
+                (Ok(module), Ok(reflection)) => {
+                    let module = self
+                        .shaders_mut(shader.kind)
+                        .insert(shader.name.clone(), module);
+                    let reflection = self
+                        .reflections
+                        .insert((shader.kind, shader.name.clone()), reflection);
+
+                    self.replaced.push(ReplacedShader {
+                        kind: shader.kind,
+                        name: shader.name.clone(),
+                        module,
+                        reflection,
+                    });
+                    reloaded.push((shader.kind, shader.name));
                 }
+                (Err(e), _) => eprintln!("Failed to load shader {}: {e}", shader.name),
+                (_, Err(e)) => eprintln!("Failed to reflect shader {}: {e}", shader.name),
             }
-        });
+        }
+
+        reloaded
+    }
+
+    #[cfg(not(feature = "hot-reload"))]
+    pub fn reload(&mut self, _device: &Arc<Device>) -> Vec<(ShaderKind, String)> {
+        vec![]
+    }
 
-        shaders
+    // Puts back the modules the last reload replaced, so pipelines built later don't pick up
+    // shaders that failed to link. Returns the shaders that changed back.
+    #[cfg(feature = "hot-reload")]
+    pub fn revert(&mut self) -> Vec<(ShaderKind, String)> {
+        let mut reverted = vec![];
+
+        for replaced in std::mem::take(&mut self.replaced) {
+            let key = (replaced.kind, replaced.name.clone());
+
+            match replaced.module {
+                Some(module) => self
+                    .shaders_mut(replaced.kind)
+                    .insert(replaced.name.clone(), module),
+                None => self.shaders_mut(replaced.kind).remove(&replaced.name),
+            };
+            match replaced.reflection {
+                Some(reflection) => self.reflections.insert(key.clone(), reflection),
+                None => self.reflections.remove(&key),
+            };
+
+            reverted.push(key);
+        }
+
+        reverted
+    }
+
+    #[cfg(not(feature = "hot-reload"))]
+    pub fn revert(&mut self) -> Vec<(ShaderKind, String)> {
+        vec![]
+    }
+
+    fn shaders_mut(&mut self, kind: ShaderKind) -> &mut HashMap<String, Arc<ShaderModule>> {
+        match kind {
+            ShaderKind::Vertex => &mut self.vertex_shaders,
+            ShaderKind::Fragment => &mut self.fragment_shaders,
+            ShaderKind::Compute => &mut self.compute_shaders,
+        }
     }
 }
@@ -1 +1,22 @@
+#[cfg(feature = "hot-reload")]
+pub mod compiler;
+pub mod include;
 pub mod loader;
+pub mod reflect;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderKind {
+    Vertex,
+    Fragment,
+    Compute,
+}
+
+// SPIR-V compiled by build.rs and embedded into the binary
+pub struct EmbeddedShader {
+    pub name: &'static str,
+    pub kind: ShaderKind,
+    pub source: &'static str,
+    pub spirv: &'static [u8],
+}
+
+include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
@@ -0,0 +1,259 @@
+use vulkano::shader::spirv::{
+    Decoration, Id, Instruction, Spirv, SpirvError, StorageClass, StructMemberInfo,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UniformType {
+    Float,
+    Int,
+    UInt,
+    Vec2,
+    Vec3,
+    Vec4,
+    Mat4,
+    Other,
+}
+
+#[derive(Clone, Debug)]
+pub struct UniformMember {
+    pub name: String,
+    pub ty: UniformType,
+    pub offset: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct UniformBlock {
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+    pub size: u32,
+    pub members: Vec<UniformMember>,
+}
+
+impl UniformBlock {
+    pub fn member(&self, name: &str) -> Option<&UniformMember> {
+        self.members.iter().find(|member| member.name == name)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SamplerBinding {
+    pub name: String,
+    pub set: u32,
+    pub binding: u32,
+}
+
+// Uniform blocks and combined image samplers declared by a shader, read from its SPIR-V
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    uniform_blocks: Vec<UniformBlock>,
+    samplers: Vec<SamplerBinding>,
+}
+
+impl ShaderReflection {
+    pub fn new(words: &[u32]) -> Result<ShaderReflection, SpirvError> {
+        let spirv = Spirv::new(words)?;
+        let mut reflection = ShaderReflection::default();
+
+        for instruction in spirv.iter_global() {
+            let (pointer, variable) = match instruction {
+                Instruction::Variable {
+                    result_type_id,
+                    result_id,
+                    storage_class: StorageClass::Uniform | StorageClass::UniformConstant,
+                    ..
+                } => (*result_type_id, *result_id),
+                _ => continue,
+            };
+
+            let (set, binding) = match descriptor_binding(&spirv, variable) {
+                Some(binding) => binding,
+                None => continue,
+            };
+
+            let ty = match spirv.id(pointer).instruction() {
+                Instruction::TypePointer { ty, .. } => *ty,
+                _ => continue,
+            };
+
+            match spirv.id(ty).instruction() {
+                Instruction::TypeStruct { member_types, .. } => {
+                    let members = spirv
+                        .id(ty)
+                        .iter_members()
+                        .zip(member_types.iter())
+                        .map(|(member, member_type)| UniformMember {
+                            name: member
+                                .iter_name()
+                                .find_map(|instruction| match instruction {
+                                    Instruction::MemberName { name, .. } => Some(name.clone()),
+                                    _ => None,
+                                })
+                                .unwrap_or_default(),
+                            ty: uniform_type(&spirv, *member_type),
+                            offset: member_offset(&member),
+                        })
+                        .collect();
+
+                    reflection.uniform_blocks.push(UniformBlock {
+                        name: name(&spirv, ty),
+                        set,
+                        binding,
+                        size: type_size(&spirv, ty).next_multiple_of(16),
+                        members,
+                    });
+                }
+                Instruction::TypeSampledImage { .. } => {
+                    reflection.samplers.push(SamplerBinding {
+                        name: name(&spirv, variable),
+                        set,
+                        binding,
+                    });
+                }
+                _ => (),
+            }
+        }
+
+        Ok(reflection)
+    }
+
+    // Embedded shaders are stored as bytes, so they are reassembled into words first
+    pub fn from_bytes(bytes: &[u8]) -> Result<ShaderReflection, SpirvError> {
+        let words = bytes
+            .chunks_exact(4)
+            .map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+            .collect::<Vec<_>>();
+
+        ShaderReflection::new(&words)
+    }
+
+    pub fn uniform_blocks(&self) -> &[UniformBlock] {
+        &self.uniform_blocks
+    }
+
+    pub fn samplers(&self) -> &[SamplerBinding] {
+        &self.samplers
+    }
+
+    pub fn uniform_block(&self, set: u32, binding: u32) -> Option<&UniformBlock> {
+        self.uniform_blocks
+            .iter()
+            .find(|block| block.set == set && block.binding == binding)
+    }
+}
+
+fn name(spirv: &Spirv, id: Id) -> String {
+    spirv
+        .id(id)
+        .iter_name()
+        .find_map(|instruction| match instruction {
+            Instruction::Name { name, .. } => Some(name.clone()),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+fn member_offset(member: &StructMemberInfo<'_>) -> u32 {
+    member
+        .iter_decoration()
+        .find_map(|instruction| match instruction {
+            Instruction::MemberDecorate {
+                decoration: Decoration::Offset { byte_offset },
+                ..
+            } => Some(*byte_offset),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+fn descriptor_binding(spirv: &Spirv, id: Id) -> Option<(u32, u32)> {
+    let mut set = None;
+    let mut binding = None;
+
+    for instruction in spirv.id(id).iter_decoration() {
+        match instruction {
+            Instruction::Decorate {
+                decoration: Decoration::DescriptorSet { descriptor_set },
+                ..
+            } => set = Some(*descriptor_set),
+            Instruction::Decorate {
+                decoration: Decoration::Binding { binding_point },
+                ..
+            } => binding = Some(*binding_point),
+            _ => (),
+        }
+    }
+
+    Some((set?, binding?))
+}
+
+fn uniform_type(spirv: &Spirv, id: Id) -> UniformType {
+    match spirv.id(id).instruction() {
+        Instruction::TypeFloat { width: 32, .. } => UniformType::Float,
+        Instruction::TypeInt {
+            width: 32,
+            signedness,
+            ..
+        } => match signedness {
+            0 => UniformType::UInt,
+            _ => UniformType::Int,
+        },
+        Instruction::TypeVector {
+            component_type,
+            component_count,
+            ..
+        } if uniform_type(spirv, *component_type) == UniformType::Float => match component_count {
+            2 => UniformType::Vec2,
+            3 => UniformType::Vec3,
+            4 => UniformType::Vec4,
+            _ => UniformType::Other,
+        },
+        Instruction::TypeMatrix {
+            column_type,
+            column_count: 4,
+            ..
+        } if uniform_type(spirv, *column_type) == UniformType::Vec4 => UniformType::Mat4,
+        _ => UniformType::Other,
+    }
+}
+
+// Sizes follow std140, where every matrix column and array element is padded to 16 bytes
+fn type_size(spirv: &Spirv, id: Id) -> u32 {
+    let info = spirv.id(id);
+
+    match info.instruction() {
+        Instruction::TypeFloat { width, .. } | Instruction::TypeInt { width, .. } => width / 8,
+        Instruction::TypeVector {
+            component_type,
+            component_count,
+            ..
+        } => type_size(spirv, *component_type) * component_count,
+        Instruction::TypeMatrix { column_count, .. } => 16 * column_count,
+        Instruction::TypeArray { length, .. } => {
+            let stride = info
+                .iter_decoration()
+                .find_map(|instruction| match instruction {
+                    Instruction::Decorate {
+                        decoration: Decoration::ArrayStride { array_stride },
+                        ..
+                    } => Some(*array_stride),
+                    _ => None,
+                })
+                .unwrap_or(16);
+
+            let length = match spirv.id(*length).instruction() {
+                Instruction::Constant { value, .. } => value.first().copied().unwrap_or(0),
+                _ => 0,
+            };
+
+            stride * length
+        }
+        Instruction::TypeStruct { member_types, .. } => info
+            .iter_members()
+            .zip(member_types.iter())
+            .map(|(member, member_type)| member_offset(&member) + type_size(spirv, *member_type))
+            .max()
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
@@ -3,7 +3,8 @@ use std::sync::Arc;
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy,
+        CommandBufferExecFuture, CommandBufferUsage, CopyBufferToImageInfo,
         PrimaryCommandBufferAbstract,
     },
     descriptor_set::{
@@ -12,77 +13,120 @@ use vulkano::{
     },
     device::{
         physical::{PhysicalDevice, PhysicalDeviceType},
-        Device, DeviceCreateInfo, DeviceExtensions, Queue, QueueCreateInfo, QueueFlags,
+        Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags,
+    },
+    format::{CompressionType, Format, FormatFeatures},
+    image::{
+        view::{ImageView, ImageViewAbstract},
+        AttachmentImage, ImageAccess, ImageCreateFlags, ImageDimensions, ImageLayout,
+        ImageSubresourceLayers, ImageUsage, ImmutableImage, MipmapsCount, SampleCount,
+        StorageImage, SwapchainImage,
     },
-    format::Format,
-    image::{view::ImageView, ImageDimensions, ImageUsage, ImmutableImage, SwapchainImage},
     instance::{Instance, InstanceCreateInfo, InstanceExtensions},
     memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
     pipeline::{
         graphics::{
             input_assembly::InputAssemblyState,
+            multisample::MultisampleState,
             vertex_input::Vertex as BaseVertex,
             viewport::{Viewport, ViewportState},
         },
-        GraphicsPipeline,
+        ComputePipeline, GraphicsPipeline,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sampler::{
+        Filter, Sampler, SamplerAddressMode, SamplerCreateInfo, SamplerMipmapMode, LOD_CLAMP_NONE,
     },
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
-    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
     shader::{EntryPoint, ShaderModule},
-    swapchain::{ColorSpace, Surface, Swapchain, SwapchainCreateInfo},
+    swapchain::{
+        ColorSpace, PresentMode, Surface, SurfaceCapabilities, Swapchain, SwapchainCreateInfo,
+    },
+    sync::{
+        future::{FenceSignalFuture, NowFuture},
+        FlushError, GpuFuture,
+    },
     Version, VulkanLibrary,
 };
 use vulkano_win::VkSurfaceBuild;
 use winit::{
+    dpi::LogicalSize,
     event_loop::EventLoop,
-    window::{Window, WindowBuilder},
+    window::{Fullscreen, Window, WindowBuilder},
 };
 
-use super::data::Vertex;
+use super::{
+    config::{GpuPreference, TerraConfig},
+    data::{FilterMode, Mipmaps, SamplerSettings, TextureFormat, Vertex, WrapMode},
+    error::{TerraError, TerraResult},
+    programs::CommandBuilder,
+};
 
-pub fn create_library() -> Arc<VulkanLibrary> {
-    VulkanLibrary::new().expect("Failed to create library.")
+pub fn create_library() -> TerraResult<Arc<VulkanLibrary>> {
+    VulkanLibrary::new().map_err(|e| TerraError::NoDevice(e.to_string()))
 }
 
-pub fn create_instance(library: &Arc<VulkanLibrary>) -> Arc<Instance> {
+pub fn create_instance(
+    library: &Arc<VulkanLibrary>,
+    config: &TerraConfig,
+) -> TerraResult<Arc<Instance>> {
     let app_info = InstanceCreateInfo {
-        application_name: Some("Hello Triangle".into()),
-        application_version: Version {
-            major: 1,
-            minor: 0,
-            patch: 0,
-        },
-        engine_name: Some("No Engine".into()),
+        application_name: Some(config.app_name.clone()),
+        application_version: config.app_version,
+        engine_name: Some("Terra".into()),
         engine_version: Version {
             major: 1,
             minor: 0,
             patch: 0,
         },
-        enabled_extensions: required_extensions(library, true),
+        enabled_extensions: required_extensions(library, config.validation),
+        enabled_layers: validation_layers(library, config.validation),
         ..Default::default()
     };
 
-    Instance::new(library.clone(), app_info).expect("failed to create Vulkan instance")
+    Instance::new(library.clone(), app_info).map_err(TerraError::vulkan("create the instance"))
 }
 
-pub fn create_surface(instance: &Arc<Instance>, events: &EventLoop<()>) -> Arc<Surface> {
+pub fn create_surface(
+    instance: &Arc<Instance>,
+    events: &EventLoop<()>,
+    config: &TerraConfig,
+) -> TerraResult<Arc<Surface>> {
+    let [width, height] = config.window_size;
+
     WindowBuilder::new()
+        .with_title(&config.window_title)
+        .with_inner_size(LogicalSize::new(width, height))
+        .with_resizable(config.resizable)
+        .with_fullscreen(config.fullscreen.then_some(Fullscreen::Borderless(None)))
         .build_vk_surface(events, instance.clone())
-        .expect("Failed to create Surface")
+        .map_err(TerraError::vulkan("create the window surface"))
 }
 
 pub fn create_device(
     instance: &Arc<Instance>,
     surface: &Arc<Surface>,
-) -> (Arc<Device>, Arc<Queue>) {
+    preference: &GpuPreference,
+) -> TerraResult<(Arc<Device>, Arc<Queue>)> {
     let device_extensions = DeviceExtensions {
         khr_swapchain: true,
         ..DeviceExtensions::empty()
     };
 
-    let (physical_device, queue_family_index) = get_phsyical_device(instance, surface);
+    let (physical_device, queue_family_index) = get_phsyical_device(instance, surface, preference)?;
+
+    // Anisotropic filtering and texture compression are optional, samplers fall back to no
+    // anisotropy and compressed textures are decompressed when they're missing
+    let supported = physical_device.supported_features();
+    let enabled_features = Features {
+        sampler_anisotropy: supported.sampler_anisotropy,
+        texture_compression_bc: supported.texture_compression_bc,
+        texture_compression_astc_ldr: supported.texture_compression_astc_ldr,
+        ..Features::empty()
+    };
+
     let create_info = DeviceCreateInfo {
         enabled_extensions: device_extensions,
+        enabled_features,
         queue_create_infos: vec![QueueCreateInfo {
             queue_family_index,
             ..Default::default()
@@ -90,12 +134,14 @@ pub fn create_device(
         ..Default::default()
     };
 
-    let (device, mut queues) =
-        Device::new(physical_device, create_info).expect("Failed to create logical device.");
+    let (device, mut queues) = Device::new(physical_device, create_info)
+        .map_err(TerraError::vulkan("create the logical device"))?;
 
-    let queue = queues.next().expect("Failed to obtain queue.");
+    let queue = queues
+        .next()
+        .ok_or_else(|| TerraError::NoDevice("the device didn't create a queue".into()))?;
 
-    (device, queue)
+    Ok((device, queue))
 }
 
 pub fn create_command_pool(device: &Arc<Device>) -> Arc<StandardCommandBufferAllocator> {
@@ -116,58 +162,152 @@ pub fn create_descriptor_set_pool(device: &Arc<Device>) -> Arc<StandardDescripto
 pub fn create_swap_chain(
     device: &Arc<Device>,
     surface: &Arc<Surface>,
-) -> (Arc<Swapchain>, Vec<Arc<SwapchainImage>>) {
+    config: &TerraConfig,
+) -> TerraResult<(Arc<Swapchain>, Vec<Arc<SwapchainImage>>)> {
     let surface_capabilities = device
         .physical_device()
         .surface_capabilities(surface, Default::default())
-        .expect("Failed to get surface capabilities.");
+        .map_err(TerraError::vulkan("get the surface capabilities"))?;
 
     let image_formats = device
         .physical_device()
         .surface_formats(surface, Default::default())
-        .expect("Failed to get surface formats.");
+        .map_err(TerraError::vulkan("get the surface formats"))?;
 
+    let present_mode = choose_present_mode(device, surface, config.present_mode)?;
     let window = get_surface_window(surface);
 
-    let (image_format, _) = image_formats
+    let (image_format, _) = config
+        .color_formats
         .iter()
-        .find(|f| f.0 == Format::B8G8R8A8_SRGB && f.1 == ColorSpace::SrgbNonLinear)
-        .unwrap_or(&image_formats[0]);
+        .find_map(|format| {
+            image_formats
+                .iter()
+                .find(|f| f.0 == *format && f.1 == ColorSpace::SrgbNonLinear)
+        })
+        .or(image_formats.first())
+        .ok_or_else(|| TerraError::NoDevice("the surface has no formats".into()))?;
 
     let create_info = SwapchainCreateInfo {
-        min_image_count: surface_capabilities.min_image_count,
+        min_image_count: min_image_count(&surface_capabilities, present_mode),
         image_format: Some(*image_format),
+        present_mode,
         image_extent: window.inner_size().into(),
         image_usage: ImageUsage::COLOR_ATTACHMENT,
         composite_alpha: surface_capabilities
             .supported_composite_alpha
             .into_iter()
             .next()
-            .expect("Failed to get compsite alpha"),
+            .ok_or_else(|| TerraError::NoDevice("the surface has no composite alpha".into()))?,
         ..Default::default()
     };
 
     Swapchain::new(device.clone(), surface.clone(), create_info)
-        .expect("Failed to create swapchain.")
+        .map_err(TerraError::vulkan("create the swapchain"))
 }
 
-pub fn create_render_pass(device: &Arc<Device>, format: Format) -> Arc<RenderPass> {
-    vulkano::single_pass_renderpass!(
-        device.clone(),
-        attachments: {
-            color: {
-                load: Clear,
-                store: Store,
-                format: format,
-                samples: 1,
+// Fifo is the only present mode every device supports, so it's used when the preferred one isn't
+pub fn choose_present_mode(
+    device: &Arc<Device>,
+    surface: &Arc<Surface>,
+    preferred: PresentMode,
+) -> TerraResult<PresentMode> {
+    let mut present_modes = device
+        .physical_device()
+        .surface_present_modes(surface)
+        .map_err(TerraError::vulkan("get the surface present modes"))?;
+
+    match present_modes.any(|mode| mode == preferred) {
+        true => Ok(preferred),
+        false => Ok(PresentMode::Fifo),
+    }
+}
+
+// Mailbox needs an image to spare so a new frame can always replace the one waiting to be shown
+pub fn min_image_count(capabilities: &SurfaceCapabilities, present_mode: PresentMode) -> u32 {
+    let count = match present_mode {
+        PresentMode::Mailbox => capabilities.min_image_count + 1,
+        _ => capabilities.min_image_count,
+    };
+
+    capabilities
+        .max_image_count
+        .map_or(count, |max| count.min(max))
+}
+
+// The highest sample count up to the requested one the device supports for color attachments
+pub fn choose_sample_count(device: &Arc<Device>, requested: u32) -> SampleCount {
+    let supported = device
+        .physical_device()
+        .properties()
+        .framebuffer_color_sample_counts;
+
+    [
+        SampleCount::Sample8,
+        SampleCount::Sample4,
+        SampleCount::Sample2,
+    ]
+    .into_iter()
+    .find(|&samples| samples as u32 <= requested && supported.contains_enum(samples))
+    .unwrap_or(SampleCount::Sample1)
+}
+
+// With multisampling the frame is drawn into a multisampled attachment that's resolved into the
+// swapchain image at the end of the pass
+pub fn create_render_pass(
+    device: &Arc<Device>,
+    format: Format,
+    samples: SampleCount,
+) -> TerraResult<Arc<RenderPass>> {
+    let render_pass = match samples {
+        SampleCount::Sample1 => vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                },
             },
-        },
-        pass: {
-            color: [color],
-            depth_stencil: {},
-        },
-    )
-    .expect("Failed to create render pass.")
+            pass: {
+                color: [color],
+                depth_stencil: {},
+            },
+        ),
+        samples => vulkano::single_pass_renderpass!(
+            device.clone(),
+            attachments: {
+                multisampled: {
+                    load: Clear,
+                    store: DontCare,
+                    format: format,
+                    samples: samples,
+                },
+                color: {
+                    load: DontCare,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                },
+            },
+            pass: {
+                color: [multisampled],
+                depth_stencil: {},
+                resolve: [color],
+            },
+        ),
+    };
+
+    render_pass.map_err(TerraError::vulkan("create the render pass"))
+}
+
+// Pipelines have to match the sample count of the pass they draw in
+pub fn multisample_state(subpass: &Subpass) -> MultisampleState {
+    MultisampleState {
+        rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+        ..Default::default()
+    }
 }
 
 pub fn create_graphics_pipeline(
@@ -175,40 +315,74 @@ pub fn create_graphics_pipeline(
     render_pass: &Arc<RenderPass>,
     vertex_shader: &Arc<ShaderModule>,
     fragment_shader: &Arc<ShaderModule>,
-) -> Arc<GraphicsPipeline> {
-    let vs = get_shader_entry_point(vertex_shader);
-    let fs = get_shader_entry_point(fragment_shader);
+) -> TerraResult<Arc<GraphicsPipeline>> {
+    let vs = get_shader_entry_point(vertex_shader, "vertex")?;
+    let fs = get_shader_entry_point(fragment_shader, "fragment")?;
     let subpass = render_pass.clone().first_subpass();
 
     GraphicsPipeline::start()
         .vertex_input_state(Vertex::per_vertex())
         .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .multisample_state(multisample_state(&subpass))
         .render_pass(subpass)
         .vertex_shader(vs, ())
         .fragment_shader(fs, ())
         .input_assembly_state(InputAssemblyState::default())
         .build(device.clone())
-        .expect("Failed to build graphics pipeline")
+        .map_err(TerraError::vulkan("build a graphics pipeline"))
 }
 
+pub fn create_compute_pipeline(
+    device: &Arc<Device>,
+    compute_shader: &Arc<ShaderModule>,
+    name: &str,
+) -> TerraResult<Arc<ComputePipeline>> {
+    let cs = get_shader_entry_point(compute_shader, name)?;
+
+    ComputePipeline::new(device.clone(), cs, &(), None, |_| {})
+        .map_err(TerraError::vulkan("build a compute pipeline"))
+}
+
+// Each framebuffer gets its own multisampled attachment when the render pass has one, so frames
+// in flight never draw into the same image
 pub fn create_frame_buffers(
     render_targets: &Vec<Arc<SwapchainImage>>,
     render_pass: &Arc<RenderPass>,
-) -> Vec<Arc<Framebuffer>> {
+    allocator: &StandardMemoryAllocator,
+    samples: SampleCount,
+) -> TerraResult<Vec<Arc<Framebuffer>>> {
     render_targets
         .iter()
         .map(|image| {
             let view = ImageView::new_default(image.clone())
-                .expect("Failed to create Image View for render target");
+                .map_err(TerraError::vulkan("create a render target view"))?;
+
+            let attachments: Vec<Arc<dyn ImageViewAbstract>> = match samples {
+                SampleCount::Sample1 => vec![view],
+                samples => {
+                    let multisampled = AttachmentImage::transient_multisampled(
+                        allocator,
+                        image.dimensions().width_height(),
+                        samples,
+                        image.format(),
+                    )
+                    .map_err(TerraError::vulkan("create a multisampled attachment"))?;
+                    let multisampled = ImageView::new_default(multisampled)
+                        .map_err(TerraError::vulkan("create a multisampled attachment view"))?;
+
+                    vec![multisampled, view]
+                }
+            };
+
             let create_info = FramebufferCreateInfo {
-                attachments: vec![view],
+                attachments,
                 ..Default::default()
             };
 
             Framebuffer::new(render_pass.clone(), create_info)
-                .expect("Failed to create Frame buffer")
+                .map_err(TerraError::vulkan("create a framebuffer"))
         })
-        .collect::<Vec<_>>()
+        .collect()
 }
 
 pub fn get_surface_window(surface: &Arc<Surface>) -> &Window {
@@ -229,30 +403,61 @@ pub fn create_viewport(dimensions: [u32; 2]) -> Viewport {
     }
 }
 
+// The preferred device if it's supported, otherwise the best ranked one
 fn get_phsyical_device(
     instance: &Arc<Instance>,
     surface: &Arc<Surface>,
-) -> (Arc<PhysicalDevice>, u32) {
+    preference: &GpuPreference,
+) -> TerraResult<(Arc<PhysicalDevice>, u32)> {
     let device_extensions = DeviceExtensions {
         khr_swapchain: true,
         ..DeviceExtensions::empty()
     };
 
-    let devices = instance
+    let devices: Vec<_> = instance
         .enumerate_physical_devices()
-        .expect("Failed to get physical devices.");
-
-    devices
-        .filter_map(|device| is_device_supported(&device, &device_extensions, surface))
-        .min_by_key(|(device, _)| match device.properties().device_type {
-            PhysicalDeviceType::DiscreteGpu => 0,
-            PhysicalDeviceType::IntegratedGpu => 1,
-            PhysicalDeviceType::VirtualGpu => 2,
-            PhysicalDeviceType::Cpu => 3,
-            PhysicalDeviceType::Other => 4,
-            _ => 5,
+        .map_err(TerraError::vulkan("list the physical devices"))?
+        .enumerate()
+        .filter_map(|(index, device)| {
+            Some((
+                index,
+                is_device_supported(&device, &device_extensions, surface)?,
+            ))
+        })
+        .collect();
+
+    let preferred = devices.iter().find(|(index, (device, _))| {
+        let properties = device.properties();
+        match preference {
+            GpuPreference::Any => false,
+            GpuPreference::Name(name) => properties
+                .device_name
+                .to_lowercase()
+                .contains(&name.to_lowercase()),
+            GpuPreference::Index(preferred) => index == preferred,
+            GpuPreference::Type(device_type) => properties.device_type == *device_type,
+        }
+    });
+
+    if preferred.is_none() && *preference != GpuPreference::Any {
+        eprintln!("No supported GPU matches {preference:?}, using the best available one");
+    }
+
+    preferred
+        .or_else(|| {
+            devices
+                .iter()
+                .min_by_key(|(_, (device, _))| match device.properties().device_type {
+                    PhysicalDeviceType::DiscreteGpu => 0,
+                    PhysicalDeviceType::IntegratedGpu => 1,
+                    PhysicalDeviceType::VirtualGpu => 2,
+                    PhysicalDeviceType::Cpu => 3,
+                    PhysicalDeviceType::Other => 4,
+                    _ => 5,
+                })
         })
-        .expect("No suitable physical device found")
+        .map(|(_, supported)| supported.clone())
+        .ok_or_else(|| TerraError::NoDevice("none support the swapchain extension".into()))
 }
 
 fn is_device_supported(
@@ -285,16 +490,40 @@ fn required_extensions(
 ) -> InstanceExtensions {
     let mut extensions = vulkano_win::required_extensions(library);
     if enable_validation_layers {
-        extensions.ext_debug_report = true;
+        extensions.ext_debug_report = library.supported_extensions().ext_debug_report;
     }
 
     extensions
 }
 
-pub fn get_shader_entry_point(shader: &Arc<ShaderModule>) -> EntryPoint<'_> {
+// The validation layer comes with the Vulkan SDK rather than the driver, so it's skipped with
+// a warning when it isn't installed
+fn validation_layers(library: &Arc<VulkanLibrary>, enable_validation_layers: bool) -> Vec<String> {
+    const VALIDATION_LAYER: &str = "VK_LAYER_KHRONOS_validation";
+
+    if !enable_validation_layers {
+        return vec![];
+    }
+
+    let installed = library
+        .layer_properties()
+        .is_ok_and(|mut layers| layers.any(|layer| layer.name() == VALIDATION_LAYER));
+
+    if !installed {
+        eprintln!("{VALIDATION_LAYER} isn't installed, running without validation");
+        return vec![];
+    }
+
+    vec![VALIDATION_LAYER.to_owned()]
+}
+
+pub fn get_shader_entry_point<'a>(
+    shader: &'a Arc<ShaderModule>,
+    name: &str,
+) -> TerraResult<EntryPoint<'a>> {
     shader
         .entry_point("main")
-        .expect("Failed to get vertex shader entry point.")
+        .ok_or_else(|| TerraError::shader(name, "has no main function"))
 }
 
 pub fn mat4_to_array(matrix: Mat4) -> [f32; 16] {
@@ -313,7 +542,7 @@ pub fn buffer_from_data<T>(
     data: T,
     buffer_usage: BufferUsage,
     memory_usage: MemoryUsage,
-) -> Subbuffer<T>
+) -> TerraResult<Subbuffer<T>>
 where
     T: BufferContents + Send + Sync,
 {
@@ -329,7 +558,7 @@ where
         },
         data,
     )
-    .expect("Failed to create Buffer")
+    .map_err(TerraError::vulkan("create a buffer"))
 }
 
 pub fn buffer_from_iter<I, T>(
@@ -337,7 +566,7 @@ pub fn buffer_from_iter<I, T>(
     iter: I,
     buffer_usage: BufferUsage,
     memory_usage: MemoryUsage,
-) -> Subbuffer<[T]>
+) -> TerraResult<Subbuffer<[T]>>
 where
     I: IntoIterator<Item = T>,
     I::IntoIter: ExactSizeIterator,
@@ -355,18 +584,48 @@ where
         },
         iter,
     )
-    .expect("Failed to create buffer.")
+    .map_err(TerraError::vulkan("create a buffer"))
 }
 
-pub fn create_sampler(device: &Arc<Device>) -> Arc<Sampler> {
+pub fn create_sampler(
+    device: &Arc<Device>,
+    settings: SamplerSettings,
+) -> TerraResult<Arc<Sampler>> {
+    let filter = match settings.filter {
+        FilterMode::Nearest => Filter::Nearest,
+        FilterMode::Linear => Filter::Linear,
+    };
+
+    let address_mode = match settings.wrap {
+        WrapMode::Clamp => SamplerAddressMode::ClampToEdge,
+        WrapMode::Repeat => SamplerAddressMode::Repeat,
+        WrapMode::Mirror => SamplerAddressMode::MirroredRepeat,
+    };
+
+    let anisotropy = if settings.anisotropy > 1 && device.enabled_features().sampler_anisotropy {
+        let limit = device.physical_device().properties().max_sampler_anisotropy;
+        Some((settings.anisotropy as f32).min(limit))
+    } else {
+        None
+    };
+
+    // Linear filtering blends between mip levels too, making it trilinear
+    let mipmap_mode = match settings.filter {
+        FilterMode::Nearest => SamplerMipmapMode::Nearest,
+        FilterMode::Linear => SamplerMipmapMode::Linear,
+    };
+
     let create_info = SamplerCreateInfo {
-        mag_filter: Filter::Linear,
-        min_filter: Filter::Linear,
-        address_mode: [SamplerAddressMode::Repeat; 3],
+        mag_filter: filter,
+        min_filter: filter,
+        mipmap_mode,
+        address_mode: [address_mode; 3],
+        anisotropy,
+        lod: 0.0..=LOD_CLAMP_NONE,
         ..Default::default()
     };
 
-    Sampler::new(device.clone(), create_info).expect("Failed to create sampler.")
+    Sampler::new(device.clone(), create_info).map_err(TerraError::vulkan("create a sampler"))
 }
 
 pub fn create_immutable_image<I, T>(
@@ -375,39 +634,253 @@ pub fn create_immutable_image<I, T>(
     queue: &Arc<Queue>,
     iter: I,
     dimensions: ImageDimensions,
+    mip_levels: MipmapsCount,
     format: Format,
-) -> Arc<ImageView<ImmutableImage>>
+) -> TerraResult<Arc<ImageView<ImmutableImage>>>
 where
     I: IntoIterator<Item = T>,
     I::IntoIter: ExactSizeIterator,
     T: BufferContents + Send + Sync,
 {
-    let mut builder = AutoCommandBufferBuilder::primary(
+    let mut builder = create_command_builder(command_buffer_alloc, queue)?;
+    let image_view = upload_image(
+        allocator,
+        &mut builder,
+        iter,
+        dimensions,
+        mip_levels,
+        format,
+    )?;
+    submit_and_wait(builder, queue)?;
+
+    Ok(image_view)
+}
+
+// Records the upload, the image can be used once the command buffer has finished
+pub fn upload_image<I, T>(
+    allocator: &StandardMemoryAllocator,
+    builder: &mut CommandBuilder,
+    iter: I,
+    dimensions: ImageDimensions,
+    mip_levels: MipmapsCount,
+    format: Format,
+) -> TerraResult<Arc<ImageView<ImmutableImage>>>
+where
+    I: IntoIterator<Item = T>,
+    I::IntoIter: ExactSizeIterator,
+    T: BufferContents + Send + Sync,
+{
+    let image = ImmutableImage::from_iter(allocator, iter, dimensions, mip_levels, format, builder)
+        .map_err(TerraError::vulkan("create an image"))?;
+
+    ImageView::new_default(image).map_err(TerraError::vulkan("create an image view"))
+}
+
+pub fn create_command_builder(
+    command_buffer_alloc: &Arc<StandardCommandBufferAllocator>,
+    queue: &Arc<Queue>,
+) -> TerraResult<CommandBuilder> {
+    AutoCommandBufferBuilder::primary(
         command_buffer_alloc,
         queue.queue_family_index(),
         CommandBufferUsage::OneTimeSubmit,
     )
-    .unwrap();
+    .map_err(TerraError::vulkan("allocate a command buffer"))
+}
+
+// The returned fence signals once the GPU has run the commands
+pub fn submit(
+    builder: CommandBuilder,
+    queue: &Arc<Queue>,
+) -> TerraResult<FenceSignalFuture<CommandBufferExecFuture<NowFuture>>> {
+    builder
+        .build()
+        .map_err(TerraError::vulkan("build a command buffer"))?
+        .execute(queue.clone())
+        .map_err(TerraError::vulkan("submit a command buffer"))?
+        .then_signal_fence_and_flush()
+        .map_err(flush_error)
+}
+
+pub fn submit_and_wait(builder: CommandBuilder, queue: &Arc<Queue>) -> TerraResult<()> {
+    submit(builder, queue)?.wait(None).map_err(flush_error)
+}
 
-    let image = ImmutableImage::from_iter(
+// Lost devices get their own error since they can't be recovered from without starting over
+pub fn flush_error(error: FlushError) -> TerraError {
+    match error {
+        FlushError::DeviceLost => TerraError::DeviceLost,
+        e => TerraError::vulkan("flush a command buffer")(e),
+    }
+}
+
+// Vulkan format for a texture, None for ASTC block sizes Vulkan doesn't have
+pub fn texture_format(format: TextureFormat, srgb: bool) -> Option<Format> {
+    let (unorm, srgb_format) = match format {
+        TextureFormat::Rgba8 => (Format::R8G8B8A8_UNORM, Format::R8G8B8A8_SRGB),
+        TextureFormat::Bc1 => (Format::BC1_RGBA_UNORM_BLOCK, Format::BC1_RGBA_SRGB_BLOCK),
+        TextureFormat::Bc3 => (Format::BC3_UNORM_BLOCK, Format::BC3_SRGB_BLOCK),
+        TextureFormat::Bc7 => (Format::BC7_UNORM_BLOCK, Format::BC7_SRGB_BLOCK),
+        TextureFormat::Astc(width, height) => match (width, height) {
+            (4, 4) => (Format::ASTC_4x4_UNORM_BLOCK, Format::ASTC_4x4_SRGB_BLOCK),
+            (5, 4) => (Format::ASTC_5x4_UNORM_BLOCK, Format::ASTC_5x4_SRGB_BLOCK),
+            (5, 5) => (Format::ASTC_5x5_UNORM_BLOCK, Format::ASTC_5x5_SRGB_BLOCK),
+            (6, 5) => (Format::ASTC_6x5_UNORM_BLOCK, Format::ASTC_6x5_SRGB_BLOCK),
+            (6, 6) => (Format::ASTC_6x6_UNORM_BLOCK, Format::ASTC_6x6_SRGB_BLOCK),
+            (8, 5) => (Format::ASTC_8x5_UNORM_BLOCK, Format::ASTC_8x5_SRGB_BLOCK),
+            (8, 6) => (Format::ASTC_8x6_UNORM_BLOCK, Format::ASTC_8x6_SRGB_BLOCK),
+            (8, 8) => (Format::ASTC_8x8_UNORM_BLOCK, Format::ASTC_8x8_SRGB_BLOCK),
+            (10, 5) => (Format::ASTC_10x5_UNORM_BLOCK, Format::ASTC_10x5_SRGB_BLOCK),
+            (10, 6) => (Format::ASTC_10x6_UNORM_BLOCK, Format::ASTC_10x6_SRGB_BLOCK),
+            (10, 8) => (Format::ASTC_10x8_UNORM_BLOCK, Format::ASTC_10x8_SRGB_BLOCK),
+            (10, 10) => (
+                Format::ASTC_10x10_UNORM_BLOCK,
+                Format::ASTC_10x10_SRGB_BLOCK,
+            ),
+            (12, 10) => (
+                Format::ASTC_12x10_UNORM_BLOCK,
+                Format::ASTC_12x10_SRGB_BLOCK,
+            ),
+            (12, 12) => (
+                Format::ASTC_12x12_UNORM_BLOCK,
+                Format::ASTC_12x12_SRGB_BLOCK,
+            ),
+            _ => return None,
+        },
+    };
+
+    Some(if srgb { srgb_format } else { unorm })
+}
+
+// Whether images of the format can be created and sampled on the device
+pub fn is_texture_format_supported(device: &Arc<Device>, format: Format) -> bool {
+    let features = device.enabled_features();
+    let enabled = match format.compression() {
+        Some(CompressionType::BC) => features.texture_compression_bc,
+        Some(CompressionType::ASTC_LDR) => features.texture_compression_astc_ldr,
+        Some(_) => false,
+        None => true,
+    };
+
+    enabled
+        && device
+            .physical_device()
+            .format_properties(format)
+            .is_ok_and(|properties| {
+                properties
+                    .optimal_tiling_features
+                    .intersects(FormatFeatures::SAMPLED_IMAGE)
+            })
+}
+
+// Records the upload of a sprite texture along with its mip levels
+pub fn create_texture(
+    allocator: &StandardMemoryAllocator,
+    builder: &mut CommandBuilder,
+    queue: &Arc<Queue>,
+    pixels: Vec<u8>,
+    mipmaps: Mipmaps,
+    dimensions: ImageDimensions,
+    format: Format,
+) -> TerraResult<Arc<ImageView<ImmutableImage>>> {
+    let mip_levels = match mipmaps {
+        Mipmaps::None => MipmapsCount::One,
+        Mipmaps::Generate => MipmapsCount::Log2,
+        Mipmaps::Levels(levels) => {
+            let levels = std::iter::once(pixels).chain(levels).collect();
+            return create_image_from_levels(allocator, builder, queue, levels, dimensions, format);
+        }
+    };
+
+    upload_image(allocator, builder, pixels, dimensions, mip_levels, format)
+}
+
+// Copies every level in one go, the data of each level must be tightly packed
+pub fn create_image_from_levels(
+    allocator: &StandardMemoryAllocator,
+    builder: &mut CommandBuilder,
+    queue: &Arc<Queue>,
+    levels: Vec<Vec<u8>>,
+    dimensions: ImageDimensions,
+    format: Format,
+) -> TerraResult<Arc<ImageView<ImmutableImage>>> {
+    let mip_levels = (levels.len() as u32).min(dimensions.max_mip_levels());
+    let (image, initializer) = ImmutableImage::uninitialized(
         allocator,
-        iter,
         dimensions,
-        vulkano::image::MipmapsCount::One,
         format,
-        &mut builder,
+        MipmapsCount::Specific(mip_levels),
+        ImageUsage::TRANSFER_DST | ImageUsage::SAMPLED,
+        ImageCreateFlags::empty(),
+        ImageLayout::ShaderReadOnlyOptimal,
+        queue.device().active_queue_family_indices().iter().copied(),
     )
-    .expect("Failed to create image for sprite");
+    .map_err(TerraError::vulkan("create an image"))?;
 
-    let image_view =
-        ImageView::new_default(image).expect("Failed to create image view for sprite.");
+    let mut offset = 0;
+    let regions = levels
+        .iter()
+        .take(mip_levels as usize)
+        .enumerate()
+        .map(|(level, data)| {
+            let region = BufferImageCopy {
+                buffer_offset: offset,
+                image_subresource: ImageSubresourceLayers {
+                    mip_level: level as u32,
+                    ..image.subresource_layers()
+                },
+                image_extent: dimensions
+                    .mip_level_dimensions(level as u32)
+                    .unwrap()
+                    .width_height_depth(),
+                ..Default::default()
+            };
+            offset += data.len() as u64;
+            region
+        })
+        .collect();
+
+    let data: Vec<u8> = levels
+        .into_iter()
+        .take(mip_levels as usize)
+        .flatten()
+        .collect();
+    let source = buffer_from_iter(
+        allocator,
+        data,
+        BufferUsage::TRANSFER_SRC,
+        MemoryUsage::Upload,
+    )?;
+
+    builder
+        .copy_buffer_to_image(CopyBufferToImageInfo {
+            regions,
+            ..CopyBufferToImageInfo::buffer_image(source, initializer)
+        })
+        .map_err(TerraError::vulkan("copy mip levels to an image"))?;
 
-    let command_buffer = builder.build().expect("Failed to build command buffer.");
-    let _future = command_buffer
-        .execute(queue.clone())
-        .expect("Failed to submit command buffer.");
+    ImageView::new_default(image).map_err(TerraError::vulkan("create an image view"))
+}
+
+// Written by compute shaders and read back on the GPU, never uploaded from the CPU. Can be
+// cleared with clear_color_image before its first use.
+pub fn create_storage_image(
+    allocator: &StandardMemoryAllocator,
+    queue: &Arc<Queue>,
+    dimensions: ImageDimensions,
+    format: Format,
+) -> TerraResult<Arc<ImageView<StorageImage>>> {
+    let image = StorageImage::with_usage(
+        allocator,
+        dimensions,
+        format,
+        ImageUsage::STORAGE | ImageUsage::TRANSFER_DST,
+        ImageCreateFlags::empty(),
+        [queue.queue_family_index()],
+    )
+    .map_err(TerraError::vulkan("create a storage image"))?;
 
-    image_view
+    ImageView::new_default(image).map_err(TerraError::vulkan("create a storage image view"))
 }
 
 pub fn create_image_descriptor_set(
@@ -415,7 +888,7 @@ pub fn create_image_descriptor_set(
     layout: &Arc<DescriptorSetLayout>,
     image: &Arc<ImageView<ImmutableImage>>,
     sampler: &Arc<Sampler>,
-) -> Arc<PersistentDescriptorSet> {
+) -> TerraResult<Arc<PersistentDescriptorSet>> {
     PersistentDescriptorSet::new(
         allocator,
         layout.clone(),
@@ -425,5 +898,5 @@ pub fn create_image_descriptor_set(
             sampler.clone(),
         )],
     )
-    .expect("Failed to create image descriptor set")
+    .map_err(TerraError::vulkan("create an image descriptor set"))
 }
@@ -0,0 +1,51 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+// Collects files created or modified under a directory so they can be reloaded between frames
+pub struct FileWatcher {
+    _watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<Event>>,
+}
+
+impl FileWatcher {
+    pub fn new(root: &Path) -> notify::Result<FileWatcher> {
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+
+        Ok(FileWatcher {
+            _watcher: watcher,
+            receiver,
+        })
+    }
+
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = vec![];
+
+        while let Ok(event) = self.receiver.try_recv() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    eprintln!("File watcher error: {e}");
+                    continue;
+                }
+            };
+
+            if !event.kind.is_create() && !event.kind.is_modify() {
+                continue;
+            }
+
+            for path in event.paths {
+                if path.is_file() && !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+
+        paths
+    }
+}
@@ -0,0 +1,265 @@
+// TPAK archive layout, shared with the pack binary. Everything is little-endian:
+//
+//   header   magic "TPAK", version u32, entry count u32, table of contents offset u64
+//   data     entry contents back to back, each compressed on its own
+//   toc      per entry: path length u16, path, offset u64, stored size u64, size u64,
+//            compression u8
+
+use std::io::{self, Write};
+
+pub const MAGIC: [u8; 4] = *b"TPAK";
+pub const VERSION: u32 = 1;
+pub const HEADER_SIZE: usize = 20;
+
+// Largest decompressed entry, so a corrupt size can't make the reader allocate everything
+pub const MAX_ENTRY_SIZE: u64 = 1 << 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Lz4,
+}
+
+impl Compression {
+    fn from_byte(byte: u8) -> Option<Compression> {
+        match byte {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Lz4),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TocEntry {
+    pub path: String,
+    pub offset: u64,
+    pub stored_size: u64,
+    pub size: u64,
+    pub compression: Compression,
+}
+
+// Entries are compressed with LZ4 unless that doesn't make them smaller, which is common for
+// images that are already compressed
+pub fn write(writer: &mut impl Write, files: &[(String, Vec<u8>)]) -> io::Result<Vec<TocEntry>> {
+    let mut toc = vec![];
+    let mut data = vec![];
+
+    for (path, contents) in files {
+        let compressed = lz4_flex::compress(contents);
+        let (stored, compression) = match compressed.len() < contents.len() {
+            true => (compressed.as_slice(), Compression::Lz4),
+            false => (contents.as_slice(), Compression::None),
+        };
+
+        toc.push(TocEntry {
+            path: path.clone(),
+            offset: (HEADER_SIZE + data.len()) as u64,
+            stored_size: stored.len() as u64,
+            size: contents.len() as u64,
+            compression,
+        });
+        data.extend_from_slice(stored);
+    }
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&(toc.len() as u32).to_le_bytes())?;
+    writer.write_all(&((HEADER_SIZE + data.len()) as u64).to_le_bytes())?;
+    writer.write_all(&data)?;
+
+    for entry in toc.iter() {
+        let path = entry.path.as_bytes();
+        let path_len = u16::try_from(path.len()).map_err(|_| invalid("path is too long"))?;
+
+        writer.write_all(&path_len.to_le_bytes())?;
+        writer.write_all(path)?;
+        writer.write_all(&entry.offset.to_le_bytes())?;
+        writer.write_all(&entry.stored_size.to_le_bytes())?;
+        writer.write_all(&entry.size.to_le_bytes())?;
+        writer.write_all(&[entry.compression.to_byte()])?;
+    }
+
+    Ok(toc)
+}
+
+// Parses the header and table of contents, checking every entry lies within the archive
+pub fn read_toc(bytes: &[u8]) -> io::Result<Vec<TocEntry>> {
+    let mut cursor = Cursor { bytes, position: 0 };
+
+    if cursor.take(4)? != MAGIC {
+        return Err(invalid("not a TPAK archive"));
+    }
+
+    let version = cursor.u32()?;
+    if version != VERSION {
+        return Err(invalid(&format!("unsupported TPAK version {version}")));
+    }
+
+    let count = cursor.u32()?;
+    cursor.position =
+        usize::try_from(cursor.u64()?).map_err(|_| invalid("unexpected end of archive"))?;
+
+    let mut toc = vec![];
+    for _ in 0..count {
+        let path_len = cursor.u16()? as usize;
+        let path = String::from_utf8(cursor.take(path_len)?.to_vec())
+            .map_err(|_| invalid("entry path isn't UTF-8"))?;
+
+        let entry = TocEntry {
+            path,
+            offset: cursor.u64()?,
+            stored_size: cursor.u64()?,
+            size: cursor.u64()?,
+            compression: Compression::from_byte(cursor.take(1)?[0])
+                .ok_or_else(|| invalid("unknown compression"))?,
+        };
+
+        check_entry(bytes, &entry)?;
+        toc.push(entry);
+    }
+
+    Ok(toc)
+}
+
+// Decompressed contents of an entry. Entries are checked again since they don't have to come
+// from read_toc.
+pub fn read_entry(bytes: &[u8], entry: &TocEntry) -> io::Result<Vec<u8>> {
+    let stored = check_entry(bytes, entry)?;
+
+    match entry.compression {
+        Compression::None => Ok(stored.to_vec()),
+        Compression::Lz4 => lz4_flex::decompress(stored, entry.size as usize)
+            .map_err(|e| invalid(&format!("{}: {e}", entry.path))),
+    }
+}
+
+// The entry's stored bytes, if they lie within the archive and its sizes are sane
+fn check_entry<'a>(bytes: &'a [u8], entry: &TocEntry) -> io::Result<&'a [u8]> {
+    if entry.size > MAX_ENTRY_SIZE {
+        return Err(invalid(&format!("{} is too large", entry.path)));
+    }
+    if entry.compression == Compression::None && entry.stored_size != entry.size {
+        return Err(invalid(&format!("{} has the wrong size", entry.path)));
+    }
+
+    let start = usize::try_from(entry.offset).ok();
+    let len = usize::try_from(entry.stored_size).ok();
+    start
+        .zip(len)
+        .and_then(|(start, len)| bytes.get(start..start.checked_add(len)?))
+        .ok_or_else(|| invalid(&format!("{} lies outside the archive", entry.path)))
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .position
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.position..end))
+            .ok_or_else(|| invalid("unexpected end of archive"))?;
+
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn archive() -> (Vec<u8>, Vec<(String, Vec<u8>)>) {
+        let files = vec![
+            ("hero.png".to_owned(), vec![7; 1000]),
+            ("enemies/bat.png".to_owned(), (0..=255).collect()),
+            ("empty".to_owned(), vec![]),
+        ];
+        let mut bytes = vec![];
+        write(&mut bytes, &files).unwrap();
+
+        (bytes, files)
+    }
+
+    #[test]
+    fn round_trip() {
+        let (bytes, files) = archive();
+        let toc = read_toc(&bytes).unwrap();
+
+        assert_eq!(toc.len(), files.len());
+        // Repeated bytes shrink, random-looking ones are stored as they are
+        assert_eq!(toc[0].compression, Compression::Lz4);
+        assert_eq!(toc[1].compression, Compression::None);
+
+        for (entry, (path, contents)) in toc.iter().zip(files.iter()) {
+            assert_eq!(&entry.path, path);
+            assert_eq!(&read_entry(&bytes, entry).unwrap(), contents);
+        }
+    }
+
+    #[test]
+    fn truncated_archives_are_rejected() {
+        let (bytes, _) = archive();
+
+        for len in [0, 3, HEADER_SIZE - 1, HEADER_SIZE + 10, bytes.len() - 1] {
+            let error = read_toc(&bytes[..len]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{len}");
+        }
+
+        // Entries read against a shorter archive than their table of contents came from
+        let toc = read_toc(&bytes).unwrap();
+        let error = read_entry(&bytes[..HEADER_SIZE + 10], &toc[1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn bad_magic_is_rejected() {
+        let (mut bytes, _) = archive();
+        bytes[..4].copy_from_slice(b"PAKT");
+
+        let error = read_toc(&bytes).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn oversized_entries_are_rejected() {
+        let (bytes, _) = archive();
+        let mut toc = read_toc(&bytes).unwrap();
+
+        toc[0].size = MAX_ENTRY_SIZE + 1;
+        toc[1].offset = u64::MAX;
+        toc[2].stored_size = 1;
+
+        for entry in toc.iter() {
+            let error = read_entry(&bytes, entry).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", entry.path);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+pub mod format;
+
+use std::{collections::HashMap, fs::File, io, path::Path};
+
+use memmap2::Mmap;
+
+use self::format::TocEntry;
+use super::Mount;
+
+// TPAK archive built by the pack binary. The file is memory-mapped, so entries are only read
+// from disk when they're loaded.
+pub struct Archive {
+    bytes: Mmap,
+    entries: HashMap<String, TocEntry>,
+}
+
+impl Archive {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Archive> {
+        let file = File::open(path)?;
+
+        // The archive must not be modified while it's mounted
+        let bytes = unsafe { Mmap::map(&file)? };
+        let entries = format::read_toc(&bytes)?
+            .into_iter()
+            .map(|entry| (entry.path.clone(), entry))
+            .collect();
+
+        Ok(Archive { bytes, entries })
+    }
+}
+
+impl Mount for Archive {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self.entries.get(path) {
+            Some(entry) => format::read_entry(&self.bytes, entry),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{path} not found"),
+            )),
+        }
+    }
+
+    fn files(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+}
@@ -0,0 +1,333 @@
+pub mod archive;
+
+pub use archive::Archive;
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+// Mounted sources by scheme, so `sprites://hero.png` is looked up in the "sprites" mounts.
+// Shared with the asset loader threads.
+static MOUNTS: RwLock<Vec<(String, Arc<dyn Mount>)>> = RwLock::new(Vec::new());
+
+// A source of files that can be mounted under a scheme. Paths are relative to the mount and
+// always use forward slashes.
+pub trait Mount: Send + Sync {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+
+    // Every file in the mount
+    fn files(&self) -> Vec<String>;
+
+    // Only directory mounts can be written to
+    fn write(&self, path: &str, _contents: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{path} is in a read-only mount"),
+        ))
+    }
+
+    // Where the file lives on disk, None for mounts that aren't backed by a directory
+    fn local_path(&self, _path: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+pub struct Directory {
+    root: PathBuf,
+}
+
+impl Directory {
+    pub fn new(root: impl Into<PathBuf>) -> Directory {
+        Directory { root: root.into() }
+    }
+}
+
+impl Mount for Directory {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.root.join(path))
+    }
+
+    fn files(&self) -> Vec<String> {
+        let mut files = vec![];
+        if let Err(e) = visit_dirs(&self.root, &self.root, &mut files) {
+            eprintln!("Failed to read {}: {e}", self.root.display());
+        }
+
+        files
+    }
+
+    fn write(&self, path: &str, contents: &[u8]) -> io::Result<()> {
+        fs::write(self.root.join(path), contents)
+    }
+
+    fn local_path(&self, path: &str) -> Option<PathBuf> {
+        Some(self.root.join(path))
+    }
+}
+
+// Files compiled into the binary with include_bytes!
+pub struct Embedded {
+    files: HashMap<String, &'static [u8]>,
+}
+
+impl Embedded {
+    pub fn new(files: impl IntoIterator<Item = (&'static str, &'static [u8])>) -> Embedded {
+        let files = files
+            .into_iter()
+            .filter_map(|(path, data)| match normalize(path) {
+                Ok(path) => Some((path.to_owned(), data)),
+                Err(e) => {
+                    eprintln!("Failed to embed {path}: {e}");
+                    None
+                }
+            })
+            .collect();
+
+        Embedded { files }
+    }
+}
+
+impl Mount for Embedded {
+    fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        self.files
+            .get(path)
+            .map(|data| data.to_vec())
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn files(&self) -> Vec<String> {
+        self.files.keys().cloned().collect()
+    }
+}
+
+// Mounts added later take precedence over earlier ones with the same scheme
+pub fn mount(scheme: &str, mount: impl Mount + 'static) {
+    MOUNTS
+        .write()
+        .unwrap()
+        .push((scheme.to_owned(), Arc::new(mount)));
+}
+
+pub fn unmount(scheme: &str) {
+    MOUNTS.write().unwrap().retain(|(s, _)| s != scheme);
+}
+
+pub fn read(path: &str) -> io::Result<Vec<u8>> {
+    let (scheme, path) = split(path)?;
+
+    for mount in mounts(scheme).iter().rev() {
+        match mount.read(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            result => return result,
+        }
+    }
+
+    Err(not_found(&format!("{scheme}://{path}")))
+}
+
+// Writes to the most recently added mount that accepts writes
+pub fn write(path: &str, contents: &[u8]) -> io::Result<()> {
+    let (scheme, path) = split(path)?;
+
+    for mount in mounts(scheme).iter().rev() {
+        match mount.write(path, contents) {
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => continue,
+            result => return result,
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Nothing writable is mounted at {scheme}://"),
+    ))
+}
+
+// Logical paths of every file under a logical directory such as `sprites://` or
+// `sprites://enemies`, sorted and without duplicates
+pub fn list(dir: &str) -> io::Result<Vec<String>> {
+    let (scheme, dir) = split(dir)?;
+    let prefix = match dir {
+        "" => String::new(),
+        dir => format!("{}/", dir.trim_end_matches('/')),
+    };
+
+    let mut files: Vec<String> = mounts(scheme)
+        .iter()
+        .flat_map(|mount| mount.files())
+        .filter(|path| path.starts_with(&prefix))
+        .map(|path| format!("{scheme}://{path}"))
+        .collect();
+    files.sort();
+    files.dedup();
+
+    Ok(files)
+}
+
+// File on disk a logical path resolves to, used to watch files for hot reloading
+pub fn local_path(path: &str) -> Option<PathBuf> {
+    let (scheme, path) = split(path).ok()?;
+
+    mounts(scheme)
+        .iter()
+        .rev()
+        .filter_map(|mount| mount.local_path(path))
+        .find(|local| local.exists())
+}
+
+// Directory mounts by scheme, used to watch them for changes
+pub fn directories() -> Vec<(String, PathBuf)> {
+    MOUNTS
+        .read()
+        .unwrap()
+        .iter()
+        .filter_map(|(scheme, mount)| Some((scheme.clone(), mount.local_path("")?)))
+        .collect()
+}
+
+fn mounts(scheme: &str) -> Vec<Arc<dyn Mount>> {
+    MOUNTS
+        .read()
+        .unwrap()
+        .iter()
+        .filter(|(s, _)| s == scheme)
+        .map(|(_, mount)| mount.clone())
+        .collect()
+}
+
+fn split(path: &str) -> io::Result<(&str, &str)> {
+    let (scheme, path) = path.split_once("://").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{path} is missing a scheme such as sprites://"),
+        )
+    })?;
+
+    Ok((scheme, normalize(path)?))
+}
+
+// Paths can't climb out of their mount, so `..` and anything that would replace the mount's
+// root when joined, like drive letters on Windows, are rejected
+fn normalize(path: &str) -> io::Result<&str> {
+    let path = path.trim_start_matches('/');
+    let escapes = path.split(['/', '\\']).any(|part| part == "..")
+        || Path::new(path)
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_) | Component::CurDir));
+
+    if escapes {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{path} leaves its mount"),
+        ));
+    }
+
+    Ok(path)
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{path} not found"))
+}
+
+fn visit_dirs(root: &Path, dir: &Path, files: &mut Vec<String>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            visit_dirs(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_string_lossy().replace('\\', "/"));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_separates_the_scheme() {
+        assert_eq!(
+            split("sprites://hero.png").unwrap(),
+            ("sprites", "hero.png")
+        );
+        assert_eq!(split("sprites:///a/b.png").unwrap(), ("sprites", "a/b.png"));
+        assert_eq!(split("sprites://").unwrap(), ("sprites", ""));
+
+        let error = split("hero.png").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn normalize_rejects_paths_leaving_the_mount() {
+        assert_eq!(normalize("//a/./b.png").unwrap(), "a/./b.png");
+        assert_eq!(normalize("a..b/c").unwrap(), "a..b/c");
+
+        for path in ["..", "../secret", "a/../../secret", "a/..", "a\\..\\b"] {
+            let error = normalize(path).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "{path}");
+        }
+    }
+
+    #[test]
+    fn directory_reads_stay_in_the_mount() {
+        let root = std::env::temp_dir().join(format!("vfs-test-{}", std::process::id()));
+        fs::create_dir_all(root.join("inner")).unwrap();
+        fs::write(root.join("secret.txt"), b"secret").unwrap();
+        mount("test-escape", Directory::new(root.join("inner")));
+
+        let read_error = read("test-escape://../secret.txt").unwrap_err();
+        let write_error = write("test-escape://../secret.txt", b"changed").unwrap_err();
+
+        unmount("test-escape");
+        assert_eq!(fs::read(root.join("secret.txt")).unwrap(), b"secret");
+        fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(read_error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(write_error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn list_merges_mounts_under_a_directory() {
+        mount(
+            "test-list",
+            Embedded::new([
+                ("/enemies/bat.png", &b"bat"[..]),
+                ("enemies/rat.png", &b"rat"[..]),
+                ("hero.png", &b"hero"[..]),
+            ]),
+        );
+        mount(
+            "test-list",
+            Embedded::new([
+                ("enemies/bat.png", &b"new bat"[..]),
+                ("../escaped.png", &b""[..]),
+            ]),
+        );
+
+        let all = list("test-list://").unwrap();
+        let enemies = list("test-list://enemies/").unwrap();
+        let bat = read("test-list://enemies/bat.png").unwrap();
+        unmount("test-list");
+
+        assert_eq!(
+            all,
+            [
+                "test-list://enemies/bat.png",
+                "test-list://enemies/rat.png",
+                "test-list://hero.png",
+            ]
+        );
+        assert_eq!(
+            enemies,
+            ["test-list://enemies/bat.png", "test-list://enemies/rat.png"]
+        );
+        // Later mounts take precedence
+        assert_eq!(bat, b"new bat");
+        assert!(list("test-list://").unwrap().is_empty());
+    }
+}